@@ -1,9 +1,15 @@
-use clap::{App, Arg};
-use easy_fs::{BlockDevice, EasyFileSystem};
-use std::fs::{read_dir, File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use easy_fs::{fsck, BlockDevice, DiskInodeType, EasyFileSystem, Inode};
+use fuser::MountOption;
+use std::fs::{create_dir_all, read_dir, File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod mount;
 
 const BLOCK_SIZE: usize = 512;
 
@@ -29,11 +35,16 @@ impl BlockDevice for BlockFile {
     }
 }
 
-fn main() {
-    easy_fs_pack().expect("Error when packing easy-fs!");
+/// 主机的当前时间，自1970年以来的秒数
+fn host_clock() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
 }
 
-fn easy_fs_pack() -> std::io::Result<()> {
+fn main() {
+    easy_fs::set_clock(host_clock);
     let matches = App::new("EasyFileSystem packer")
         .arg(
             Arg::with_name("source")
@@ -49,7 +60,289 @@ fn easy_fs_pack() -> std::io::Result<()> {
                 .takes_value(true)
                 .help("Executable target dir(with backslash)"),
         )
+        .arg(
+            Arg::with_name("dir")
+                .short("d")
+                .long("dir")
+                .takes_value(true)
+                .help("Host directory tree packed into the root directory"),
+        )
+        .subcommand(
+            SubCommand::with_name("fsck")
+                .about("Check an easy-fs image")
+                .arg(image_arg())
+                .arg(
+                    Arg::with_name("repair")
+                        .short("r")
+                        .long("repair")
+                        .help("Repair the problems found"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("ls")
+                .about("List files in an easy-fs image with their sizes")
+                .arg(image_arg())
+                .arg(Arg::with_name("path").help("Directory to list (default: /)"))
+                .arg(
+                    Arg::with_name("recursive")
+                        .short("r")
+                        .long("recursive")
+                        .help("List subdirectories recursively"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("extract")
+                .about("Copy a file or directory tree out of an easy-fs image")
+                .arg(image_arg())
+                .arg(
+                    Arg::with_name("path")
+                        .required(true)
+                        .help("File or directory in the image, / for all files"),
+                )
+                .arg(
+                    Arg::with_name("dest")
+                        .required(true)
+                        .help("Destination path on the host"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("Add or replace a file in an easy-fs image")
+                .arg(image_arg())
+                .arg(
+                    Arg::with_name("source")
+                        .required(true)
+                        .help("File on the host"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .required(true)
+                        .help("Path in the image, missing directories are created"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("mount")
+                .about("Mount an easy-fs image through FUSE until it is unmounted")
+                .arg(image_arg())
+                .arg(
+                    Arg::with_name("mountpoint")
+                        .required(true)
+                        .help("Directory to mount the image on"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Show super block and bitmap usage of an easy-fs image")
+                .arg(image_arg()),
+        )
         .get_matches();
+    let result = match matches.subcommand() {
+        ("fsck", Some(matches)) => {
+            let code = easy_fs_fsck(
+                matches.value_of("image").unwrap(),
+                matches.is_present("repair"),
+            )
+            .expect("Error when checking easy-fs!");
+            std::process::exit(code);
+        }
+        ("ls", Some(matches)) => easy_fs_ls(
+            matches.value_of("image").unwrap(),
+            matches.value_of("path").unwrap_or("/"),
+            matches.is_present("recursive"),
+        ),
+        ("extract", Some(matches)) => easy_fs_extract(
+            matches.value_of("image").unwrap(),
+            matches.value_of("path").unwrap(),
+            Path::new(matches.value_of("dest").unwrap()),
+        ),
+        ("add", Some(matches)) => easy_fs_add(
+            matches.value_of("image").unwrap(),
+            Path::new(matches.value_of("source").unwrap()),
+            matches.value_of("path").unwrap(),
+        ),
+        ("mount", Some(matches)) => easy_fs_mount(
+            matches.value_of("image").unwrap(),
+            Path::new(matches.value_of("mountpoint").unwrap()),
+        ),
+        ("stats", Some(matches)) => easy_fs_stats(matches.value_of("image").unwrap()),
+        _ => {
+            easy_fs_pack(&matches).expect("Error when packing easy-fs!");
+            Ok(())
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+/// 子命令的镜像文件参数
+fn image_arg() -> Arg<'static, 'static> {
+    Arg::with_name("image")
+        .required(true)
+        .help("Path of the image file")
+}
+
+/// 打开镜像文件作为块设备
+fn open_image(image: &str) -> std::io::Result<Arc<dyn BlockDevice>> {
+    // 打开文件系统时可能重放日志，因此总是以读写方式打开
+    let file = OpenOptions::new().read(true).write(true).open(image)?;
+    Ok(Arc::new(BlockFile(Mutex::new(file))))
+}
+
+/// 按路径查找镜像中的文件或目录
+fn lookup(root: &Inode, path: &str) -> std::io::Result<Arc<Inode>> {
+    root.lookup(path).ok_or_else(|| {
+        std::io::Error::new(ErrorKind::NotFound, format!("{}: not found in image", path))
+    })
+}
+
+/// 列出目录path下的文件及其大小，recursive为true时递归列出子目录
+fn easy_fs_ls(image: &str, path: &str, recursive: bool) -> std::io::Result<()> {
+    let efs = EasyFileSystem::open(open_image(image)?);
+    let root = EasyFileSystem::root_inode(&efs);
+    let dir = lookup(&root, path)?;
+    if !dir.is_dir() {
+        println!("{:>10} {}", dir.size(), path);
+        return Ok(());
+    }
+    list_dir(&dir, path.trim_end_matches('/'), recursive);
+    Ok(())
+}
+
+/// 输出目录中的每一项，prefix为目录在镜像中的路径
+fn list_dir(dir: &Inode, prefix: &str, recursive: bool) {
+    let (entries, _) = dir.read_dir(0, usize::MAX).unwrap();
+    for entry in entries {
+        if entry.name == "." || entry.name == ".." {
+            continue;
+        }
+        let path = format!("{}/{}", prefix, entry.name);
+        let inode = dir.find(&entry.name).unwrap();
+        let metadata = inode.metadata();
+        if metadata.type_ == DiskInodeType::Directory {
+            println!("{:>10} {}/", metadata.size, path);
+            if recursive {
+                list_dir(&inode, &path, recursive);
+            }
+        } else if metadata.type_ == DiskInodeType::Fifo {
+            println!("{:>10} {}|", metadata.size, path);
+        } else {
+            println!("{:>10} {}", metadata.size, path);
+        }
+    }
+}
+
+/// 将镜像中的文件或目录树path复制到宿主机的dest
+fn easy_fs_extract(image: &str, path: &str, dest: &Path) -> std::io::Result<()> {
+    let efs = EasyFileSystem::open(open_image(image)?);
+    let root = EasyFileSystem::root_inode(&efs);
+    let inode = lookup(&root, path)?;
+    extract(&inode, dest)
+}
+
+/// 将inode递归复制到宿主机的host_path
+fn extract(inode: &Inode, host_path: &Path) -> std::io::Result<()> {
+    if !inode.is_dir() {
+        let mut data = vec![0u8; inode.size()];
+        inode.read_at(0, &mut data);
+        return std::fs::write(host_path, data);
+    }
+    create_dir_all(host_path)?;
+    let (entries, _) = inode.read_dir(0, usize::MAX).unwrap();
+    for entry in entries {
+        if entry.name != "." && entry.name != ".." {
+            extract(
+                &inode.find(&entry.name).unwrap(),
+                &host_path.join(&entry.name),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// 将宿主机文件source写入镜像中的path，已存在的文件被替换
+fn easy_fs_add(image: &str, source: &Path, path: &str) -> std::io::Result<()> {
+    let data = std::fs::read(source)?;
+    let efs = EasyFileSystem::open(open_image(image)?);
+    let root = EasyFileSystem::root_inode(&efs);
+    add_file(&root, path, &data)?;
+    // 块缓存是写回的，退出前写回所有脏块
+    root.sync();
+    Ok(())
+}
+
+/// 在镜像中创建或替换文件path，内容为data，缺少的目录被创建
+fn add_file(root: &Inode, path: &str, data: &[u8]) -> std::io::Result<Arc<Inode>> {
+    let invalid =
+        |msg: &str| std::io::Error::new(ErrorKind::InvalidInput, format!("{}: {}", path, msg));
+    let (dir_path, name) = path.rsplit_once('/').unwrap_or(("", path));
+    let mut dir = lookup(root, "/")?;
+    for dir_name in dir_path.split('/').filter(|name| !name.is_empty()) {
+        dir = match dir.find(dir_name) {
+            Some(sub_dir) => sub_dir,
+            None => dir
+                .mkdir(dir_name)
+                .ok_or_else(|| invalid("cannot create directory"))?,
+        };
+        if !dir.is_dir() {
+            return Err(invalid("not a directory"));
+        }
+    }
+    let file = match dir.find(name) {
+        Some(file) if file.is_dir() => return Err(invalid("is a directory")),
+        Some(file) => {
+            file.truncate(0);
+            file
+        }
+        None => dir
+            .create(name)
+            .ok_or_else(|| invalid("invalid file name"))?,
+    };
+    if file.write_at(0, data) < data.len() {
+        return Err(invalid("file too large"));
+    }
+    Ok(file)
+}
+
+/// 将镜像挂载到mountpoint，阻塞直到被卸载
+///
+/// 文件在关闭时写回，被强制结束时重放日志后镜像仍然一致
+fn easy_fs_mount(image: &str, mountpoint: &Path) -> std::io::Result<()> {
+    let owner = std::fs::metadata(image)?;
+    let efs = EasyFileSystem::open(open_image(image)?);
+    let fs = mount::EfsFuse::new(efs, owner.uid(), owner.gid());
+    let options = [
+        MountOption::FSName(image.to_string()),
+        MountOption::Subtype("easy-fs".to_string()),
+    ];
+    fuser::mount2(fs, mountpoint, &options)
+}
+
+/// 输出超级块中的各区域大小和位图的使用情况
+fn easy_fs_stats(image: &str) -> std::io::Result<()> {
+    let efs = EasyFileSystem::open(open_image(image)?);
+    let stats = efs.lock().stats();
+    println!("total blocks:        {}", stats.total_blocks);
+    println!("journal blocks:      {}", stats.journal_blocks);
+    println!("inode bitmap blocks: {}", stats.inode_bitmap_blocks);
+    println!("inode area blocks:   {}", stats.inode_area_blocks);
+    println!("data bitmap blocks:  {}", stats.data_bitmap_blocks);
+    println!("data area blocks:    {}", stats.data_area_blocks);
+    println!(
+        "inodes:              {} / {} used",
+        stats.used_inodes, stats.total_inodes
+    );
+    println!(
+        "data blocks:         {} / {} used ({} KiB free)",
+        stats.used_data_blocks,
+        stats.data_area_blocks,
+        (stats.data_area_blocks - stats.used_data_blocks) as usize * BLOCK_SIZE / 1024
+    );
+    Ok(())
+}
+
+fn easy_fs_pack(matches: &ArgMatches) -> std::io::Result<()> {
     let src_path = matches.value_of("source").unwrap();
     let target_path = matches.value_of("target").unwrap();
     println!("src_path = {}\ntarget_path = {}", src_path, target_path);
@@ -84,10 +377,73 @@ fn easy_fs_pack() -> std::io::Result<()> {
         // write data to easy-fs
         inode.write_at(0, all_data.as_slice());
     }
+    // pack the directory tree
+    if let Some(dir_path) = matches.value_of("dir") {
+        pack_dir(Path::new(dir_path), &root_inode)?;
+    }
     // list apps
     for app in root_inode.ls() {
         println!("{}", app);
     }
+    // 块缓存是写回的，退出前写回所有脏块
+    root_inode.sync();
+    Ok(())
+}
+
+/// 检查镜像文件中的文件系统，repair为true时修复发现的问题
+///
+/// 返回退出码：0表示没有问题，1表示问题已修复，4表示还有问题，8表示无法检查
+fn easy_fs_fsck(image: &str, repair: bool) -> std::io::Result<i32> {
+    let file = OpenOptions::new().read(true).write(repair).open(image)?;
+    let device_blocks = file.metadata()?.len() as usize / BLOCK_SIZE;
+    let block_file: Arc<dyn BlockDevice> = Arc::new(BlockFile(Mutex::new(file)));
+    let problems = match fsck(Arc::clone(&block_file), device_blocks, repair) {
+        Ok(problems) => problems,
+        Err(problem) => {
+            println!("{}", problem);
+            return Ok(8);
+        }
+    };
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("{}: clean", image);
+        return Ok(0);
+    }
+    if !repair {
+        println!("{}: {} problems found", image, problems.len());
+        return Ok(4);
+    }
+    // 修复后重新检查
+    let remaining = fsck(block_file, device_blocks, false).map_or(1, |problems| problems.len());
+    if remaining > 0 {
+        println!("{}: {} problems remain after repair", image, remaining);
+        return Ok(4);
+    }
+    println!("{}: {} problems repaired", image, problems.len());
+    Ok(1)
+}
+
+/// 将宿主机目录host_path下的文件和子目录递归写入easy-fs目录dir中
+fn pack_dir(host_path: &Path, dir: &Arc<Inode>) -> std::io::Result<()> {
+    for dir_entry in read_dir(host_path)? {
+        let dir_entry = dir_entry?;
+        let name = dir_entry.file_name().into_string().unwrap();
+        if dir_entry.file_type()?.is_dir() {
+            let sub_dir = dir
+                .mkdir(&name)
+                .unwrap_or_else(|| panic!("Error when creating directory {}!", name));
+            pack_dir(&dir_entry.path(), &sub_dir)?;
+        } else {
+            let mut all_data: Vec<u8> = Vec::new();
+            File::open(dir_entry.path())?.read_to_end(&mut all_data)?;
+            let inode = dir
+                .create(&name)
+                .unwrap_or_else(|| panic!("Error when creating file {}!", name));
+            inode.write_at(0, all_data.as_slice());
+        }
+    }
     Ok(())
 }
 
@@ -102,9 +458,13 @@ fn efs_test() -> std::io::Result<()> {
         f.set_len(8192 * 512).unwrap();
         f
     })));
+    easy_fs::set_clock(host_clock);
+    let start = host_clock() as u32;
     EasyFileSystem::create(block_file.clone(), 4096, 1);
     let efs = EasyFileSystem::open(block_file.clone());
     let root_inode = EasyFileSystem::root_inode(&efs);
+    assert_eq!(root_inode.ls(), [".", ".."]);
+    assert!(root_inode.metadata().ctime >= start);
     root_inode.create("filea");
     root_inode.create("fileb");
     for name in root_inode.ls() {
@@ -167,5 +527,242 @@ fn efs_test() -> std::io::Result<()> {
     random_str_test(2000 * BLOCK_SIZE);
     println!("random_str_test8 passed!");
 
+    let bin = root_inode.mkdir("bin").unwrap();
+    assert!(bin.is_dir());
+    assert!(root_inode.mkdir("bin").is_none());
+    let shell = bin.create("shell").unwrap();
+    assert!(shell.is_file());
+    shell.write_at(0, "shell".as_bytes());
+    let lib = bin.mkdir("lib").unwrap();
+
+    // 绝对路径和相对路径
+    let found = root_inode.lookup("/bin/shell").unwrap();
+    assert_eq!(found.inode_id(), shell.inode_id());
+    let found = lib.lookup("../shell").unwrap();
+    assert_eq!(found.inode_id(), shell.inode_id());
+    let found = lib.lookup("./../../bin/lib/.").unwrap();
+    assert_eq!(found.inode_id(), lib.inode_id());
+    assert_eq!(root_inode.lookup("/..").unwrap().inode_id(), 0);
+    assert!(root_inode.lookup("/bin/shell/x").is_none());
+    assert!(root_inode.lookup("/bin/none").is_none());
+    assert!(shell.create("x").is_none());
+
+    // 只能删除空目录
+    assert!(!root_inode.rmdir("bin"));
+    assert!(!bin.rmdir("shell"));
+    assert!(!bin.rmdir("."));
+    assert!(bin.rmdir("lib"));
+    assert!(bin.find("lib").is_none());
+    assert_eq!(bin.ls(), [".", "..", "shell"]);
+
+    // 删除后的目录项和I结点可以被复用
+    let etc = bin.mkdir("etc").unwrap();
+    assert_eq!(etc.inode_id(), lib.inode_id());
+    assert_eq!(bin.ls(), [".", "..", "shell", "etc"]);
+
+    // 目录的链接数包括父目录中的目录项、自身的`.`和子目录的`..`
+    assert_eq!(bin.nlink(), 3);
+    assert_eq!(shell.nlink(), 1);
+
+    // 重命名和移动
+    assert!(bin.rename("shell", &etc, "sh"));
+    assert!(bin.find("shell").is_none());
+    assert_eq!(
+        root_inode.lookup("/bin/etc/sh").unwrap().inode_id(),
+        shell.inode_id()
+    );
+    assert!(root_inode.rename("bin", &root_inode, "usr"));
+    assert!(!root_inode.rename("usr", &etc, "usr"));
+    assert!(etc.rename("sh", &root_inode, "sh"));
+    assert!(root_inode.rename("usr", &root_inode, "bin"));
+    assert_eq!(etc.lookup("../..").unwrap().inode_id(), 0);
+    assert!(bin.rename("etc", &root_inode, "etc"));
+    assert_eq!(etc.lookup("..").unwrap().inode_id(), 0);
+    assert_eq!(bin.nlink(), 2);
+    // 文件替换文件，目录不能替换文件
+    let tmp = root_inode.create("tmp").unwrap();
+    tmp.write_at(0, &[1u8; 4 * BLOCK_SIZE]);
+    assert!(!root_inode.rename("etc", &root_inode, "tmp"));
+    assert!(root_inode.rename("tmp", &root_inode, "sh"));
+    assert_eq!(root_inode.find("sh").unwrap().inode_id(), tmp.inode_id());
+
+    // 打开的文件在被删除后仍可访问，最后一次关闭时回收
+    tmp.open();
+    assert!(root_inode.unlink("sh"));
+    assert!(!root_inode.unlink("sh"));
+    assert!(!root_inode.unlink("etc"));
+    let mut buffer = [0u8; BLOCK_SIZE];
+    assert_eq!(tmp.read_at(0, &mut buffer), BLOCK_SIZE);
+    assert_eq!(
+        root_inode.create("new").unwrap().inode_id(),
+        shell.inode_id()
+    );
+    assert_ne!(
+        root_inode.create("other").unwrap().inode_id(),
+        tmp.inode_id()
+    );
+    tmp.close();
+    let last = root_inode.create("last").unwrap();
+    assert_eq!(last.inode_id(), tmp.inode_id());
+
+    // 元数据
+    last.write_at(0, &[1u8; 30 * BLOCK_SIZE]);
+    let metadata = last.metadata();
+    assert_eq!(metadata.inode_id, last.inode_id());
+    assert_eq!(metadata.type_, DiskInodeType::File);
+    assert_eq!(metadata.size as usize, 30 * BLOCK_SIZE);
+    assert_eq!(metadata.nlink, 1);
+    // 23个直接块，7个数据块和1个索引块在一级间接块中
+    assert_eq!(metadata.blocks, 31);
+    assert!(metadata.ctime >= start && metadata.mtime >= metadata.ctime);
+    assert_eq!(bin.metadata().type_, DiskInodeType::Directory);
+
+    // 分批读取目录项
+    let (entries, next) = bin.read_dir(0, 2).unwrap();
+    assert_eq!(entries[1].name, "..");
+    assert_eq!(entries[1].inode_id, 0);
+    assert_eq!(entries[1].type_, DiskInodeType::Directory);
+    let (entries, next) = bin.read_dir(next, 2).unwrap();
+    assert!(entries.is_empty());
+    assert!(bin.read_dir(next, 2).unwrap().0.is_empty());
+    let names: Vec<_> = root_inode
+        .read_dir(0, 16)
+        .unwrap()
+        .0
+        .into_iter()
+        .map(|e| e.name)
+        .collect();
+    assert_eq!(names, root_inode.ls());
+    assert!(last.read_dir(0, 16).is_none());
+
+    // 截断和扩展
+    let data: Vec<u8> = (0..(23 + 128 + 300) * BLOCK_SIZE + 100)
+        .map(|_| rand::random::<u8>())
+        .collect();
+    let check = |inode: &Inode, size: usize, valid: usize| {
+        let mut read_buffer = vec![0u8; size + 1];
+        assert_eq!(inode.read_at(0, &mut read_buffer), size);
+        assert_eq!(&read_buffer[..valid], &data[..valid]);
+        assert!(read_buffer[valid..size].iter().all(|b| *b == 0));
+        // 数据块和索引块
+        let data_blocks = (size + BLOCK_SIZE - 1) / BLOCK_SIZE;
+        let mut blocks = data_blocks;
+        if data_blocks > 23 {
+            blocks += 1;
+        }
+        if data_blocks > 23 + 128 {
+            blocks += 1 + (data_blocks - 23 - 128 + 127) / 128;
+        }
+        assert_eq!(inode.metadata().blocks as usize, blocks);
+    };
+    // 截断时回收数据块和索引块
+    let used = || efs.lock().used_data_blocks();
+    last.truncate(0);
+    let used_before = used();
+    for _ in 0..2 {
+        last.write_at(0, &data);
+        for size in [
+            (23 + 128 + 129) * BLOCK_SIZE + 7,
+            (23 + 128 + 1) * BLOCK_SIZE,
+            (23 + 3) * BLOCK_SIZE + 1,
+            23 * BLOCK_SIZE,
+            BLOCK_SIZE - 1,
+        ] {
+            assert!(last.truncate(size as u64));
+            check(&last, size, size);
+            assert_eq!(used(), used_before + last.metadata().blocks as usize);
+        }
+        last.truncate(0);
+        assert_eq!(used(), used_before);
+    }
+    // 扩展的部分填零
+    last.write_at(0, &data);
+    assert!(last.truncate(((23 + 3) * BLOCK_SIZE + 1) as u64));
+    assert!(last.truncate(40 * BLOCK_SIZE as u64));
+    check(&last, 40 * BLOCK_SIZE, (23 + 3) * BLOCK_SIZE + 1);
+    assert!(!bin.truncate(0));
+    assert!(!last.truncate(easy_fs::MAX_FILE_SIZE + 1));
+    // 空闲数据块不足时不扩展，也不分配数据块
+    let used_before = used();
+    assert!(!last.truncate(8192 * BLOCK_SIZE as u64));
+    assert_eq!(last.write_at(8192 * BLOCK_SIZE, &data), 0);
+    assert_eq!(used(), used_before);
+    check(&last, 40 * BLOCK_SIZE, (23 + 3) * BLOCK_SIZE + 1);
+    // 磁盘已满时不能新建目录，也不会泄漏I结点
+    let mut fillers = Vec::new();
+    while efs.lock().free_data_blocks() > 0 {
+        let name = format!("filler{}", fillers.len());
+        let filler = root_inode.create(&name).unwrap();
+        while filler.write_at(filler.size(), &[1u8; BLOCK_SIZE]) > 0 {}
+        fillers.push(name);
+    }
+    let used_inodes = efs.lock().used_inodes();
+    assert!(!root_inode.has_space_for(Some(DiskInodeType::Directory)));
+    assert!(root_inode.mkdir("full").is_none());
+    assert_eq!(efs.lock().used_inodes(), used_inodes);
+    for name in fillers.iter() {
+        assert!(root_inode.unlink(name));
+    }
+    assert!(root_inode.mkdir("full").is_some());
+    assert!(root_inode.rmdir("full"));
+    // 写回缓存：容量较小时脏块在淘汰时写回，之后从磁盘重新读出
+    easy_fs::set_block_cache_size(8);
+    last.write_at(0, &data);
+    let (cached, dirty) = easy_fs::block_cache_stats();
+    assert!(cached <= 8 && dirty > 0);
+    root_inode.sync();
+    assert_eq!(easy_fs::block_cache_stats().1, 0);
+    check(&last, data.len(), data.len());
+
+    // 统计信息
+    let stats = efs.lock().stats();
+    assert_eq!(stats.total_blocks, 4096);
+    assert_eq!(stats.used_inodes as usize, efs.lock().used_inodes());
+    assert!(stats.used_data_blocks <= stats.data_area_blocks);
+    // 添加、替换和取出文件
+    let added = add_file(&root_inode, "/x/y/z", &data)?;
+    assert_eq!(
+        root_inode.lookup("/x/y/z").unwrap().inode_id(),
+        added.inode_id()
+    );
+    add_file(&root_inode, "x/y/z", b"short")?;
+    assert_eq!(added.size(), 5);
+    assert!(add_file(&root_inode, "/x/y", b"").is_err());
+    assert!(add_file(&root_inode, "/x/y/z/w", b"").is_err());
+    extract(
+        &root_inode.lookup("/x").unwrap(),
+        Path::new("target/extract"),
+    )?;
+    assert_eq!(std::fs::read("target/extract/y/z")?, b"short");
+
+    // FUSE挂载的各项操作
+    use fuser::{FileType, FUSE_ROOT_ID};
+    use libc::{EEXIST, EISDIR, ENOENT};
+    let mut fs = mount::EfsFuse::new(Arc::clone(&efs), 0, 0);
+    let dir = fs.do_create(FUSE_ROOT_ID, "mnt", true).unwrap();
+    assert_eq!(dir.kind, FileType::Directory);
+    let file = fs.do_create(dir.ino, "f", false).unwrap();
+    assert_eq!(fs.do_create(dir.ino, "f", false).unwrap_err(), EEXIST);
+    assert_eq!(fs.do_write(file.ino, 3, b"abc"), Ok(3));
+    assert_eq!(fs.do_read(file.ino, 0, 16).unwrap(), b"\0\0\0abc");
+    assert_eq!(fs.do_setattr(file.ino, Some(1)).unwrap().size, 1);
+    let names: Vec<_> = fs
+        .do_readdir(dir.ino, 1)
+        .unwrap()
+        .into_iter()
+        .map(|(_, _, name)| name)
+        .collect();
+    assert_eq!(names, ["..", "f"]);
+    assert_eq!(fs.do_rename(dir.ino, "f", FUSE_ROOT_ID, "g"), Ok(()));
+    assert_eq!(fs.do_lookup(FUSE_ROOT_ID, "g").unwrap().ino, file.ino);
+    assert_eq!(fs.do_unlink(FUSE_ROOT_ID, "mnt", false), Err(EISDIR));
+    assert_eq!(fs.do_unlink(FUSE_ROOT_ID, "mnt", true), Ok(()));
+    assert_eq!(fs.do_unlink(FUSE_ROOT_ID, "g", false), Ok(()));
+    assert_eq!(fs.do_lookup(FUSE_ROOT_ID, "g").unwrap_err(), ENOENT);
+    // 被查找两次，忘记两次后不再可用
+    fs.do_forget(file.ino, 1);
+    assert!(fs.do_getattr(file.ino).is_ok());
+    fs.do_forget(file.ino, 1);
+    assert_eq!(fs.do_getattr(file.ino).unwrap_err(), ENOENT);
     Ok(())
 }
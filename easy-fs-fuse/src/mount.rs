@@ -0,0 +1,502 @@
+//! 通过FUSE将镜像挂载到宿主机
+//!
+//! FUSE的I结点号为easy-fs的I结点编号加1（FUSE的根目录为1）
+
+use easy_fs::{DiskInodeType, EasyFileSystem, Inode, Metadata, LINK_MAX, NAME_LENGTH_LIMIT};
+use fuser::{
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory, ReplyEmpty,
+    ReplyEntry, ReplyOpen, ReplyStatfs, ReplyWrite, Request, TimeOrNow, FUSE_ROOT_ID,
+};
+use libc::{
+    c_int, EEXIST, EFBIG, EINVAL, EISDIR, EMLINK, ENAMETOOLONG, ENOENT, ENOSPC, ENOTDIR, ENOTEMPTY,
+};
+use spin::Mutex;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// 内核缓存属性和目录项的时间
+const TTL: Duration = Duration::from_secs(1);
+
+const BLOCK_SIZE: u32 = 512;
+
+/// 挂载的文件系统
+pub struct EfsFuse {
+    efs: Arc<Mutex<EasyFileSystem>>,
+    /// 内核知道的I结点及其被查找的次数
+    inodes: HashMap<u64, (Arc<Inode>, u64)>,
+    /// 文件的所有者，easy-fs不记录所有者，使用镜像文件的所有者
+    uid: u32,
+    gid: u32,
+}
+
+impl EfsFuse {
+    pub fn new(efs: Arc<Mutex<EasyFileSystem>>, uid: u32, gid: u32) -> Self {
+        let root = Arc::new(EasyFileSystem::root_inode(&efs));
+        let mut inodes = HashMap::new();
+        // 根目录不会被忘记
+        inodes.insert(FUSE_ROOT_ID, (root, 1));
+        Self {
+            efs,
+            inodes,
+            uid,
+            gid,
+        }
+    }
+
+    fn inode(&self, ino: u64) -> Result<Arc<Inode>, c_int> {
+        self.inodes
+            .get(&ino)
+            .map(|(inode, _)| Arc::clone(inode))
+            .ok_or(ENOENT)
+    }
+
+    fn dir(&self, ino: u64) -> Result<Arc<Inode>, c_int> {
+        let dir = self.inode(ino)?;
+        if dir.is_dir() {
+            Ok(dir)
+        } else {
+            Err(ENOTDIR)
+        }
+    }
+
+    fn attr(&self, metadata: &Metadata) -> FileAttr {
+        let mtime = UNIX_EPOCH + Duration::from_secs(metadata.mtime as u64);
+        let (kind, perm) = match metadata.type_ {
+            DiskInodeType::Directory => (FileType::Directory, 0o755),
+            DiskInodeType::File => (FileType::RegularFile, 0o644),
+            DiskInodeType::Fifo => (FileType::NamedPipe, 0o644),
+        };
+        FileAttr {
+            ino: metadata.inode_id as u64 + 1,
+            size: metadata.size,
+            blocks: metadata.blocks as u64,
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: UNIX_EPOCH + Duration::from_secs(metadata.ctime as u64),
+            kind,
+            perm,
+            nlink: metadata.nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: BLOCK_SIZE,
+            flags: 0,
+        }
+    }
+
+    /// 登记一次内核对I结点的查找，返回其属性
+    fn remember(&mut self, inode: Arc<Inode>) -> FileAttr {
+        let attr = self.attr(&inode.metadata());
+        self.inodes.entry(attr.ino).or_insert((inode, 0)).1 += 1;
+        attr
+    }
+
+    /// 内核忘记了nlookup次查找
+    pub fn do_forget(&mut self, ino: u64, nlookup: u64) {
+        if ino == FUSE_ROOT_ID {
+            return;
+        }
+        if let Some((_, count)) = self.inodes.get_mut(&ino) {
+            *count = count.saturating_sub(nlookup);
+            if *count == 0 {
+                self.inodes.remove(&ino);
+            }
+        }
+    }
+
+    pub fn do_lookup(&mut self, parent: u64, name: &str) -> Result<FileAttr, c_int> {
+        let inode = self.dir(parent)?.find(name).ok_or(ENOENT)?;
+        Ok(self.remember(inode))
+    }
+
+    pub fn do_getattr(&self, ino: u64) -> Result<FileAttr, c_int> {
+        Ok(self.attr(&self.inode(ino)?.metadata()))
+    }
+
+    /// 修改文件大小，easy-fs不记录权限和所有者，其余属性被忽略
+    pub fn do_setattr(&self, ino: u64, size: Option<u64>) -> Result<FileAttr, c_int> {
+        let inode = self.inode(ino)?;
+        if let Some(size) = size {
+            if inode.is_dir() {
+                return Err(EISDIR);
+            }
+            if !inode.truncate(size) {
+                return Err(EFBIG);
+            }
+        }
+        Ok(self.attr(&inode.metadata()))
+    }
+
+    /// 在目录parent中创建文件或目录
+    ///
+    /// 父目录的链接数已达上限返回EMLINK，空闲的I结点或数据块不足返回ENOSPC
+    pub fn do_create(&mut self, parent: u64, name: &str, dir: bool) -> Result<FileAttr, c_int> {
+        let parent = self.dir(parent)?;
+        if name.len() > NAME_LENGTH_LIMIT {
+            return Err(ENAMETOOLONG);
+        }
+        if parent.find(name).is_some() {
+            return Err(EEXIST);
+        }
+        let (inode, type_) = if dir {
+            (parent.mkdir(name), DiskInodeType::Directory)
+        } else {
+            (parent.create(name), DiskInodeType::File)
+        };
+        let inode = inode.ok_or_else(|| {
+            if dir && parent.nlink() >= LINK_MAX as u32 {
+                EMLINK
+            } else if !parent.has_space_for(Some(type_)) {
+                ENOSPC
+            } else {
+                EINVAL
+            }
+        })?;
+        Ok(self.remember(inode))
+    }
+
+    /// 删除目录parent中的文件或空目录
+    pub fn do_unlink(&self, parent: u64, name: &str, dir: bool) -> Result<(), c_int> {
+        let parent = self.dir(parent)?;
+        let inode = parent.find(name).ok_or(ENOENT)?;
+        match (dir, inode.is_dir()) {
+            (true, true) if parent.rmdir(name) => Ok(()),
+            (true, true) => Err(ENOTEMPTY),
+            (true, false) => Err(ENOTDIR),
+            (false, true) => Err(EISDIR),
+            (false, false) if parent.unlink(name) => Ok(()),
+            (false, false) => Err(EINVAL),
+        }
+    }
+
+    pub fn do_rename(
+        &self,
+        parent: u64,
+        name: &str,
+        new_parent: u64,
+        new_name: &str,
+    ) -> Result<(), c_int> {
+        let parent = self.dir(parent)?;
+        let new_parent = self.dir(new_parent)?;
+        let src = parent.find(name).ok_or(ENOENT)?;
+        if let Some(dst) = new_parent.find(new_name) {
+            match (src.is_dir(), dst.is_dir()) {
+                (true, false) => return Err(ENOTDIR),
+                (false, true) => return Err(EISDIR),
+                _ => {}
+            }
+        }
+        if parent.rename(name, &new_parent, new_name) {
+            Ok(())
+        } else if new_parent.find(new_name).is_some() {
+            Err(ENOTEMPTY)
+        } else if !new_parent.has_space_for(None) {
+            Err(ENOSPC)
+        } else if src.is_dir() && new_parent.nlink() >= LINK_MAX as u32 {
+            Err(EMLINK)
+        } else {
+            Err(EINVAL)
+        }
+    }
+
+    pub fn do_read(&self, ino: u64, offset: u64, size: u32) -> Result<Vec<u8>, c_int> {
+        let inode = self.inode(ino)?;
+        if inode.is_dir() {
+            return Err(EISDIR);
+        }
+        let mut buf = vec![0u8; size as usize];
+        let len = inode.read_at(offset as usize, &mut buf);
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    pub fn do_write(&self, ino: u64, offset: u64, data: &[u8]) -> Result<u32, c_int> {
+        let inode = self.inode(ino)?;
+        if inode.is_dir() {
+            return Err(EISDIR);
+        }
+        match inode.write_at(offset as usize, data) {
+            0 if !data.is_empty() => Err(EFBIG),
+            written => Ok(written as u32),
+        }
+    }
+
+    /// 读取目录中第offset项之后的目录项，返回(I结点号, 类型, 名字)
+    pub fn do_readdir(
+        &self,
+        ino: u64,
+        offset: usize,
+    ) -> Result<Vec<(u64, FileType, String)>, c_int> {
+        let (entries, _) = self.dir(ino)?.read_dir(0, usize::MAX).ok_or(ENOTDIR)?;
+        Ok(entries
+            .into_iter()
+            .skip(offset)
+            .map(|entry| {
+                let kind = match entry.type_ {
+                    DiskInodeType::Directory => FileType::Directory,
+                    DiskInodeType::File => FileType::RegularFile,
+                    DiskInodeType::Fifo => FileType::NamedPipe,
+                };
+                (entry.inode_id as u64 + 1, kind, entry.name)
+            })
+            .collect())
+    }
+
+    /// 写回所有脏块
+    pub fn sync(&self) {
+        self.inodes[&FUSE_ROOT_ID].0.sync();
+    }
+}
+
+/// 文件名必须是UTF-8
+fn utf8(name: &OsStr) -> Result<&str, c_int> {
+    name.to_str().ok_or(EINVAL)
+}
+
+impl Filesystem for EfsFuse {
+    fn destroy(&mut self) {
+        self.sync();
+    }
+
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        match utf8(name).and_then(|name| self.do_lookup(parent, name)) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn forget(&mut self, _req: &Request<'_>, ino: u64, nlookup: u64) {
+        self.do_forget(ino, nlookup);
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        match self.do_getattr(ino) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<TimeOrNow>,
+        _mtime: Option<TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        _fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: ReplyAttr,
+    ) {
+        match self.do_setattr(ino, size) {
+            Ok(attr) => reply.attr(&TTL, &attr),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn mkdir(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: ReplyEntry,
+    ) {
+        match utf8(name).and_then(|name| self.do_create(parent, name, true)) {
+            Ok(attr) => reply.entry(&TTL, &attr, 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        match utf8(name).and_then(|name| self.do_unlink(parent, name, false)) {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+        match utf8(name).and_then(|name| self.do_unlink(parent, name, true)) {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn rename(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        new_parent: u64,
+        new_name: &OsStr,
+        flags: u32,
+        reply: ReplyEmpty,
+    ) {
+        // 不支持RENAME_NOREPLACE等标志
+        let result = if flags != 0 {
+            Err(EINVAL)
+        } else {
+            utf8(name).and_then(|name| {
+                let new_name = utf8(new_name)?;
+                self.do_rename(parent, name, new_parent, new_name)
+            })
+        };
+        match result {
+            Ok(()) => reply.ok(),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    /// 打开的文件在被删除后仍可访问，直到最后一次关闭
+    fn open(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        match self.inode(ino) {
+            Ok(inode) => {
+                inode.open();
+                reply.opened(0, 0);
+            }
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        if let Ok(inode) = self.inode(ino) {
+            inode.close();
+        }
+        reply.ok();
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        match self.do_read(ino, offset as u64, size) {
+            Ok(data) => reply.data(&data),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn write(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyWrite,
+    ) {
+        match self.do_write(ino, offset as u64, data) {
+            Ok(written) => reply.written(written),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn flush(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _lock_owner: u64,
+        reply: ReplyEmpty,
+    ) {
+        // 关闭文件时写回
+        self.sync();
+        reply.ok();
+    }
+
+    fn fsync(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _datasync: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.sync();
+        reply.ok();
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        match self.do_readdir(ino, offset as usize) {
+            Ok(entries) => {
+                for (i, (ino, kind, name)) in entries.into_iter().enumerate() {
+                    // 下一次从这一项之后读取，缓冲区满时停止
+                    if reply.add(ino, offset + i as i64 + 1, kind, name) {
+                        break;
+                    }
+                }
+                reply.ok();
+            }
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+        let stats = self.efs.lock().stats();
+        let free_blocks = (stats.data_area_blocks - stats.used_data_blocks) as u64;
+        reply.statfs(
+            stats.total_blocks as u64,
+            free_blocks,
+            free_blocks,
+            stats.total_inodes as u64,
+            (stats.total_inodes - stats.used_inodes) as u64,
+            BLOCK_SIZE,
+            NAME_LENGTH_LIMIT as u32,
+            BLOCK_SIZE,
+        );
+    }
+
+    fn create(
+        &mut self,
+        _req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        _mode: u32,
+        _umask: u32,
+        _flags: i32,
+        reply: ReplyCreate,
+    ) {
+        match utf8(name).and_then(|name| self.do_create(parent, name, false)) {
+            Ok(attr) => {
+                self.inode(attr.ino).unwrap().open();
+                reply.created(&TTL, &attr, 0, 0, 0);
+            }
+            Err(errno) => reply.error(errno),
+        }
+    }
+}
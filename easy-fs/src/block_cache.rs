@@ -1,37 +1,45 @@
-use super::{BlockDevice, BLOCK_SIZE};
+use super::{journal, BlockDevice, BLOCK_SIZE};
 use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 use spin::Mutex;
 
+/// 按8字节对齐的块数据，使其中可以存放含u64字段的结构
+#[repr(C, align(8))]
+struct AlignedBlock([u8; BLOCK_SIZE]);
+
 /// 块缓存
 pub struct BlockCache {
     /// 缓存区域
-    cache: [u8; BLOCK_SIZE],
+    cache: AlignedBlock,
     /// 块id
     block_id: usize,
     /// 块设备
     block_device: Arc<dyn BlockDevice>,
     /// 缓存是否被修改
     modified: bool,
+    /// 是否在未提交的事务中被修改，此时不能写回
+    in_transaction: bool,
 }
 
 impl BlockCache {
     /// Load a new BlockCache from disk.
     pub fn new(block_id: usize, block_device: Arc<dyn BlockDevice>) -> Self {
-        let mut cache = [0u8; BLOCK_SIZE];
-        block_device.read_block(block_id, &mut cache);
+        let mut cache = AlignedBlock([0u8; BLOCK_SIZE]);
+        block_device.read_block(block_id, &mut cache.0);
         Self {
             cache,
             block_id,
             block_device,
             modified: false,
+            in_transaction: false,
         }
     }
 
     /// 获取块缓存中offset处的内存地址
     fn addr_of_offset(&self, offset: usize) -> usize {
-        &self.cache[offset] as *const _ as usize
+        &self.cache.0[offset] as *const _ as usize
     }
 
     /// 获取块缓存中offset处的T类型不可变引用
@@ -53,6 +61,9 @@ impl BlockCache {
         let type_size = core::mem::size_of::<T>();
         assert!(offset + type_size <= BLOCK_SIZE);
         self.modified = true;
+        if journal::record(self.block_id) {
+            self.in_transaction = true;
+        }
         let addr = self.addr_of_offset(offset);
         unsafe { &mut *(addr as *mut T) }
     }
@@ -71,11 +82,26 @@ impl BlockCache {
         f(self.get_mut(offset))
     }
 
-    /// 写回块设备
+    /// 缓存是否被修改且尚未写回
+    pub fn is_dirty(&self) -> bool {
+        self.modified
+    }
+
+    /// 事务已提交，允许写回
+    pub fn unpin(&mut self) {
+        self.in_transaction = false;
+    }
+
+    /// 是否可以被淘汰：没有被持有且不在未提交的事务中
+    fn evictable(cache: &Arc<Mutex<BlockCache>>) -> bool {
+        Arc::strong_count(cache) == 1 && !cache.lock().in_transaction
+    }
+
+    /// 写回块设备，未提交的事务中的块要等到提交后才写回
     pub fn sync(&mut self) {
-        if self.modified {
+        if self.modified && !self.in_transaction {
             self.modified = false;
-            self.block_device.write_block(self.block_id, &self.cache);
+            self.block_device.write_block(self.block_id, &self.cache.0);
         }
     }
 }
@@ -87,53 +113,103 @@ impl Drop for BlockCache {
     }
 }
 
-const BLOCK_CACHE_SIZE: usize = 16;
+/// 默认的块缓存容量
+const DEFAULT_BLOCK_CACHE_SIZE: usize = 64;
 
 /// 块缓存管理器
+///
+/// 按LRU顺序管理块缓存，脏块只在被淘汰或显式同步时写回块设备
 pub struct BlockCacheManager {
-    /// 管理的块缓存队列
+    /// 管理的块缓存队列，队首最久未使用
     queue: VecDeque<(usize, Arc<Mutex<BlockCache>>)>,
+    /// 容量
+    capacity: usize,
 }
 
 impl BlockCacheManager {
     pub fn new() -> Self {
         Self {
             queue: VecDeque::new(),
+            capacity: DEFAULT_BLOCK_CACHE_SIZE,
         }
     }
 
     /// 获取一个块缓存
+    ///
+    /// 命中时将其移到队尾；缓存已满时淘汰最久未使用且未被持有的块，
+    /// 所有块都被持有时暂时超出容量，之后再逐步淘汰
     pub fn get_block_cache(
         &mut self,
         block_id: usize,
         block_device: Arc<dyn BlockDevice>,
     ) -> Arc<Mutex<BlockCache>> {
-        if let Some(pair) = self.queue.iter().find(|pair| pair.0 == block_id) {
-            Arc::clone(&pair.1)
-        } else {
-            // 找一个块缓存替换
-            if self.queue.len() == BLOCK_CACHE_SIZE {
-                // from front to tail
-                if let Some((idx, _)) = self
-                    .queue
-                    .iter()
-                    .enumerate()
-                    .find(|(_, pair)| Arc::strong_count(&pair.1) == 1)
-                {
-                    self.queue.drain(idx..=idx);
-                } else {
-                    panic!("Run out of BlockCache!");
-                }
+        if let Some(idx) = self.queue.iter().position(|pair| pair.0 == block_id) {
+            let pair = self.queue.remove(idx).unwrap();
+            let block_cache = Arc::clone(&pair.1);
+            self.queue.push_back(pair);
+            return block_cache;
+        }
+        self.shrink(self.capacity - 1);
+        // 脏块过多时先写回一部分，写入速度受限于块设备
+        if self.dirty_count() > self.capacity / 2 {
+            self.write_back(self.capacity / 4);
+        }
+        // load block into mem and push back
+        let block_cache = Arc::new(Mutex::new(BlockCache::new(
+            block_id,
+            Arc::clone(&block_device),
+        )));
+        self.queue.push_back((block_id, Arc::clone(&block_cache)));
+        block_cache
+    }
+
+    /// 从队首开始淘汰未被持有的块，直到不超过len个，脏块在释放时写回
+    fn shrink(&mut self, len: usize) {
+        let mut idx = 0;
+        while self.queue.len() > len && idx < self.queue.len() {
+            if BlockCache::evictable(&self.queue[idx].1) {
+                self.queue.remove(idx);
+            } else {
+                idx += 1;
             }
-            // load block into mem and push back
-            let block_cache = Arc::new(Mutex::new(BlockCache::new(
-                block_id,
-                Arc::clone(&block_device),
-            )));
-            self.queue.push_back((block_id, Arc::clone(&block_cache)));
-            block_cache
         }
     }
+
+    /// 可以被淘汰的脏块个数
+    ///
+    /// 被持有的块可能正被锁住，不计入
+    fn dirty_count(&self) -> usize {
+        self.queue
+            .iter()
+            .filter(|pair| BlockCache::evictable(&pair.1) && pair.1.lock().is_dirty())
+            .count()
+    }
+
+    /// 从队首开始写回最多count个可以被淘汰的脏块
+    fn write_back(&mut self, count: usize) {
+        self.queue
+            .iter()
+            .filter(|pair| BlockCache::evictable(&pair.1))
+            .filter(|pair| pair.1.lock().is_dirty())
+            .take(count)
+            .for_each(|pair| pair.1.lock().sync());
+    }
+
+    /// 设置容量，至少为1，超出的块立即淘汰
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        self.shrink(self.capacity);
+    }
+
+    /// 缓存的块数和其中的脏块数，正被锁住的块视为脏块
+    pub fn stats(&self) -> (usize, usize) {
+        let dirty = self
+            .queue
+            .iter()
+            .filter(|pair| pair.1.try_lock().map_or(true, |cache| cache.is_dirty()))
+            .count();
+        (self.queue.len(), dirty)
+    }
 }
 
 lazy_static! {
@@ -151,9 +227,54 @@ pub fn get_block_cache(
         .get_block_cache(block_id, block_device)
 }
 
+/// 设置块缓存的容量（块数）
+pub fn set_block_cache_size(capacity: usize) {
+    BLOCK_CACHE_MANAGER.lock().set_capacity(capacity);
+}
+
+/// 若块block_id在缓存中则将其写回，unpin为true时同时允许之后写回
+pub fn sync_block_cache(block_id: usize, unpin: bool) {
+    let block_cache = BLOCK_CACHE_MANAGER
+        .lock()
+        .queue
+        .iter()
+        .find(|pair| pair.0 == block_id)
+        .map(|pair| &pair.1)
+        .cloned();
+    if let Some(block_cache) = block_cache {
+        let mut block_cache = block_cache.lock();
+        if unpin {
+            block_cache.unpin();
+        }
+        block_cache.sync();
+    }
+}
+
+/// 写回并丢弃所有可以被淘汰的块缓存
+///
+/// 块缓存不区分块设备，更换块设备前需要调用
+pub fn block_cache_clear() {
+    BLOCK_CACHE_MANAGER.lock().shrink(0);
+}
+
+/// 缓存的块数和其中的脏块数
+pub fn block_cache_stats() -> (usize, usize) {
+    BLOCK_CACHE_MANAGER.lock().stats()
+}
+
+/// 将所有脏块写回块设备，未提交的事务中的块除外
+///
+/// 调用者不能持有任何块缓存的锁
 pub fn block_cache_sync_all() {
-    let manager = BLOCK_CACHE_MANAGER.lock();
-    for (_, cache) in manager.queue.iter() {
+    // 先复制队列再逐个写回，写回时不持有管理器的锁
+    let caches: Vec<_> = BLOCK_CACHE_MANAGER
+        .lock()
+        .queue
+        .iter()
+        .map(|(_, cache)| cache)
+        .cloned()
+        .collect();
+    for cache in caches {
         cache.lock().sync();
     }
 }
@@ -0,0 +1,25 @@
+//! 文件系统时钟
+//!
+//! 创建和修改文件时从时钟读取时间，写入DiskInode中
+use lazy_static::*;
+use spin::Mutex;
+
+/// 没有设置时钟时，时间总是0
+fn zero_clock() -> u64 {
+    0
+}
+
+lazy_static! {
+    /// 获取当前时间的函数，返回自1970年以来的秒数
+    static ref CLOCK: Mutex<fn() -> u64> = Mutex::new(zero_clock);
+}
+
+/// 设置文件系统获取当前时间的函数
+pub fn set_clock(clock: fn() -> u64) {
+    *CLOCK.lock() = clock;
+}
+
+/// 当前时间，自1970年以来的秒数
+pub fn now() -> u32 {
+    (CLOCK.lock())() as u32
+}
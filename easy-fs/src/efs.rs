@@ -1,9 +1,9 @@
 use super::{
-    block_cache_sync_all, get_block_cache, Bitmap, BlockDevice, DiskInode, DiskInodeType, Inode,
-    SuperBlock,
+    block_cache_sync_all, get_block_cache, journal, now, Bitmap, BlockDevice, DirEntry, DiskInode,
+    DiskInodeType, Inode, SuperBlock, DIRENT_SZ,
 };
 use crate::BLOCK_SIZE;
-use alloc::sync::Arc;
+use alloc::{collections::BTreeMap, sync::Arc};
 use spin::Mutex;
 
 /// 内存中的文件系统管理结构
@@ -20,10 +20,35 @@ pub struct EasyFileSystem {
     inode_area_start_block: u32,
     /// 数据区的起始块号
     data_area_start_block: u32,
+    /// 被打开的I结点编号及其打开次数
+    open_inodes: BTreeMap<u32, usize>,
 }
 
 type DataBlock = [u8; BLOCK_SIZE];
 
+/// 文件系统各区域的大小和使用情况
+#[derive(Debug, Clone, Copy)]
+pub struct FsStats {
+    /// 总块数
+    pub total_blocks: u32,
+    /// 日志区的块数
+    pub journal_blocks: u32,
+    /// I结点位图的块数
+    pub inode_bitmap_blocks: u32,
+    /// I结点区的块数
+    pub inode_area_blocks: u32,
+    /// 数据位图的块数
+    pub data_bitmap_blocks: u32,
+    /// 数据区的块数
+    pub data_area_blocks: u32,
+    /// I结点总数
+    pub total_inodes: u32,
+    /// 已分配的I结点数
+    pub used_inodes: u32,
+    /// 已分配的数据块数（包括索引块）
+    pub used_data_blocks: u32,
+}
+
 impl EasyFileSystem {
     pub fn create(
         block_device: Arc<dyn BlockDevice>,
@@ -31,26 +56,31 @@ impl EasyFileSystem {
         inode_bitmap_blocks: u32,
     ) -> Arc<Mutex<Self>> {
         // calculate block size of areas & create bitmaps
-        let inode_bitmap = Bitmap::new(1, inode_bitmap_blocks as usize);
+        // 超级块之后是日志区
+        let journal_blocks = journal::JOURNAL_BLOCKS;
+        let inode_bitmap = Bitmap::new(1 + journal_blocks as usize, inode_bitmap_blocks as usize);
         let inode_num = inode_bitmap.maximum();
         let inode_area_blocks =
             ((inode_num * core::mem::size_of::<DiskInode>() + BLOCK_SIZE - 1) / BLOCK_SIZE) as u32;
         let inode_total_blocks = inode_bitmap_blocks + inode_area_blocks;
 
-        let data_total_blocks = total_blocks - 1 - inode_total_blocks;
+        let data_total_blocks = total_blocks - 1 - journal_blocks - inode_total_blocks;
         let data_bitmap_blocks = (data_total_blocks + 4096) / 4097;
         let data_area_blocks = data_total_blocks - data_bitmap_blocks;
         let data_bitmap = Bitmap::new(
-            (1 + inode_bitmap_blocks + inode_area_blocks) as usize,
+            (1 + journal_blocks + inode_total_blocks) as usize,
             data_bitmap_blocks as usize,
         );
         let mut efs = Self {
             block_device: Arc::clone(&block_device),
             inode_bitmap,
             data_bitmap,
-            inode_area_start_block: 1 + inode_bitmap_blocks,
-            data_area_start_block: 1 + inode_total_blocks + data_bitmap_blocks,
+            inode_area_start_block: 1 + journal_blocks + inode_bitmap_blocks,
+            data_area_start_block: 1 + journal_blocks + inode_total_blocks + data_bitmap_blocks,
+            open_inodes: BTreeMap::new(),
         };
+        // 格式化期间不记日志
+        journal::init(Arc::clone(&block_device), 1, 0);
         // clear all blocks
         for i in 0..total_blocks {
             get_block_cache(i as usize, Arc::clone(&block_device))
@@ -71,6 +101,7 @@ impl EasyFileSystem {
                     inode_area_blocks,
                     data_bitmap_blocks,
                     data_area_blocks,
+                    journal_blocks,
                 );
             },
         );
@@ -78,38 +109,65 @@ impl EasyFileSystem {
         // create a inode for root node "/"
         assert_eq!(efs.alloc_inode(), 0);
         let (root_inode_block_id, root_inode_offset) = efs.get_disk_inode_pos(0);
+        let root_data_block = efs.alloc_data();
         get_block_cache(root_inode_block_id as usize, Arc::clone(&block_device))
             .lock()
             .modify(root_inode_offset, |disk_inode: &mut DiskInode| {
-                disk_inode.initialize(DiskInodeType::Directory);
+                disk_inode.initialize(DiskInodeType::Directory, now());
+                // 根目录的`.`和`..`都指向自己
+                disk_inode.increase_size(
+                    2 * DIRENT_SZ as u64,
+                    alloc::vec![root_data_block],
+                    &block_device,
+                );
+                disk_inode.write_at(0, DirEntry::new(".", 0).as_bytes(), &block_device);
+                disk_inode.write_at(DIRENT_SZ, DirEntry::new("..", 0).as_bytes(), &block_device);
             });
         block_cache_sync_all();
+        journal::init(block_device, 1, journal_blocks as usize);
         Arc::new(Mutex::new(efs))
     }
 
     /// 打开一个块设备
     ///
-    /// 读取超级块，返回`EasyFileSystem`对象
+    /// 读取超级块并重放日志，返回`EasyFileSystem`对象
     pub fn open(block_device: Arc<dyn BlockDevice>) -> Arc<Mutex<Self>> {
         // read SuperBlock
-        get_block_cache(0, Arc::clone(&block_device))
-            .lock()
-            .read(0, |super_block: &SuperBlock| {
+        let (efs, journal_blocks) = get_block_cache(0, Arc::clone(&block_device)).lock().read(
+            0,
+            |super_block: &SuperBlock| {
                 assert!(super_block.is_valid(), "Error loading EFS!");
+                assert!(
+                    super_block.is_supported(),
+                    "Unsupported EFS format version {}!",
+                    super_block.version
+                );
+                let journal_blocks = super_block.journal_blocks;
                 let inode_total_blocks =
                     super_block.inode_bitmap_blocks + super_block.inode_area_blocks;
                 let efs = Self {
-                    block_device,
-                    inode_bitmap: Bitmap::new(1, super_block.inode_bitmap_blocks as usize),
+                    block_device: Arc::clone(&block_device),
+                    inode_bitmap: Bitmap::new(
+                        (1 + journal_blocks) as usize,
+                        super_block.inode_bitmap_blocks as usize,
+                    ),
                     data_bitmap: Bitmap::new(
-                        (1 + inode_total_blocks) as usize,
+                        (1 + journal_blocks + inode_total_blocks) as usize,
                         super_block.data_bitmap_blocks as usize,
                     ),
-                    inode_area_start_block: 1 + super_block.inode_bitmap_blocks,
-                    data_area_start_block: 1 + inode_total_blocks + super_block.data_bitmap_blocks,
+                    inode_area_start_block: 1 + journal_blocks + super_block.inode_bitmap_blocks,
+                    data_area_start_block: 1
+                        + journal_blocks
+                        + inode_total_blocks
+                        + super_block.data_bitmap_blocks,
+                    open_inodes: BTreeMap::new(),
                 };
-                Arc::new(Mutex::new(efs))
-            })
+                (efs, journal_blocks as usize)
+            },
+        );
+        journal::replay(&block_device, 1, journal_blocks);
+        journal::init(block_device, 1, journal_blocks);
+        Arc::new(Mutex::new(efs))
     }
 
     /// 为根节点（第0个Inode）建立Inode
@@ -120,7 +178,7 @@ impl EasyFileSystem {
         // acquire efs lock temporarily
         let (block_id, block_offset) = efs.lock().get_disk_inode_pos(0);
         // release efs lock
-        Inode::new(block_id, block_offset, Arc::clone(efs), block_device)
+        Inode::new(0, block_id, block_offset, Arc::clone(efs), block_device)
     }
 
     /// 给定Inode编号，返回其在磁盘上的块编号和块中偏移
@@ -142,19 +200,92 @@ impl EasyFileSystem {
         self.inode_bitmap.alloc(&self.block_device).unwrap() as u32
     }
 
+    /// 登记一次对I结点的打开
+    pub fn open_inode(&mut self, inode_id: u32) {
+        *self.open_inodes.entry(inode_id).or_insert(0) += 1;
+    }
+
+    /// 撤销一次对I结点的打开
+    pub fn close_inode(&mut self, inode_id: u32) {
+        if let Some(count) = self.open_inodes.get_mut(&inode_id) {
+            *count -= 1;
+            if *count == 0 {
+                self.open_inodes.remove(&inode_id);
+            }
+        }
+    }
+
+    /// 已分配的I结点数
+    pub fn used_inodes(&self) -> usize {
+        self.inode_bitmap.count_allocated(&self.block_device)
+    }
+
+    /// 空闲的I结点数
+    pub fn free_inodes(&self) -> usize {
+        self.inode_bitmap.maximum() - self.used_inodes()
+    }
+
+    /// I结点是否被打开
+    pub fn is_open(&self, inode_id: u32) -> bool {
+        self.open_inodes.contains_key(&inode_id)
+    }
+
+    /// 释放一个I结点
+    pub fn dealloc_inode(&mut self, inode_id: u32) {
+        self.inode_bitmap
+            .dealloc(&self.block_device, inode_id as usize)
+    }
+
     /// Return a block ID not ID in the data area.
     pub fn alloc_data(&mut self) -> u32 {
         self.data_bitmap.alloc(&self.block_device).unwrap() as u32 + self.data_area_start_block
     }
 
-    pub fn dealloc_data(&mut self, block_id: u32) {
-        get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+    /// 已分配的数据块数
+    pub fn used_data_blocks(&self) -> usize {
+        self.data_bitmap.count_allocated(&self.block_device)
+    }
+
+    /// 空闲的数据块数
+    pub fn free_data_blocks(&self) -> usize {
+        let data_area_blocks = get_block_cache(0, Arc::clone(&self.block_device))
             .lock()
-            .modify(0, |data_block: &mut DataBlock| {
-                data_block.iter_mut().for_each(|p| {
-                    *p = 0;
-                })
+            .read(0, |super_block: &SuperBlock| super_block.data_area_blocks);
+        (data_area_blocks as usize).saturating_sub(self.used_data_blocks())
+    }
+
+    /// 读取超级块，统计各区域的大小和使用情况
+    pub fn stats(&self) -> FsStats {
+        let mut stats = get_block_cache(0, Arc::clone(&self.block_device))
+            .lock()
+            .read(0, |super_block: &SuperBlock| FsStats {
+                total_blocks: super_block.total_blocks,
+                journal_blocks: super_block.journal_blocks,
+                inode_bitmap_blocks: super_block.inode_bitmap_blocks,
+                inode_area_blocks: super_block.inode_area_blocks,
+                data_bitmap_blocks: super_block.data_bitmap_blocks,
+                data_area_blocks: super_block.data_area_blocks,
+                total_inodes: 0,
+                used_inodes: 0,
+                used_data_blocks: 0,
             });
+        stats.total_inodes = self.inode_bitmap.maximum() as u32;
+        stats.used_inodes = self.used_inodes() as u32;
+        stats.used_data_blocks = self.used_data_blocks() as u32;
+        stats
+    }
+
+    /// 释放一个数据块，清零后的块在事务提交后写回
+    pub fn dealloc_data(&mut self, block_id: u32) {
+        journal::ordered(|| {
+            get_block_cache(block_id as usize, Arc::clone(&self.block_device))
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| {
+                    data_block.iter_mut().for_each(|p| {
+                        *p = 0;
+                    })
+                })
+        });
         self.data_bitmap.dealloc(
             &self.block_device,
             (block_id - self.data_area_start_block) as usize,
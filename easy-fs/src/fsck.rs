@@ -0,0 +1,701 @@
+//! 文件系统检查
+//!
+//! 从根目录遍历目录树，检查超级块、I结点、目录项与两个位图是否一致，
+//! 并可选地修复发现的问题。检查直接读写块设备，不能与修改文件系统的操作同时进行
+
+use super::{
+    block_cache_sync_all, get_block_cache, journal, Bitmap, BlockDevice, DirEntry, DiskInode,
+    DiskInodeType, IndirectBlock, SuperBlock, BLOCK_SIZE, DIRENT_SZ, INODE_DIRECT_COUNT,
+    INODE_INDIRECT1_COUNT, MAX_FILE_SIZE,
+};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::fmt;
+
+type DataBlock = [u8; BLOCK_SIZE];
+
+/// 修复后重新检查的最多次数
+///
+/// 一次修复可能产生新的问题，如断开的目录中的I结点成为孤立的
+const MAX_PASSES: usize = 8;
+
+/// 检查发现的问题
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// 超级块损坏，无法继续检查
+    BadSuperBlock(&'static str),
+    /// 根目录损坏，无法继续检查
+    BadRoot(&'static str),
+    /// 日志中有已提交但未写回的事务
+    JournalPending,
+    /// I结点损坏
+    BadInode { inode_id: u32, reason: &'static str },
+    /// 目录项损坏，slot为目录项在目录中的序号
+    BadDirent {
+        dir: u32,
+        slot: usize,
+        reason: &'static str,
+    },
+    /// 已分配但从根目录不可达的I结点
+    OrphanInode(u32),
+    /// 可达但未在位图中分配的I结点
+    InodeNotAllocated(u32),
+    /// 同一个块被两处引用
+    DoubleAllocated {
+        block_id: u32,
+        first: u32,
+        second: u32,
+    },
+    /// 链接数与指向I结点的目录项数不符
+    WrongLinkCount {
+        inode_id: u32,
+        nlink: u16,
+        expected: u16,
+    },
+    /// 已分配但未被引用的数据块
+    LeakedBlock(u32),
+    /// 被引用但未在位图中分配的数据块
+    BlockNotAllocated(u32),
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Problem::BadSuperBlock(reason) => write!(f, "bad super block: {}", reason),
+            Problem::BadRoot(reason) => write!(f, "bad root directory: {}", reason),
+            Problem::JournalPending => write!(f, "journal has a transaction to replay"),
+            Problem::BadInode { inode_id, reason } => write!(f, "inode {}: {}", inode_id, reason),
+            Problem::BadDirent { dir, slot, reason } => {
+                write!(f, "directory {} entry {}: {}", dir, slot, reason)
+            }
+            Problem::OrphanInode(inode_id) => write!(f, "inode {} is unreachable", inode_id),
+            Problem::InodeNotAllocated(inode_id) => {
+                write!(f, "inode {} is in use but free in the bitmap", inode_id)
+            }
+            Problem::DoubleAllocated {
+                block_id,
+                first,
+                second,
+            } => write!(
+                f,
+                "block {} is used by both inode {} and inode {}",
+                block_id, first, second
+            ),
+            Problem::WrongLinkCount {
+                inode_id,
+                nlink,
+                expected,
+            } => write!(
+                f,
+                "inode {} has {} links, expected {}",
+                inode_id, nlink, expected
+            ),
+            Problem::LeakedBlock(block_id) => {
+                write!(f, "block {} is allocated but unused", block_id)
+            }
+            Problem::BlockNotAllocated(block_id) => {
+                write!(f, "block {} is in use but free in the bitmap", block_id)
+            }
+        }
+    }
+}
+
+/// 检查块设备上的文件系统，device_blocks为块设备的总块数
+///
+/// repair为true时先重放日志，再修复发现的问题并重新检查，直到没有问题。
+/// 返回发现的问题，超级块或根目录损坏时返回Err
+pub fn fsck(
+    block_device: Arc<dyn BlockDevice>,
+    device_blocks: usize,
+    repair: bool,
+) -> Result<Vec<Problem>, Problem> {
+    let geometry = Geometry::read(&block_device, device_blocks)?;
+    let mut problems = Vec::new();
+    if journal::pending(&block_device, 1, geometry.journal_blocks) {
+        problems.push(Problem::JournalPending);
+        if repair {
+            journal::replay(&block_device, 1, geometry.journal_blocks);
+        }
+    }
+    for _ in 0..MAX_PASSES {
+        let found = Checker::new(&block_device, &geometry, repair).run()?;
+        let clean = found.is_empty();
+        for problem in found {
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+        if clean || !repair {
+            break;
+        }
+    }
+    if repair {
+        block_cache_sync_all();
+    }
+    Ok(problems)
+}
+
+/// 由超级块得到的各区域位置
+struct Geometry {
+    journal_blocks: usize,
+    inode_bitmap: Bitmap,
+    data_bitmap: Bitmap,
+    inode_area_start: u32,
+    data_area_start: u32,
+    data_area_blocks: u32,
+    /// I结点区能容纳的I结点数
+    inodes: u32,
+}
+
+impl Geometry {
+    /// 读取并检查超级块
+    fn read(block_device: &Arc<dyn BlockDevice>, device_blocks: usize) -> Result<Self, Problem> {
+        let (valid, supported, sb) = get_block_cache(0, Arc::clone(block_device)).lock().read(
+            0,
+            |super_block: &SuperBlock| {
+                (
+                    super_block.is_valid(),
+                    super_block.is_supported(),
+                    [
+                        super_block.total_blocks,
+                        super_block.journal_blocks,
+                        super_block.inode_bitmap_blocks,
+                        super_block.inode_area_blocks,
+                        super_block.data_bitmap_blocks,
+                        super_block.data_area_blocks,
+                    ],
+                )
+            },
+        );
+        if !valid {
+            return Err(Problem::BadSuperBlock("bad magic number"));
+        }
+        if !supported {
+            return Err(Problem::BadSuperBlock("unsupported format version"));
+        }
+        let [total, journal, inode_bitmap, inode_area, data_bitmap, data_area] = sb.map(u64::from);
+        if total > device_blocks as u64 {
+            return Err(Problem::BadSuperBlock(
+                "file system is larger than the device",
+            ));
+        }
+        if 1 + journal + inode_bitmap + inode_area + data_bitmap + data_area != total {
+            return Err(Problem::BadSuperBlock(
+                "areas do not add up to the total size",
+            ));
+        }
+        let bits = (BLOCK_SIZE * 8) as u64;
+        let inodes_per_block = (BLOCK_SIZE / core::mem::size_of::<DiskInode>()) as u64;
+        if inode_area * inodes_per_block < inode_bitmap * bits {
+            return Err(Problem::BadSuperBlock(
+                "inode area is smaller than the inode bitmap",
+            ));
+        }
+        if data_bitmap * bits < data_area {
+            return Err(Problem::BadSuperBlock(
+                "data bitmap is smaller than the data area",
+            ));
+        }
+        if inode_bitmap == 0 {
+            return Err(Problem::BadSuperBlock("no inode bitmap"));
+        }
+        Ok(Self {
+            journal_blocks: journal as usize,
+            inode_bitmap: Bitmap::new((1 + journal) as usize, inode_bitmap as usize),
+            data_bitmap: Bitmap::new(
+                (1 + journal + inode_bitmap + inode_area) as usize,
+                data_bitmap as usize,
+            ),
+            inode_area_start: (1 + journal + inode_bitmap) as u32,
+            data_area_start: (1 + journal + inode_bitmap + inode_area + data_bitmap) as u32,
+            data_area_blocks: data_area as u32,
+            inodes: (inode_bitmap * bits) as u32,
+        })
+    }
+
+    /// I结点在磁盘上的块编号和块中偏移
+    fn inode_pos(&self, inode_id: u32) -> (usize, usize) {
+        let inode_size = core::mem::size_of::<DiskInode>();
+        let inodes_per_block = (BLOCK_SIZE / inode_size) as u32;
+        (
+            (self.inode_area_start + inode_id / inodes_per_block) as usize,
+            (inode_id % inodes_per_block) as usize * inode_size,
+        )
+    }
+
+    fn in_data_area(&self, block_id: u32) -> bool {
+        block_id >= self.data_area_start && block_id - self.data_area_start < self.data_area_blocks
+    }
+}
+
+/// 块指针所在的位置
+#[derive(Clone, Copy)]
+enum Slot {
+    /// I结点的直接索引
+    Direct(u32, usize),
+    /// I结点的间接索引树的根，第二项为级别（从0开始）
+    Indirect(u32, usize),
+    /// 索引块中的一项
+    Entry(u32, usize),
+}
+
+/// I结点引用的块
+struct Blocks {
+    /// 按顺序排列的数据块
+    data: Vec<u32>,
+    /// 所有块（包括索引块）、指向它的位置以及是否为索引块
+    all: Vec<(u32, Slot, bool)>,
+}
+
+/// 从磁盘I结点中复制出的字段
+struct InodeInfo {
+    type_: DiskInodeType,
+    size: u64,
+    direct: [u32; INODE_DIRECT_COUNT],
+    indirect: [u32; 4],
+    nlink: u16,
+}
+
+/// 一次检查的状态
+struct Checker<'a> {
+    block_device: &'a Arc<dyn BlockDevice>,
+    geometry: &'a Geometry,
+    repair: bool,
+    problems: Vec<Problem>,
+    /// 可达的I结点及指向它的目录项数（目录为子目录数）
+    refs: BTreeMap<u32, (DiskInodeType, u32)>,
+    /// 被引用的块及引用它的I结点
+    owners: BTreeMap<u32, u32>,
+    /// 需要复制一份的重复引用的块及指向它的位置
+    clones: Vec<(u32, Slot)>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(block_device: &'a Arc<dyn BlockDevice>, geometry: &'a Geometry, repair: bool) -> Self {
+        Self {
+            block_device,
+            geometry,
+            repair,
+            problems: Vec::new(),
+            refs: BTreeMap::new(),
+            owners: BTreeMap::new(),
+            clones: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Result<Vec<Problem>, Problem> {
+        let root = self
+            .read_inode(0)
+            .filter(|info| info.type_ == DiskInodeType::Directory)
+            .ok_or(Problem::BadRoot("not a directory"))?;
+        let blocks = self.collect_blocks(0, &root).map_err(Problem::BadRoot)?;
+        if (root.size as usize) < 2 * DIRENT_SZ {
+            return Err(Problem::BadRoot("missing `.` or `..`"));
+        }
+        self.refs.insert(0, (DiskInodeType::Directory, 0));
+        self.claim_blocks(0, &blocks);
+        // 待检查的目录及其父目录
+        let mut dirs = alloc::vec![(0, 0, root, blocks)];
+        while let Some((dir, parent, info, blocks)) = dirs.pop() {
+            self.check_dir(dir, parent, &info, &blocks, &mut dirs);
+        }
+        self.check_links();
+        self.check_inode_bitmap();
+        self.check_data_bitmap();
+        self.clone_blocks();
+        Ok(self.problems)
+    }
+
+    /// 读取I结点，类型不合法时返回None
+    fn read_inode(&self, inode_id: u32) -> Option<InodeInfo> {
+        let (block_id, offset) = self.geometry.inode_pos(inode_id);
+        let block_cache = get_block_cache(block_id, Arc::clone(self.block_device));
+        let block_cache = block_cache.lock();
+        let type_ = block_cache.read(offset + DiskInode::TYPE_OFFSET, |raw: &u8| *raw);
+        DiskInodeType::from_raw(type_)?;
+        Some(
+            block_cache.read(offset, |disk_inode: &DiskInode| InodeInfo {
+                type_: disk_inode.type_(),
+                size: disk_inode.size,
+                direct: disk_inode.direct,
+                indirect: disk_inode.indirect(),
+                nlink: disk_inode.nlink,
+            }),
+        )
+    }
+
+    fn modify_inode(&self, inode_id: u32, f: impl FnOnce(&mut DiskInode)) {
+        let (block_id, offset) = self.geometry.inode_pos(inode_id);
+        get_block_cache(block_id, Arc::clone(self.block_device))
+            .lock()
+            .modify(offset, f);
+    }
+
+    fn read_indirect(&self, block_id: u32) -> IndirectBlock {
+        get_block_cache(block_id as usize, Arc::clone(self.block_device))
+            .lock()
+            .read(0, |indirect: &IndirectBlock| *indirect)
+    }
+
+    /// 检查I结点的块指针，返回它引用的块
+    fn collect_blocks(&self, inode_id: u32, info: &InodeInfo) -> Result<Blocks, &'static str> {
+        if info.size > MAX_FILE_SIZE {
+            return Err("size exceeds the maximum file size");
+        }
+        let mut blocks = Blocks {
+            data: Vec::new(),
+            all: Vec::new(),
+        };
+        let mut push = |block_id: u32, slot: Slot, index: bool| {
+            if !self.geometry.in_data_area(block_id) {
+                return Err("block pointer out of the data area");
+            }
+            if !index {
+                blocks.data.push(block_id);
+            }
+            blocks.all.push((block_id, slot, index));
+            Ok(())
+        };
+        let data_blocks = ((info.size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64) as usize;
+        for (i, &block_id) in info.direct.iter().enumerate().take(data_blocks) {
+            push(block_id, Slot::Direct(inode_id, i), false)?;
+        }
+        for (level, &root) in info.indirect.iter().enumerate() {
+            let count = DiskInode::tree_blocks(level, data_blocks);
+            if count > 0 {
+                push(root, Slot::Indirect(inode_id, level), true)?;
+                self.collect_tree(root, level as u32 + 1, count, &mut push)?;
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// 检查以root为根、深度为depth的索引树中的前count个数据块及其索引块
+    fn collect_tree(
+        &self,
+        root: u32,
+        depth: u32,
+        count: usize,
+        push: &mut impl FnMut(u32, Slot, bool) -> Result<(), &'static str>,
+    ) -> Result<(), &'static str> {
+        let indirect = self.read_indirect(root);
+        if depth == 1 {
+            for (i, &block_id) in indirect.iter().enumerate().take(count) {
+                push(block_id, Slot::Entry(root, i), false)?;
+            }
+            return Ok(());
+        }
+        let span = INODE_INDIRECT1_COUNT.pow(depth - 1);
+        for (i, &child) in indirect.iter().enumerate().take((count + span - 1) / span) {
+            push(child, Slot::Entry(root, i), true)?;
+            self.collect_tree(child, depth - 1, (count - i * span).min(span), push)?;
+        }
+        Ok(())
+    }
+
+    /// 登记I结点引用的块，检查重复引用
+    fn claim_blocks(&mut self, inode_id: u32, blocks: &Blocks) {
+        // 索引块被重复引用时先只复制索引块，其中的块在下一次检查时复制
+        let index_cloned = blocks
+            .all
+            .iter()
+            .any(|(block_id, _, index)| *index && self.owners.contains_key(block_id));
+        for &(block_id, slot, index) in blocks.all.iter() {
+            match self.owners.get(&block_id) {
+                Some(&first) => {
+                    self.problems.push(Problem::DoubleAllocated {
+                        block_id,
+                        first,
+                        second: inode_id,
+                    });
+                    if index || !index_cloned {
+                        self.clones.push((block_id, slot));
+                    }
+                }
+                None => {
+                    self.owners.insert(block_id, inode_id);
+                }
+            }
+        }
+    }
+
+    /// 检查目录中的目录项，将子目录加入dirs
+    fn check_dir(
+        &mut self,
+        dir: u32,
+        parent: u32,
+        info: &InodeInfo,
+        blocks: &Blocks,
+        dirs: &mut Vec<(u32, u32, InodeInfo, Blocks)>,
+    ) {
+        if info.size as usize % DIRENT_SZ != 0 {
+            self.problem(Problem::BadInode {
+                inode_id: dir,
+                reason: "directory size is not a multiple of the entry size",
+            });
+            if self.repair {
+                self.modify_inode(dir, |disk_inode| {
+                    disk_inode.size -= disk_inode.size % DIRENT_SZ as u64
+                });
+            }
+        }
+        let mut names: Vec<String> = Vec::new();
+        for slot in 0..info.size as usize / DIRENT_SZ {
+            let block_id = blocks.data[slot * DIRENT_SZ / BLOCK_SIZE] as usize;
+            let offset = slot * DIRENT_SZ % BLOCK_SIZE;
+            let dirent = get_block_cache(block_id, Arc::clone(self.block_device))
+                .lock()
+                .read(offset, |dirent: &DirEntry| {
+                    let mut copy = DirEntry::empty();
+                    copy.as_bytes_mut().copy_from_slice(dirent.as_bytes());
+                    copy
+                });
+            let block_device = self.block_device;
+            let set_dirent = |dirent: DirEntry| {
+                get_block_cache(block_id, Arc::clone(block_device))
+                    .lock()
+                    .modify(offset, |old: &mut DirEntry| *old = dirent);
+            };
+            // 前两项必须是`.`和`..`
+            if slot < 2 {
+                let (name, inode_id) = if slot == 0 {
+                    (".", dir)
+                } else {
+                    ("..", parent)
+                };
+                if dirent.checked_name() != Some(name) || dirent.inode_number() != inode_id {
+                    self.problem(Problem::BadDirent {
+                        dir,
+                        slot,
+                        reason: "wrong `.` or `..` entry",
+                    });
+                    if self.repair {
+                        set_dirent(DirEntry::new(name, inode_id));
+                    }
+                }
+                continue;
+            }
+            if dirent.is_empty() {
+                continue;
+            }
+            // 损坏的目录项被清除
+            let problem = match self.check_dirent(&dirent, &names) {
+                Ok(name) => {
+                    names.push(String::from(name));
+                    match self.visit(dir, slot, dirent.inode_number(), dirs) {
+                        Ok(()) => continue,
+                        Err(problem) => problem,
+                    }
+                }
+                Err(reason) => Problem::BadDirent { dir, slot, reason },
+            };
+            self.problem(problem);
+            if self.repair {
+                set_dirent(DirEntry::empty());
+            }
+        }
+    }
+
+    /// 检查目录项的名字和I结点编号，返回名字
+    fn check_dirent<'d>(
+        &self,
+        dirent: &'d DirEntry,
+        names: &[String],
+    ) -> Result<&'d str, &'static str> {
+        let name = dirent.checked_name().ok_or("invalid name")?;
+        if name == "." || name == ".." || name.contains('/') {
+            return Err("invalid name");
+        }
+        if names.iter().any(|other| other == name) {
+            return Err("duplicate name");
+        }
+        let inode_id = dirent.inode_number();
+        if inode_id >= self.geometry.inodes {
+            return Err("inode number out of range");
+        }
+        if let Some((DiskInodeType::Directory, _)) = self.refs.get(&inode_id) {
+            return Err("second link to a directory");
+        }
+        Ok(name)
+    }
+
+    /// 经由目录dir中的第slot项到达I结点
+    ///
+    /// I结点损坏时返回错误，之后目录项被清除
+    fn visit(
+        &mut self,
+        dir: u32,
+        slot: usize,
+        inode_id: u32,
+        dirs: &mut Vec<(u32, u32, InodeInfo, Blocks)>,
+    ) -> Result<(), Problem> {
+        if let Some((_, refs)) = self.refs.get_mut(&inode_id) {
+            *refs += 1;
+            return Ok(());
+        }
+        let bad = |reason| Problem::BadInode { inode_id, reason };
+        let info = self.read_inode(inode_id).ok_or(bad("invalid type"))?;
+        // 未分配且链接数为0的I结点说明目录项指向已删除的文件
+        if info.nlink == 0
+            && !self
+                .geometry
+                .inode_bitmap
+                .is_allocated(self.block_device, inode_id as usize)
+        {
+            return Err(Problem::BadDirent {
+                dir,
+                slot,
+                reason: "entry refers to a free inode",
+            });
+        }
+        let blocks = match self.collect_blocks(inode_id, &info) {
+            Ok(blocks) => blocks,
+            // 损坏的目录从父目录中断开，其中的I结点成为孤立的
+            Err(reason) if info.type_ == DiskInodeType::Directory => return Err(bad(reason)),
+            // 损坏的文件被清空
+            Err(reason) => {
+                self.problem(bad(reason));
+                if self.repair {
+                    self.modify_inode(inode_id, |disk_inode| {
+                        disk_inode.size = 0;
+                        disk_inode.direct.iter_mut().for_each(|v| *v = 0);
+                        disk_inode.indirect_mut().into_iter().for_each(|v| *v = 0);
+                    });
+                }
+                Blocks {
+                    data: Vec::new(),
+                    all: Vec::new(),
+                }
+            }
+        };
+        if info.type_ == DiskInodeType::Directory {
+            if (info.size as usize) < 2 * DIRENT_SZ {
+                return Err(bad("missing `.` or `..`"));
+            }
+            self.refs.get_mut(&dir).unwrap().1 += 1;
+            self.refs.insert(inode_id, (DiskInodeType::Directory, 0));
+            self.claim_blocks(inode_id, &blocks);
+            dirs.push((inode_id, dir, info, blocks));
+        } else {
+            self.refs.insert(inode_id, (info.type_, 1));
+            self.claim_blocks(inode_id, &blocks);
+        }
+        Ok(())
+    }
+
+    /// 检查链接数：文件为指向它的目录项数，目录为2加子目录数
+    fn check_links(&mut self) {
+        let refs: Vec<_> = self.refs.iter().map(|(id, refs)| (*id, *refs)).collect();
+        for (inode_id, (type_, refs)) in refs {
+            let expected = match type_ {
+                DiskInodeType::File | DiskInodeType::Fifo => refs,
+                DiskInodeType::Directory => 2 + refs,
+            };
+            let expected = expected.min(u16::MAX as u32) as u16;
+            let nlink = self.read_inode(inode_id).unwrap().nlink;
+            if nlink != expected {
+                self.problem(Problem::WrongLinkCount {
+                    inode_id,
+                    nlink,
+                    expected,
+                });
+                if self.repair {
+                    self.modify_inode(inode_id, |disk_inode| disk_inode.nlink = expected);
+                }
+            }
+        }
+    }
+
+    /// 检查I结点位图与可达的I结点是否一致
+    fn check_inode_bitmap(&mut self) {
+        let bitmap = &self.geometry.inode_bitmap;
+        for inode_id in 0..self.geometry.inodes {
+            let allocated = bitmap.is_allocated(self.block_device, inode_id as usize);
+            let reachable = self.refs.contains_key(&inode_id);
+            if allocated && !reachable {
+                self.problem(Problem::OrphanInode(inode_id));
+                if self.repair {
+                    // 释放I结点，它引用的块未被登记，作为泄漏的块回收
+                    bitmap.dealloc(self.block_device, inode_id as usize);
+                    let (block_id, offset) = self.geometry.inode_pos(inode_id);
+                    get_block_cache(block_id, Arc::clone(self.block_device))
+                        .lock()
+                        .modify(
+                            offset,
+                            |raw: &mut [u8; core::mem::size_of::<DiskInode>()]| raw.fill(0),
+                        );
+                }
+            } else if !allocated && reachable {
+                self.problem(Problem::InodeNotAllocated(inode_id));
+                if self.repair {
+                    bitmap.set_allocated(self.block_device, inode_id as usize);
+                }
+            }
+        }
+    }
+
+    /// 检查数据位图与被引用的块是否一致
+    fn check_data_bitmap(&mut self) {
+        let bitmap = &self.geometry.data_bitmap;
+        for bit in 0..bitmap.maximum() {
+            let block_id = self.geometry.data_area_start + bit as u32;
+            let allocated = bitmap.is_allocated(self.block_device, bit);
+            let used = self.owners.contains_key(&block_id);
+            if allocated && !used {
+                self.problem(Problem::LeakedBlock(block_id));
+                if self.repair {
+                    // 空闲块保持为零
+                    if self.geometry.in_data_area(block_id) {
+                        get_block_cache(block_id as usize, Arc::clone(self.block_device))
+                            .lock()
+                            .modify(0, |data_block: &mut DataBlock| data_block.fill(0));
+                    }
+                    bitmap.dealloc(self.block_device, bit);
+                }
+            } else if !allocated && used {
+                self.problem(Problem::BlockNotAllocated(block_id));
+                if self.repair {
+                    bitmap.set_allocated(self.block_device, bit);
+                }
+            }
+        }
+    }
+
+    /// 为重复引用的块分配新块并复制内容，在位图修复后进行
+    fn clone_blocks(&mut self) {
+        if !self.repair {
+            return;
+        }
+        for (block_id, slot) in core::mem::take(&mut self.clones) {
+            let new_block_id = match self.geometry.data_bitmap.alloc(self.block_device) {
+                Some(bit) if bit < self.geometry.data_area_blocks as usize => {
+                    self.geometry.data_area_start + bit as u32
+                }
+                // 没有空闲块时保留重复引用
+                _ => return,
+            };
+            let data = get_block_cache(block_id as usize, Arc::clone(self.block_device))
+                .lock()
+                .read(0, |data_block: &DataBlock| *data_block);
+            get_block_cache(new_block_id as usize, Arc::clone(self.block_device))
+                .lock()
+                .modify(0, |data_block: &mut DataBlock| *data_block = data);
+            match slot {
+                Slot::Direct(inode_id, i) => {
+                    self.modify_inode(inode_id, |disk_inode| disk_inode.direct[i] = new_block_id)
+                }
+                Slot::Indirect(inode_id, level) => self.modify_inode(inode_id, |disk_inode| {
+                    *disk_inode.indirect_mut()[level] = new_block_id
+                }),
+                Slot::Entry(indirect, i) => {
+                    get_block_cache(indirect as usize, Arc::clone(self.block_device))
+                        .lock()
+                        .modify(0, |indirect: &mut IndirectBlock| indirect[i] = new_block_id);
+                }
+            }
+        }
+    }
+
+    fn problem(&mut self, problem: Problem) {
+        self.problems.push(problem);
+    }
+}
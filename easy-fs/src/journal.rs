@@ -0,0 +1,318 @@
+//! 元数据日志
+//!
+//! 日志区紧跟超级块，第一个块为日志头，其后为被修改的元数据块的副本。
+//! 每个修改文件系统的操作是一个事务：操作中被修改的块暂时不能写回，
+//! 操作结束时先将副本和日志头写入日志区（提交），之后再写回原位置，
+//! 打开文件系统时若日志头合法则重放日志。
+//!
+//! 普通文件的数据不记入日志；被回收的数据块在提交后立即写回（保证空闲块为零）
+
+use super::{get_block_cache, sync_block_cache, BlockDevice, BLOCK_SIZE};
+use alloc::{sync::Arc, vec::Vec};
+use lazy_static::*;
+use spin::Mutex;
+
+const JOURNAL_MAGIC: u32 = 0x4a524e4c;
+/// 日志头中记录的最大块数
+const JOURNAL_TARGETS: usize = (BLOCK_SIZE - 12) / 4;
+/// 默认的日志区大小（块数），包括日志头
+pub const JOURNAL_BLOCKS: u32 = 1 + JOURNAL_TARGETS as u32;
+
+type DataBlock = [u8; BLOCK_SIZE];
+
+/// 日志头
+#[repr(C)]
+struct JournalHeader {
+    magic: u32,
+    /// 日志中的块数
+    count: u32,
+    /// 日志头和所有副本的校验和，计算时此字段为0
+    checksum: u32,
+    /// 副本对应的块号
+    targets: [u32; JOURNAL_TARGETS],
+}
+
+/// 被修改的块如何记录
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    /// 记入日志
+    Metadata,
+    /// 不记入日志，在提交后写回
+    Ordered,
+    /// 不记录
+    Untracked,
+}
+
+/// 日志状态
+struct Journal {
+    /// 块设备，文件系统打开前为None
+    block_device: Option<Arc<dyn BlockDevice>>,
+    /// 日志头的块号
+    start: usize,
+    /// 日志中最多的块数，为0时不记日志
+    capacity: usize,
+    /// 事务的嵌套层数
+    depth: usize,
+    mode: Mode,
+    /// 当前事务中需要记入日志的块
+    metadata: Vec<usize>,
+    /// 当前事务中需要在提交后写回的块
+    ordered: Vec<usize>,
+    /// 已提交但可能尚未写回原位置的块
+    checkpoint: Vec<usize>,
+}
+
+lazy_static! {
+    static ref JOURNAL: Mutex<Journal> = Mutex::new(Journal {
+        block_device: None,
+        start: 0,
+        capacity: 0,
+        depth: 0,
+        mode: Mode::Metadata,
+        metadata: Vec::new(),
+        ordered: Vec::new(),
+        checkpoint: Vec::new(),
+    });
+}
+
+/// FNV-1a校验和
+fn checksum(hash: u32, bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .fold(hash, |hash, b| (hash ^ *b as u32).wrapping_mul(0x01000193))
+}
+
+/// 将块视为字节数组
+fn as_bytes<T>(value: &T) -> &[u8] {
+    unsafe {
+        core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>())
+    }
+}
+
+/// 使用以start为日志头、共blocks块的日志区
+///
+/// blocks为0时（没有日志区的旧文件系统）不记日志
+pub fn init(block_device: Arc<dyn BlockDevice>, start: usize, blocks: usize) {
+    let mut journal = JOURNAL.lock();
+    journal.block_device = Some(block_device);
+    journal.start = start;
+    journal.capacity = blocks.saturating_sub(1).min(JOURNAL_TARGETS);
+    journal.depth = 0;
+    journal.mode = Mode::Metadata;
+    journal.metadata.clear();
+    journal.ordered.clear();
+    journal.checkpoint.clear();
+}
+
+/// 读取日志区中已提交的事务，返回副本对应的块号和副本
+///
+/// 日志头或副本不完整（校验和不符）时说明事务未提交，返回None
+fn load(
+    block_device: &Arc<dyn BlockDevice>,
+    start: usize,
+    blocks: usize,
+) -> Option<(Vec<u32>, Vec<DataBlock>)> {
+    if blocks == 0 {
+        return None;
+    }
+    let mut header_block: DataBlock = [0; BLOCK_SIZE];
+    block_device.read_block(start, &mut header_block);
+    let header = unsafe { &mut *(header_block.as_mut_ptr() as *mut JournalHeader) };
+    let count = header.count as usize;
+    if header.magic != JOURNAL_MAGIC || count > JOURNAL_TARGETS || count >= blocks {
+        return None;
+    }
+    let expected = header.checksum;
+    header.checksum = 0;
+    let mut hash = checksum(0x811c9dc5, as_bytes(header));
+    let mut copies = Vec::with_capacity(count);
+    for i in 0..count {
+        let mut copy: DataBlock = [0; BLOCK_SIZE];
+        block_device.read_block(start + 1 + i, &mut copy);
+        hash = checksum(hash, &copy);
+        copies.push(copy);
+    }
+    (hash == expected).then(|| (header.targets[..count].to_vec(), copies))
+}
+
+/// 日志区中是否有已提交但尚未写回原位置的事务
+///
+/// 已写回的事务仍留在日志区中，此时原位置的内容与副本相同
+pub fn pending(block_device: &Arc<dyn BlockDevice>, start: usize, blocks: usize) -> bool {
+    load(block_device, start, blocks).map_or(false, |(targets, copies)| {
+        targets.iter().zip(copies.iter()).any(|(target, copy)| {
+            get_block_cache(*target as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |data_block: &DataBlock| data_block != copy)
+        })
+    })
+}
+
+/// 重放日志区中已提交的事务，并清空日志头
+///
+/// 未提交的事务直接丢弃，返回是否重放了事务
+pub fn replay(block_device: &Arc<dyn BlockDevice>, start: usize, blocks: usize) -> bool {
+    if blocks == 0 {
+        return false;
+    }
+    let transaction = load(block_device, start, blocks);
+    if let Some((targets, copies)) = transaction.as_ref() {
+        // 经由块缓存写回，避免缓存中留有旧的内容
+        for (target, copy) in targets.iter().zip(copies.iter()) {
+            let block_cache = get_block_cache(*target as usize, Arc::clone(block_device));
+            let mut block_cache = block_cache.lock();
+            block_cache.modify(0, |data_block: &mut DataBlock| {
+                data_block.copy_from_slice(copy)
+            });
+            block_cache.sync();
+        }
+    }
+    block_device.write_block(start, &[0; BLOCK_SIZE]);
+    transaction.is_some()
+}
+
+/// 块缓存被修改时调用，返回在事务提交前是否不能写回此块
+pub fn record(block_id: usize) -> bool {
+    let mut journal = JOURNAL.lock();
+    if journal.depth == 0 {
+        return false;
+    }
+    let list = match journal.mode {
+        Mode::Metadata => &mut journal.metadata,
+        Mode::Ordered => &mut journal.ordered,
+        Mode::Untracked => return false,
+    };
+    if !list.contains(&block_id) {
+        list.push(block_id);
+    }
+    true
+}
+
+/// 以mode记录f中被修改的块
+fn with_mode<V>(mode: Mode, f: impl FnOnce() -> V) -> V {
+    let old_mode = core::mem::replace(&mut JOURNAL.lock().mode, mode);
+    let ret = f();
+    JOURNAL.lock().mode = old_mode;
+    ret
+}
+
+/// f中被修改的块不记入日志，用于写普通文件的数据
+pub fn untracked<V>(f: impl FnOnce() -> V) -> V {
+    with_mode(Mode::Untracked, f)
+}
+
+/// f中被修改的块不记入日志，在事务提交后写回，用于清零数据块
+pub fn ordered<V>(f: impl FnOnce() -> V) -> V {
+    with_mode(Mode::Ordered, f)
+}
+
+/// 事务，在修改文件系统前创建，释放时提交
+///
+/// 必须在持有文件系统的锁时创建和释放，可以嵌套，只有最外层的事务会提交
+pub struct Transaction;
+
+impl Transaction {
+    /// 开始一个事务
+    ///
+    /// 日志区只保存一个事务，因此先将上一个事务的块写回原位置
+    pub fn begin() -> Self {
+        let checkpoint = {
+            let mut journal = JOURNAL.lock();
+            journal.depth += 1;
+            if journal.depth > 1 {
+                return Transaction;
+            }
+            core::mem::take(&mut journal.checkpoint)
+        };
+        for block_id in checkpoint {
+            sync_block_cache(block_id, false);
+        }
+        Transaction
+    }
+}
+
+impl Drop for Transaction {
+    /// 提交事务
+    fn drop(&mut self) {
+        let (block_device, start, capacity, metadata, ordered) = {
+            let mut journal = JOURNAL.lock();
+            journal.depth -= 1;
+            if journal.depth > 0 {
+                return;
+            }
+            (
+                journal.block_device.clone(),
+                journal.start,
+                journal.capacity,
+                core::mem::take(&mut journal.metadata),
+                core::mem::take(&mut journal.ordered),
+            )
+        };
+        if !metadata.is_empty() {
+            commit(&block_device.unwrap(), start, capacity, metadata);
+        }
+        // 提交后再写回被清零的数据块，提交前崩溃时旧的文件仍引用这些块
+        for &block_id in ordered.iter() {
+            sync_block_cache(block_id, true);
+        }
+    }
+}
+
+/// 将metadata中的块写入日志区并提交
+fn commit(
+    block_device: &Arc<dyn BlockDevice>,
+    start: usize,
+    capacity: usize,
+    metadata: Vec<usize>,
+) {
+    if capacity == 0 {
+        // 没有日志区，按普通的写回缓存处理
+        metadata
+            .iter()
+            .for_each(|&block_id| unpin(block_id, block_device));
+        return;
+    }
+    if metadata.len() > capacity {
+        // 日志区放不下，作废日志后直接写回，此时不保证原子性
+        block_device.write_block(start, &[0; BLOCK_SIZE]);
+        for &block_id in metadata.iter() {
+            sync_block_cache(block_id, true);
+        }
+        return;
+    }
+    // 写入副本
+    let mut header = JournalHeader {
+        magic: JOURNAL_MAGIC,
+        count: metadata.len() as u32,
+        checksum: 0,
+        targets: [0; JOURNAL_TARGETS],
+    };
+    let mut copies = Vec::with_capacity(metadata.len());
+    for (i, &block_id) in metadata.iter().enumerate() {
+        header.targets[i] = block_id as u32;
+        let copy = get_block_cache(block_id, Arc::clone(block_device))
+            .lock()
+            .read(0, |data_block: &DataBlock| *data_block);
+        block_device.write_block(start + 1 + i, &copy);
+        copies.push(copy);
+    }
+    // 写入日志头，写入完成即提交
+    let mut hash = checksum(0x811c9dc5, as_bytes(&header));
+    for copy in copies.iter() {
+        hash = checksum(hash, copy);
+    }
+    header.checksum = hash;
+    block_device.write_block(start, as_bytes(&header));
+    // 提交后的块可以随时写回
+    for &block_id in metadata.iter() {
+        unpin(block_id, block_device);
+    }
+    JOURNAL.lock().checkpoint = metadata;
+}
+
+/// 允许写回已提交的块
+fn unpin(block_id: usize, block_device: &Arc<dyn BlockDevice>) {
+    get_block_cache(block_id, Arc::clone(block_device))
+        .lock()
+        .unpin();
+}
@@ -2,12 +2,33 @@ use super::{get_block_cache, BlockDevice, BLOCK_SIZE};
 use alloc::{sync::Arc, vec::Vec};
 
 const EFS_MAGIC: u32 = 0x3b800001;
-const NAME_LENGTH_LIMIT: usize = 27;
-const INODE_DIRECT_COUNT: usize = 28;
-const INODE_INDIRECT1_COUNT: usize = BLOCK_SIZE / 4;
+/// 磁盘格式的版本号，I结点布局改变时增加
+///
+/// 没有版本号的旧格式读出为0
+pub const EFS_VERSION: u32 = 2;
+/// 文件名的最大长度
+pub const NAME_LENGTH_LIMIT: usize = 27;
+/// I结点的最大链接数，目录的链接数随子目录增加
+pub const LINK_MAX: u16 = u16::MAX;
+pub const INODE_DIRECT_COUNT: usize = 23;
+pub const INODE_INDIRECT1_COUNT: usize = BLOCK_SIZE / 4;
 const INODE_INDIRECT2_COUNT: usize = INODE_INDIRECT1_COUNT * INODE_INDIRECT1_COUNT;
+const INODE_INDIRECT3_COUNT: usize = INODE_INDIRECT2_COUNT * INODE_INDIRECT1_COUNT;
+const INODE_INDIRECT4_COUNT: usize = INODE_INDIRECT3_COUNT * INODE_INDIRECT1_COUNT;
 const DIRECT_BOUND: usize = INODE_DIRECT_COUNT;
-const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
+pub const INDIRECT1_BOUND: usize = DIRECT_BOUND + INODE_INDIRECT1_COUNT;
+const INDIRECT2_BOUND: usize = INDIRECT1_BOUND + INODE_INDIRECT2_COUNT;
+const INDIRECT3_BOUND: usize = INDIRECT2_BOUND + INODE_INDIRECT3_COUNT;
+const INDIRECT4_BOUND: usize = INDIRECT3_BOUND + INODE_INDIRECT4_COUNT;
+/// 各级间接索引树中第一个数据块在I结点中的索引编号
+pub const INDIRECT_BASES: [usize; 4] = [
+    DIRECT_BOUND,
+    INDIRECT1_BOUND,
+    INDIRECT2_BOUND,
+    INDIRECT3_BOUND,
+];
+/// 单个文件的最大大小（约128GiB）
+pub const MAX_FILE_SIZE: u64 = INDIRECT4_BOUND as u64 * BLOCK_SIZE as u64;
 
 /// 超级块
 #[repr(C)]
@@ -18,6 +39,10 @@ pub struct SuperBlock {
     pub inode_area_blocks: u32,
     pub data_bitmap_blocks: u32,
     pub data_area_blocks: u32,
+    /// 日志区的块数，紧跟超级块，为0表示没有日志区
+    pub journal_blocks: u32,
+    /// 磁盘格式的版本号
+    pub version: u32,
 }
 
 impl SuperBlock {
@@ -28,6 +53,7 @@ impl SuperBlock {
         inode_area_blocks: u32,
         data_bitmap_blocks: u32,
         data_area_blocks: u32,
+        journal_blocks: u32,
     ) {
         *self = Self {
             magic: EFS_MAGIC,
@@ -36,76 +62,246 @@ impl SuperBlock {
             inode_area_blocks,
             data_bitmap_blocks,
             data_area_blocks,
+            journal_blocks,
+            version: EFS_VERSION,
         }
     }
 
     pub fn is_valid(&self) -> bool {
         self.magic == EFS_MAGIC
     }
+
+    /// 磁盘格式是否为当前版本
+    pub fn is_supported(&self) -> bool {
+        self.version == EFS_VERSION
+    }
 }
 
-#[derive(PartialEq)]
+/// 磁盘上I结点的类型
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(u8)]
 pub enum DiskInodeType {
     File,
     Directory,
+    /// 命名管道，不占用数据块
+    Fifo,
 }
 
-type IndirectBlock = [u32; BLOCK_SIZE / 4];
+impl DiskInodeType {
+    /// 由磁盘上的原始值得到类型，用于检查可能损坏的I结点
+    pub fn from_raw(raw: u8) -> Option<Self> {
+        match raw {
+            0 => Some(Self::File),
+            1 => Some(Self::Directory),
+            2 => Some(Self::Fifo),
+            _ => None,
+        }
+    }
+}
+
+pub type IndirectBlock = [u32; BLOCK_SIZE / 4];
 type DataBlock = [u8; BLOCK_SIZE];
 
 /// 磁盘上的Inode类型
 #[repr(C)]
 pub struct DiskInode {
-    pub size: u32,
+    pub size: u64,
     pub direct: [u32; INODE_DIRECT_COUNT],
     pub indirect1: u32,
     pub indirect2: u32,
+    pub indirect3: u32,
+    pub indirect4: u32,
     type_: DiskInodeType,
+    /// 链接数，即指向该I结点的目录项个数
+    pub nlink: u16,
+    /// 创建时间，自1970年以来的秒数
+    pub ctime: u32,
+    /// 最后修改时间，自1970年以来的秒数
+    pub mtime: u32,
+}
+
+/// 深度为depth的索引树中每个子树索引的数据块数
+fn span(depth: u32) -> usize {
+    INODE_INDIRECT1_COUNT.pow(depth - 1)
+}
+
+/// 在以root为根、深度为depth的索引树中查找第index个数据块的块编号
+fn tree_get(
+    mut root: u32,
+    depth: u32,
+    mut index: usize,
+    block_device: &Arc<dyn BlockDevice>,
+) -> u32 {
+    for depth in (1..=depth).rev() {
+        let span = span(depth);
+        root = get_block_cache(root as usize, Arc::clone(block_device))
+            .lock()
+            .read(0, |indirect: &IndirectBlock| indirect[index / span]);
+        index %= span;
+    }
+    root
+}
+
+/// 在索引树中为第start到end个数据块建立索引，用到的块从new_blocks中依次取出
+///
+/// start为0时树根也是新分配的
+fn tree_fill(
+    root: &mut u32,
+    depth: u32,
+    start: usize,
+    end: usize,
+    new_blocks: &mut impl Iterator<Item = u32>,
+    block_device: &Arc<dyn BlockDevice>,
+) {
+    if start == 0 {
+        *root = new_blocks.next().unwrap();
+    }
+    if depth == 0 {
+        return;
+    }
+    let span = span(depth);
+    get_block_cache(*root as usize, Arc::clone(block_device))
+        .lock()
+        .modify(0, |indirect: &mut IndirectBlock| {
+            let children = indirect.iter_mut().enumerate();
+            for (i, child) in children.take((end + span - 1) / span).skip(start / span) {
+                let base = i * span;
+                tree_fill(
+                    child,
+                    depth - 1,
+                    start.max(base) - base,
+                    end.min(base + span) - base,
+                    new_blocks,
+                    block_device,
+                );
+            }
+        });
+}
+
+/// 将索引树索引的数据块从len个缩减到keep个，不再使用的块（包括索引块）加入v
+///
+/// keep为0时树根也被回收
+fn tree_shrink(
+    root: u32,
+    depth: u32,
+    keep: usize,
+    len: usize,
+    v: &mut Vec<u32>,
+    block_device: &Arc<dyn BlockDevice>,
+) {
+    if depth > 0 {
+        let span = span(depth);
+        get_block_cache(root as usize, Arc::clone(block_device))
+            .lock()
+            .read(0, |indirect: &IndirectBlock| {
+                let children = indirect.iter().enumerate();
+                for (i, &child) in children.take((len + span - 1) / span).skip(keep / span) {
+                    let base = i * span;
+                    tree_shrink(
+                        child,
+                        depth - 1,
+                        keep.max(base) - base,
+                        len.min(base + span) - base,
+                        v,
+                        block_device,
+                    );
+                }
+            });
+    }
+    if keep == 0 {
+        v.push(root);
+    }
 }
 
 impl DiskInode {
-    pub fn initialize(&mut self, type_: DiskInodeType) {
+    /// type_字段在I结点中的偏移
+    pub const TYPE_OFFSET: usize = core::mem::offset_of!(DiskInode, type_);
+
+    pub fn initialize(&mut self, type_: DiskInodeType, now: u32) {
         self.size = 0;
         self.direct.iter_mut().for_each(|v| *v = 0);
         self.indirect1 = 0;
         self.indirect2 = 0;
+        self.indirect3 = 0;
+        self.indirect4 = 0;
         self.type_ = type_;
+        // 目录还被自身的`.`指向
+        self.nlink = match type_ {
+            DiskInodeType::File | DiskInodeType::Fifo => 1,
+            DiskInodeType::Directory => 2,
+        };
+        self.ctime = now;
+        self.mtime = now;
+    }
+
+    pub fn type_(&self) -> DiskInodeType {
+        self.type_
     }
 
     pub fn is_dir(&self) -> bool {
         self.type_ == DiskInodeType::Directory
     }
 
-    #[allow(dead_code)]
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
 
+    pub fn is_fifo(&self) -> bool {
+        self.type_ == DiskInodeType::Fifo
+    }
+
+    /// 一级到四级间接索引树的根
+    pub fn indirect(&self) -> [u32; 4] {
+        [
+            self.indirect1,
+            self.indirect2,
+            self.indirect3,
+            self.indirect4,
+        ]
+    }
+
+    /// 可修改的各级间接索引树的根
+    pub fn indirect_mut(&mut self) -> [&mut u32; 4] {
+        [
+            &mut self.indirect1,
+            &mut self.indirect2,
+            &mut self.indirect3,
+            &mut self.indirect4,
+        ]
+    }
+
+    /// 共data_blocks个数据块时，第level级（从0开始）间接索引树索引的数据块数
+    pub fn tree_blocks(level: usize, data_blocks: usize) -> usize {
+        data_blocks
+            .saturating_sub(INDIRECT_BASES[level])
+            .min(INODE_INDIRECT1_COUNT.pow(level as u32 + 1))
+    }
+
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
     }
 
-    fn _data_blocks(size: u32) -> u32 {
-        (size + BLOCK_SIZE as u32 - 1) / BLOCK_SIZE as u32
+    fn _data_blocks(size: u64) -> u32 {
+        ((size + BLOCK_SIZE as u64 - 1) / BLOCK_SIZE as u64) as u32
     }
 
     /// 计算当前I结点需要的总块数（包括间接索引）
-    pub fn total_blocks(size: u32) -> u32 {
+    pub fn total_blocks(size: u64) -> u32 {
         let data_blocks = Self::_data_blocks(size) as usize;
-        let mut total = data_blocks as usize;
-        if data_blocks > INODE_DIRECT_COUNT {
-            total += 1;
-        }
-        if data_blocks > INDIRECT1_BOUND {
-            total += 1;
-            total +=
-                (data_blocks - INDIRECT1_BOUND + INODE_INDIRECT1_COUNT - 1) / INODE_INDIRECT1_COUNT;
+        let mut total = data_blocks;
+        for level in 0..INDIRECT_BASES.len() {
+            let n = Self::tree_blocks(level, data_blocks);
+            // 索引树每一层的索引块数，第height层的每个索引块索引128^height个数据块
+            for height in 1..=level as u32 + 1 {
+                let span = INODE_INDIRECT1_COUNT.pow(height);
+                total += (n + span - 1) / span;
+            }
         }
         total as u32
     }
 
     /// 给定新的大小，计算扩容这个I结点需要新增的块数
-    pub fn blocks_num_needed(&self, new_size: u32) -> u32 {
+    pub fn blocks_num_needed(&self, new_size: u64) -> u32 {
         assert!(new_size >= self.size);
         Self::total_blocks(new_size) - Self::total_blocks(self.size)
     }
@@ -113,27 +309,19 @@ impl DiskInode {
     /// 给定块在此I结点中的索引编号，返回它磁盘中的块编号
     pub fn get_block_id(&self, inner_id: u32, block_device: &Arc<dyn BlockDevice>) -> u32 {
         let inner_id = inner_id as usize;
-        if inner_id < INODE_DIRECT_COUNT {
-            self.direct[inner_id]
-        } else if inner_id < INDIRECT1_BOUND {
-            get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-                .lock()
-                .read(0, |indirect_block: &IndirectBlock| {
-                    indirect_block[inner_id - INODE_DIRECT_COUNT]
-                })
-        } else {
-            let last = inner_id - INDIRECT1_BOUND;
-            let indirect1 = get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-                .lock()
-                .read(0, |indirect2: &IndirectBlock| {
-                    indirect2[last / INODE_INDIRECT1_COUNT]
-                });
-            get_block_cache(indirect1 as usize, Arc::clone(block_device))
-                .lock()
-                .read(0, |indirect1: &IndirectBlock| {
-                    indirect1[last % INODE_INDIRECT1_COUNT]
-                })
+        if inner_id < DIRECT_BOUND {
+            return self.direct[inner_id];
         }
+        let level = INDIRECT_BASES
+            .iter()
+            .rposition(|base| inner_id >= *base)
+            .unwrap();
+        tree_get(
+            self.indirect()[level],
+            level as u32 + 1,
+            inner_id - INDIRECT_BASES[level],
+            block_device,
+        )
     }
 
     /// 扩容当前磁盘I结点
@@ -141,155 +329,67 @@ impl DiskInode {
     /// 给定块设备编号队列，为他们建立索引
     pub fn increase_size(
         &mut self,
-        new_size: u32,
+        new_size: u64,
         new_blocks: Vec<u32>,
         block_device: &Arc<dyn BlockDevice>,
     ) {
-        let mut current_blocks = self.data_blocks();
+        let old_blocks = self.data_blocks() as usize;
         self.size = new_size;
-        let mut total_blocks = self.data_blocks();
+        let total_blocks = self.data_blocks() as usize;
         let mut new_blocks = new_blocks.into_iter();
-
-        // fill direct
-        while current_blocks < total_blocks.min(INODE_DIRECT_COUNT as u32) {
-            self.direct[current_blocks as usize] = new_blocks.next().unwrap();
-            current_blocks += 1;
+        for block_id in
+            &mut self.direct[old_blocks.min(DIRECT_BOUND)..total_blocks.min(DIRECT_BOUND)]
+        {
+            *block_id = new_blocks.next().unwrap();
         }
-
-        // alloc indirect1
-        if total_blocks > INODE_DIRECT_COUNT as u32 {
-            if current_blocks == INODE_DIRECT_COUNT as u32 {
-                self.indirect1 = new_blocks.next().unwrap();
+        for (level, root) in self.indirect_mut().into_iter().enumerate() {
+            let start = Self::tree_blocks(level, old_blocks);
+            let end = Self::tree_blocks(level, total_blocks);
+            if start < end {
+                let depth = level as u32 + 1;
+                tree_fill(root, depth, start, end, &mut new_blocks, block_device);
             }
-            current_blocks -= INODE_DIRECT_COUNT as u32;
-            total_blocks -= INODE_DIRECT_COUNT as u32;
-        } else {
-            return;
         }
+    }
 
-        // fill indirect1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
-                while current_blocks < total_blocks.min(INODE_INDIRECT1_COUNT as u32) {
-                    indirect1[current_blocks as usize] = new_blocks.next().unwrap();
-                    current_blocks += 1;
+    /// 缩小当前磁盘I结点到new_size，返回需要回收的数据块和不再使用的索引块
+    ///
+    /// 与clear_size相同，这里并未去数据块中操作
+    pub fn decrease_size(
+        &mut self,
+        new_size: u64,
+        block_device: &Arc<dyn BlockDevice>,
+    ) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        let old_blocks = self.data_blocks() as usize;
+        let new_blocks = Self::_data_blocks(new_size) as usize;
+        // 被截断的直接索引的数据块
+        let mut v = Vec::new();
+        for block_id in &mut self.direct[new_blocks.min(DIRECT_BOUND)..old_blocks.min(DIRECT_BOUND)]
+        {
+            v.push(*block_id);
+            *block_id = 0;
+        }
+        // 间接索引树中被截断的数据块和不再使用的索引块
+        for (level, root) in self.indirect_mut().into_iter().enumerate() {
+            let keep = Self::tree_blocks(level, new_blocks);
+            let len = Self::tree_blocks(level, old_blocks);
+            if keep < len {
+                tree_shrink(*root, level as u32 + 1, keep, len, &mut v, block_device);
+                if keep == 0 {
+                    *root = 0;
                 }
-            });
-
-        // alloc indirect2
-        if total_blocks > INODE_INDIRECT1_COUNT as u32 {
-            if current_blocks == INODE_INDIRECT1_COUNT as u32 {
-                self.indirect2 = new_blocks.next().unwrap();
             }
-            current_blocks -= INODE_INDIRECT1_COUNT as u32;
-            total_blocks -= INODE_INDIRECT1_COUNT as u32;
-        } else {
-            return;
         }
-
-        // fill indirect2 from (a0, b0) -> (a1, b1)
-        let mut a0 = current_blocks as usize / INODE_INDIRECT1_COUNT;
-        let mut b0 = current_blocks as usize % INODE_INDIRECT1_COUNT;
-        let a1 = total_blocks as usize / INODE_INDIRECT1_COUNT;
-        let b1 = total_blocks as usize % INODE_INDIRECT1_COUNT;
-
-        // fill indirect2
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
-                while a0 < a1 || (a0 == a1 && b0 < b1) {
-                    if b0 == 0 {
-                        indirect2[a0] = new_blocks.next().unwrap();
-                    }
-                    // fill current
-                    get_block_cache(indirect2[a0] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            indirect1[b0] = new_blocks.next().unwrap();
-                        });
-                    b0 += 1;
-                    if b0 == INODE_INDIRECT1_COUNT {
-                        b0 = 0;
-                        a0 += 1;
-                    }
-                }
-            });
+        self.size = new_size;
+        v
     }
 
     /// 清除这个I结点索引的各个数据块
     ///
     /// 但是这里并未去数据块中操作
     pub fn clear_size(&mut self, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
-        let mut v: Vec<u32> = Vec::new();
-        let mut data_blocks = self.data_blocks() as usize;
-        self.size = 0;
-        let mut current_blocks = 0usize;
-        // direct
-        while current_blocks < data_blocks.min(INODE_DIRECT_COUNT) {
-            v.push(self.direct[current_blocks]);
-            self.direct[current_blocks] = 0;
-            current_blocks += 1;
-        }
-        // indirect1 block
-        if data_blocks > INODE_DIRECT_COUNT {
-            v.push(self.indirect1);
-            data_blocks -= INODE_DIRECT_COUNT;
-            current_blocks = 0;
-        } else {
-            return v;
-        }
-        // indirect1
-        get_block_cache(self.indirect1 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect1: &mut IndirectBlock| {
-                while current_blocks < data_blocks.min(INODE_INDIRECT1_COUNT) {
-                    v.push(indirect1[current_blocks]);
-                    //indirect1[current_blocks] = 0;
-                    current_blocks += 1;
-                }
-            });
-        self.indirect1 = 0;
-        // indirect2 block
-        if data_blocks > INODE_INDIRECT1_COUNT {
-            v.push(self.indirect2);
-            data_blocks -= INODE_INDIRECT1_COUNT;
-        } else {
-            return v;
-        }
-        // indirect2
-        assert!(data_blocks <= INODE_INDIRECT2_COUNT);
-        let a1 = data_blocks / INODE_INDIRECT1_COUNT;
-        let b1 = data_blocks % INODE_INDIRECT1_COUNT;
-        get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
-            .lock()
-            .modify(0, |indirect2: &mut IndirectBlock| {
-                // full indirect1 blocks
-                for entry in indirect2.iter_mut().take(a1) {
-                    v.push(*entry);
-                    get_block_cache(*entry as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter() {
-                                v.push(*entry);
-                            }
-                        });
-                }
-                // last indirect1 block
-                if b1 > 0 {
-                    v.push(indirect2[a1]);
-                    get_block_cache(indirect2[a1] as usize, Arc::clone(block_device))
-                        .lock()
-                        .modify(0, |indirect1: &mut IndirectBlock| {
-                            for entry in indirect1.iter().take(b1) {
-                                v.push(*entry);
-                            }
-                        });
-                    //indirect2[a1] = 0;
-                }
-            });
-        self.indirect2 = 0;
-        v
+        self.decrease_size(0, block_device)
     }
 
     /// 从此I结点索引的数据块中指定偏移处读出字节
@@ -412,7 +512,16 @@ impl DirEntry {
         let len = (0usize..).find(|i| self.name[*i] == 0).unwrap();
         core::str::from_utf8(&self.name[..len]).unwrap()
     }
+    /// 检查可能损坏的目录项的名字，没有结束符或不是UTF-8时返回None
+    pub fn checked_name(&self) -> Option<&str> {
+        let len = self.name.iter().position(|b| *b == 0)?;
+        core::str::from_utf8(&self.name[..len]).ok()
+    }
     pub fn inode_number(&self) -> u32 {
         self.inode_number
     }
+    /// 是否是已删除的空目录项
+    pub fn is_empty(&self) -> bool {
+        self.name[0] == 0
+    }
 }
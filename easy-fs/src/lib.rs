@@ -3,16 +3,25 @@ extern crate alloc;
 extern crate lazy_static;
 
 use bitmap::Bitmap;
-use block_cache::{block_cache_sync_all, get_block_cache};
+pub use block_cache::{block_cache_clear, block_cache_stats, set_block_cache_size};
+use block_cache::{block_cache_sync_all, get_block_cache, sync_block_cache};
 pub use block_dev::BlockDevice;
-pub use efs::EasyFileSystem;
+use clock::now;
+pub use clock::set_clock;
+pub use efs::{EasyFileSystem, FsStats};
+pub use fsck::{fsck, Problem};
+use journal::Transaction;
 use layout::*;
-pub use vfs::Inode;
+pub use layout::{DiskInode, DiskInodeType, LINK_MAX, MAX_FILE_SIZE, NAME_LENGTH_LIMIT};
+pub use vfs::{DirEntryInfo, Inode, Metadata};
 
 mod bitmap;
 mod block_cache;
 mod block_dev;
+mod clock;
 mod efs;
+mod fsck;
+mod journal;
 mod layout;
 mod vfs;
 
@@ -1,14 +1,47 @@
 //! 虚拟文件系统
 
 use super::{
-    block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    block_cache_sync_all, get_block_cache, journal, BlockDevice, DirEntry, DiskInode,
+    DiskInodeType, EasyFileSystem, Transaction, DIRENT_SZ, NAME_LENGTH_LIMIT,
 };
-use alloc::{string::String, sync::Arc, vec::Vec};
+use crate::{now, BLOCK_SIZE, LINK_MAX, MAX_FILE_SIZE};
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
 use spin::{Mutex, MutexGuard};
 
+/// I结点的元数据
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    /// I结点编号
+    pub inode_id: u32,
+    /// 类型
+    pub type_: DiskInodeType,
+    /// 文件大小
+    pub size: u64,
+    /// 链接数
+    pub nlink: u32,
+    /// 占用的块数，包括索引块
+    pub blocks: u32,
+    /// 创建时间
+    pub ctime: u32,
+    /// 最后修改时间
+    pub mtime: u32,
+}
+
+/// 目录项信息
+#[derive(Debug, Clone)]
+pub struct DirEntryInfo {
+    /// 文件名
+    pub name: String,
+    /// I结点编号
+    pub inode_id: u32,
+    /// 类型
+    pub type_: DiskInodeType,
+}
+
 /// 磁盘中的索引节点，用于对单个文件进行操作
 pub struct Inode {
+    /// I结点编号
+    inode_id: u32,
     /// 块编号
     block_id: usize,
     /// 块内偏移
@@ -21,12 +54,14 @@ pub struct Inode {
 
 impl Inode {
     pub fn new(
+        inode_id: u32,
         block_id: u32,
         block_offset: usize,
         fs: Arc<Mutex<EasyFileSystem>>,
         block_device: Arc<dyn BlockDevice>,
     ) -> Self {
         Self {
+            inode_id,
             block_id: block_id as usize,
             block_offset,
             fs,
@@ -47,124 +82,584 @@ impl Inode {
             .modify(self.block_offset, f)
     }
 
-    /// 在当前I结点的目录项中寻找给定名字的I结点，返回I结点编号
-    fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
-        assert!(disk_inode.is_dir());
+    /// 获取编号为inode_id的I结点，需要持有文件系统的锁
+    fn inode_of(&self, inode_id: u32, fs: &EasyFileSystem) -> Inode {
+        let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
+        Self::new(
+            inode_id,
+            block_id,
+            block_offset,
+            self.fs.clone(),
+            self.block_device.clone(),
+        )
+    }
+
+    /// 读取目录中的所有目录项，返回（槽位编号，目录项），已删除的空槽位不返回
+    fn dirents(&self, disk_inode: &DiskInode) -> Vec<(usize, DirEntry)> {
         let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-        let mut dirent = DirEntry::empty();
+        let mut v = Vec::new();
         for i in 0..file_count {
+            let mut dirent = DirEntry::empty();
             assert_eq!(
                 disk_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device),
                 DIRENT_SZ,
             );
-            if dirent.name() == name {
-                return Some(dirent.inode_number() as u32);
+            if !dirent.is_empty() {
+                v.push((i, dirent));
             }
         }
-        None
+        v
+    }
+
+    /// 在当前I结点的目录项中寻找给定名字的I结点，返回I结点编号
+    ///
+    /// 当前I结点不是目录时返回None
+    fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
+        if !disk_inode.is_dir() {
+            return None;
+        }
+        self.dirents(disk_inode)
+            .into_iter()
+            .find(|(_, dirent)| dirent.name() == name)
+            .map(|(_, dirent)| dirent.inode_number())
     }
 
     /// 在当前I结点的目录项中寻找给定名字的I结点，返回`Inode`类型
     pub fn find(&self, name: &str) -> Option<Arc<Inode>> {
         let fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
-            self.find_inode_id(name, disk_inode).map(|inode_id| {
-                let (block_id, block_offset) = fs.get_disk_inode_pos(inode_id);
-                Arc::new(Self::new(
-                    block_id,
-                    block_offset,
-                    self.fs.clone(),
-                    self.block_device.clone(),
-                ))
-            })
+            self.find_inode_id(name, disk_inode)
+                .map(|inode_id| Arc::new(self.inode_of(inode_id, &fs)))
         })
     }
 
+    /// 按路径查找I结点
+    ///
+    /// 以`/`开头的路径从根目录开始查找，否则从当前I结点开始查找，
+    /// `.`和`..`由目录中的目录项解析
+    pub fn lookup(&self, path: &str) -> Option<Arc<Inode>> {
+        let start = if path.starts_with('/') {
+            0
+        } else {
+            self.inode_id
+        };
+        let mut inode = Arc::new(self.inode_of(start, &self.fs.lock()));
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            inode = inode.find(name)?;
+        }
+        Some(inode)
+    }
+
+    /// 从第slot个目录项槽位开始读取至多max个目录项，跳过已删除的槽位
+    ///
+    /// 返回读取的目录项和下一次读取的起始槽位，当前I结点不是目录时返回None
+    pub fn read_dir(&self, slot: usize, max: usize) -> Option<(Vec<DirEntryInfo>, usize)> {
+        let fs = self.fs.lock();
+        let dirents = self.read_disk_inode(|disk_inode| {
+            if !disk_inode.is_dir() {
+                return None;
+            }
+            let dirents: Vec<_> = self
+                .dirents(disk_inode)
+                .into_iter()
+                .filter(|(i, _)| *i >= slot)
+                .take(max)
+                .collect();
+            let file_count = disk_inode.size as usize / DIRENT_SZ;
+            Some((dirents, file_count))
+        });
+        let (dirents, file_count) = dirents?;
+        // 读满max个时从最后一个目录项之后继续，否则已经读到目录末尾
+        let next = match dirents.last() {
+            Some((i, _)) if dirents.len() == max => i + 1,
+            _ => file_count.max(slot),
+        };
+        let entries = dirents
+            .into_iter()
+            .map(|(_, dirent)| {
+                let inode = self.inode_of(dirent.inode_number(), &fs);
+                DirEntryInfo {
+                    name: String::from(dirent.name()),
+                    inode_id: dirent.inode_number(),
+                    type_: inode.read_disk_inode(|disk_inode| disk_inode.type_()),
+                }
+            })
+            .collect();
+        Some((entries, next))
+    }
+
+    /// 获取I结点编号
+    pub fn inode_id(&self) -> u32 {
+        self.inode_id
+    }
+
+    /// 是否是目录
+    pub fn is_dir(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+
+    /// 是否是普通文件
+    pub fn is_file(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_file())
+    }
+
+    /// 是否是命名管道
+    pub fn is_fifo(&self) -> bool {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.is_fifo())
+    }
+
     /// 扩容当前I结点
     ///
-    /// 使用磁盘块管理器分配新的数据块，并为这些块建立索引
+    /// 使用磁盘块管理器分配新的数据块，并为这些块建立索引。
+    /// 空闲数据块不足时不扩容，返回false
     fn increase_size(
         &self,
-        new_size: u32,
+        new_size: u64,
         disk_inode: &mut DiskInode,
         fs: &mut MutexGuard<EasyFileSystem>,
-    ) {
+    ) -> bool {
         if new_size < disk_inode.size {
-            return;
+            return true;
         }
         let blocks_needed = disk_inode.blocks_num_needed(new_size);
+        if blocks_needed > 0 && blocks_needed as usize > fs.free_data_blocks() {
+            return false;
+        }
         let mut v: Vec<u32> = Vec::new();
         for _ in 0..blocks_needed {
             v.push(fs.alloc_data());
         }
         disk_inode.increase_size(new_size, v, &self.block_device);
+        true
     }
 
-    /// 创建一个新的文件
-    pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+    /// 目录中第一个已删除的空槽位，没有时返回目录项的个数
+    fn free_slot(&self, dir_inode: &DiskInode) -> usize {
+        let file_count = (dir_inode.size as usize) / DIRENT_SZ;
+        let mut dirent = DirEntry::empty();
+        (0..file_count)
+            .find(|&i| {
+                dir_inode.read_at(DIRENT_SZ * i, dirent.as_bytes_mut(), &self.block_device);
+                dirent.is_empty()
+            })
+            .unwrap_or(file_count)
+    }
+
+    /// 空闲的I结点和数据块是否足够在当前目录中添加一个目录项，
+    /// type_不为None时还要新建一个该类型的I结点
+    fn has_space(
+        &self,
+        dir_inode: &DiskInode,
+        type_: Option<DiskInodeType>,
+        fs: &MutexGuard<EasyFileSystem>,
+    ) -> bool {
+        let slot = self.free_slot(dir_inode);
+        let mut blocks_needed = if slot == (dir_inode.size as usize) / DIRENT_SZ {
+            dir_inode.blocks_num_needed(((slot + 1) * DIRENT_SZ) as u64)
+        } else {
+            0
+        };
+        match type_ {
+            Some(DiskInodeType::Directory) => {
+                blocks_needed += DiskInode::total_blocks(2 * DIRENT_SZ as u64)
+            }
+            Some(_) => {}
+            None => return blocks_needed as usize <= fs.free_data_blocks(),
+        }
+        fs.free_inodes() > 0 && blocks_needed as usize <= fs.free_data_blocks()
+    }
+
+    /// 空闲的I结点和数据块是否足够在当前目录中新建一个类型为type_的I结点，
+    /// type_为None时只检查添加一个目录项（如移入文件）所需的数据块
+    pub fn has_space_for(&self, type_: Option<DiskInodeType>) -> bool {
+        let fs = self.fs.lock();
+        self.read_disk_inode(|dir_inode| self.has_space(dir_inode, type_, &fs))
+    }
+
+    /// 在当前目录中添加一个目录项，优先复用已删除的空槽位
+    ///
+    /// 需要扩容但空闲数据块不足时不添加，返回false
+    fn add_dirent(&self, name: &str, inode_id: u32, fs: &mut MutexGuard<EasyFileSystem>) -> bool {
+        self.modify_disk_inode(|dir_inode| {
+            let file_count = (dir_inode.size as usize) / DIRENT_SZ;
+            let slot = self.free_slot(dir_inode);
+            // 没有空槽位，为当前I结点扩容
+            if slot == file_count {
+                let new_size = (file_count + 1) * DIRENT_SZ;
+                if !self.increase_size(new_size as u64, dir_inode, fs) {
+                    return false;
+                }
+            }
+            // 写入目录项
+            let dirent = DirEntry::new(name, inode_id);
+            dir_inode.write_at(slot * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
+            dir_inode.mtime = now();
+            true
+        })
+    }
+
+    /// 删除当前目录中名为name的目录项，将其槽位置空
+    fn remove_dirent(&self, name: &str) {
+        self.replace_dirent(name, DirEntry::empty());
+    }
+
+    /// 将当前目录中名为name的目录项替换为dirent
+    fn replace_dirent(&self, name: &str, dirent: DirEntry) {
+        self.modify_disk_inode(|dir_inode| {
+            let slot = self
+                .dirents(dir_inode)
+                .into_iter()
+                .find(|(_, dirent)| dirent.name() == name)
+                .map(|(slot, _)| slot);
+            if let Some(slot) = slot {
+                dir_inode.write_at(slot * DIRENT_SZ, dirent.as_bytes(), &self.block_device);
+                dir_inode.mtime = now();
+            }
+        });
+    }
+
+    /// 目录中是否只有`.`和`..`
+    fn is_empty_dir(&self, disk_inode: &DiskInode) -> bool {
+        disk_inode.is_dir()
+            && self
+                .dirents(disk_inode)
+                .iter()
+                .all(|(_, dirent)| dirent.name() == "." || dirent.name() == "..")
+    }
+
+    /// 链接数为0且没有被打开时，回收编号为inode_id的I结点及其数据块
+    fn try_reclaim(&self, inode_id: u32, fs: &mut MutexGuard<EasyFileSystem>) {
+        let inode = self.inode_of(inode_id, fs);
+        if inode.read_disk_inode(|disk_inode| disk_inode.nlink) != 0 || fs.is_open(inode_id) {
+            return;
+        }
+        inode.modify_disk_inode(|disk_inode| {
+            for data_block in disk_inode.clear_size(&self.block_device) {
+                fs.dealloc_data(data_block);
+            }
+        });
+        fs.dealloc_inode(inode_id);
+    }
+
+    /// 减少编号为inode_id的I结点的链接数，目录被删除时链接数直接清零
+    fn drop_link(&self, inode_id: u32, fs: &mut MutexGuard<EasyFileSystem>) {
+        let inode = self.inode_of(inode_id, fs);
+        inode.modify_disk_inode(|disk_inode| {
+            disk_inode.nlink = if disk_inode.is_dir() {
+                0
+            } else {
+                disk_inode.nlink.saturating_sub(1)
+            };
+        });
+        self.try_reclaim(inode_id, fs);
+    }
+
+    /// 在当前目录中创建一个类型为type_的I结点
+    ///
+    /// 名字不合法、已经存在、当前I结点不是目录、空闲的I结点和数据块不足
+    /// 或新建目录时当前目录的链接数已达上限时返回None
+    fn create_inode(&self, name: &str, type_: DiskInodeType) -> Option<Arc<Inode>> {
+        if name.is_empty() || name.len() > NAME_LENGTH_LIMIT || name.contains('/') {
+            return None;
+        }
         let mut fs = self.fs.lock();
-        let op = |root_inode: &DiskInode| {
-            assert!(root_inode.is_dir());
-            self.find_inode_id(name, root_inode)
+        let _txn = Transaction::begin();
+        let op = |dir_inode: &DiskInode| {
+            dir_inode.is_dir()
+                && self.find_inode_id(name, dir_inode).is_none()
+                && (type_ != DiskInodeType::Directory || dir_inode.nlink < LINK_MAX)
+                && self.has_space(dir_inode, Some(type_), &fs)
         };
-        if self.read_disk_inode(op).is_some() {
+        if !self.read_disk_inode(op) {
             return None;
         }
 
         // 为新建的文件分配初始化一个I结点
         let new_inode_id = fs.alloc_inode();
-        let (new_inode_block_id, new_inode_block_offset) = fs.get_disk_inode_pos(new_inode_id);
-        get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
-            .lock()
-            .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
-            });
+        let new_inode = self.inode_of(new_inode_id, &fs);
+        let initialized = new_inode.modify_disk_inode(|disk_inode| {
+            disk_inode.initialize(type_, now());
+            // 目录中总是包含`.`和`..`
+            if disk_inode.is_dir() {
+                if !self.increase_size(2 * DIRENT_SZ as u64, disk_inode, &mut fs) {
+                    return false;
+                }
+                let dot = DirEntry::new(".", new_inode_id);
+                let dotdot = DirEntry::new("..", self.inode_id);
+                disk_inode.write_at(0, dot.as_bytes(), &self.block_device);
+                disk_inode.write_at(DIRENT_SZ, dotdot.as_bytes(), &self.block_device);
+            }
+            true
+        });
+        // 空间已经检查过，失败时回收新分配的I结点
+        if !initialized || !self.add_dirent(name, new_inode_id, &mut fs) {
+            new_inode.modify_disk_inode(|disk_inode| disk_inode.nlink = 0);
+            self.try_reclaim(new_inode_id, &mut fs);
+            return None;
+        }
+        // 子目录的`..`指向当前目录
+        if type_ == DiskInodeType::Directory {
+            self.modify_disk_inode(|dir_inode| dir_inode.nlink += 1);
+        }
 
-        self.modify_disk_inode(|root_inode| {
-            // 为当前I结点扩容
-            let file_count = (root_inode.size as usize) / DIRENT_SZ;
-            let new_size = (file_count + 1) * DIRENT_SZ;
-            self.increase_size(new_size as u32, root_inode, &mut fs);
+        // 返回新建的I结点
+        Some(Arc::new(new_inode))
+    }
 
-            // 写入目录项
-            let dirent = DirEntry::new(name, new_inode_id);
-            root_inode.write_at(
-                file_count * DIRENT_SZ,
-                dirent.as_bytes(),
-                &self.block_device,
-            );
-        });
+    /// 创建一个新的文件
+    pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::File)
+    }
+
+    /// 创建一个新的目录，其中包含`.`和`..`
+    pub fn mkdir(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Directory)
+    }
+
+    /// 创建一个命名管道，管道中的数据不写入磁盘
+    pub fn mkfifo(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Fifo)
+    }
+
+    /// 删除当前目录中名为name的空目录
+    ///
+    /// 目录不存在、不是目录或不为空时返回false，
+    /// 目录仍被打开时推迟到最后一次关闭时回收
+    pub fn rmdir(&self, name: &str) -> bool {
+        if name == "." || name == ".." {
+            return false;
+        }
+        let mut fs = self.fs.lock();
+        let _txn = Transaction::begin();
+        let inode_id = match self.read_disk_inode(|dir_inode| self.find_inode_id(name, dir_inode)) {
+            Some(inode_id) => inode_id,
+            None => return false,
+        };
+        let dir = self.inode_of(inode_id, &fs);
+        if !dir.read_disk_inode(|disk_inode| self.is_empty_dir(disk_inode)) {
+            return false;
+        }
+        self.remove_dirent(name);
+        self.modify_disk_inode(|dir_inode| dir_inode.nlink = dir_inode.nlink.saturating_sub(1));
+        self.drop_link(inode_id, &mut fs);
+        true
+    }
+
+    /// 删除当前目录中名为name的文件的目录项
+    ///
+    /// 文件不存在或是目录时返回false，
+    /// 最后一个链接被删除且文件没有被打开时回收I结点和数据块
+    pub fn unlink(&self, name: &str) -> bool {
+        if name == "." || name == ".." {
+            return false;
+        }
+        let mut fs = self.fs.lock();
+        let _txn = Transaction::begin();
+        let inode_id = match self.read_disk_inode(|dir_inode| self.find_inode_id(name, dir_inode)) {
+            Some(inode_id) => inode_id,
+            None => return false,
+        };
+        if self
+            .inode_of(inode_id, &fs)
+            .read_disk_inode(|disk_inode| disk_inode.is_dir())
+        {
+            return false;
+        }
+        self.remove_dirent(name);
+        self.drop_link(inode_id, &mut fs);
+        true
+    }
+
+    /// 将当前目录中名为old_name的文件移动到new_dir目录中并命名为new_name
+    ///
+    /// 目标已存在时将其替换：文件只能替换文件，目录只能替换空目录。
+    /// 源不存在、名字不合法、将目录移动到自身的子目录中、空闲数据块不足
+    /// 或目标目录的链接数已达上限时返回false
+    pub fn rename(&self, old_name: &str, new_dir: &Inode, new_name: &str) -> bool {
+        let invalid = |name: &str| {
+            name.is_empty()
+                || name.len() > NAME_LENGTH_LIMIT
+                || name.contains('/')
+                || name == "."
+                || name == ".."
+        };
+        if invalid(old_name) || invalid(new_name) {
+            return false;
+        }
+        let mut fs = self.fs.lock();
+        let _txn = Transaction::begin();
+        let src_id = match self.read_disk_inode(|dir_inode| self.find_inode_id(old_name, dir_inode))
+        {
+            Some(inode_id) => inode_id,
+            None => return false,
+        };
+        if !new_dir.read_disk_inode(|disk_inode| disk_inode.is_dir()) {
+            return false;
+        }
+        let src = self.inode_of(src_id, &fs);
+        let src_is_dir = src.read_disk_inode(|disk_inode| disk_inode.is_dir());
+        // 不能将目录移动到自身或自身的子目录中
+        if src_is_dir {
+            let mut inode_id = new_dir.inode_id;
+            while inode_id != 0 {
+                if inode_id == src_id {
+                    return false;
+                }
+                let inode = self.inode_of(inode_id, &fs);
+                inode_id = inode
+                    .read_disk_inode(|disk_inode| self.find_inode_id("..", disk_inode))
+                    .unwrap();
+            }
+        }
+        // 替换已存在的目标
+        let dst_id = new_dir.read_disk_inode(|dir_inode| self.find_inode_id(new_name, dir_inode));
+        // 目标不存在时需要添加目录项，先检查空间，避免删除源目录项后失败
+        if dst_id.is_none()
+            && !new_dir.read_disk_inode(|dir_inode| self.has_space(dir_inode, None, &fs))
+        {
+            return false;
+        }
+        // 子目录移入其他目录时目标目录的链接数加一，替换已存在的目录时不变
+        if src_is_dir
+            && self.inode_id != new_dir.inode_id
+            && dst_id.is_none()
+            && new_dir.read_disk_inode(|dir_inode| dir_inode.nlink) >= LINK_MAX
+        {
+            return false;
+        }
+        if let Some(dst_id) = dst_id {
+            if dst_id == src_id {
+                return true;
+            }
+            let dst = self.inode_of(dst_id, &fs);
+            let (dst_is_dir, dst_is_empty) = dst
+                .read_disk_inode(|disk_inode| (disk_inode.is_dir(), self.is_empty_dir(disk_inode)));
+            if src_is_dir != dst_is_dir || (dst_is_dir && !dst_is_empty) {
+                return false;
+            }
+            new_dir.remove_dirent(new_name);
+            if dst_is_dir {
+                new_dir.modify_disk_inode(|dir_inode| {
+                    dir_inode.nlink = dir_inode.nlink.saturating_sub(1)
+                });
+            }
+            self.drop_link(dst_id, &mut fs);
+        }
+        self.remove_dirent(old_name);
+        if !new_dir.add_dirent(new_name, src_id, &mut fs) {
+            // 空间已经检查过，失败时将源目录项放回刚空出的槽位
+            self.add_dirent(old_name, src_id, &mut fs);
+            return false;
+        }
+        // 移动到其他目录的子目录需要更新`..`
+        if src_is_dir && self.inode_id != new_dir.inode_id {
+            src.replace_dirent("..", DirEntry::new("..", new_dir.inode_id));
+            self.modify_disk_inode(|dir_inode| dir_inode.nlink = dir_inode.nlink.saturating_sub(1));
+            new_dir.modify_disk_inode(|dir_inode| dir_inode.nlink += 1);
+        }
+        true
+    }
+
+    /// 登记一次打开，被打开的I结点在链接数为0后仍然保留
+    pub fn open(&self) {
+        self.fs.lock().open_inode(self.inode_id);
+    }
 
-        let (block_id, block_offset) = fs.get_disk_inode_pos(new_inode_id);
+    /// 撤销一次打开，最后一次关闭时若链接数为0则回收I结点
+    pub fn close(&self) {
+        let mut fs = self.fs.lock();
+        let _txn = Transaction::begin();
+        fs.close_inode(self.inode_id);
+        self.try_reclaim(self.inode_id, &mut fs);
+    }
+
+    /// 将文件系统的所有脏块写回块设备
+    ///
+    /// 块缓存不区分文件，因此同步整个文件系统；期间持有文件系统的锁，
+    /// 写回的是两次操作之间的一致状态
+    pub fn sync(&self) {
+        let _fs = self.fs.lock();
         block_cache_sync_all();
+    }
 
-        // 返回新建的I结点
-        Some(Arc::new(Self::new(
-            block_id,
-            block_offset,
-            self.fs.clone(),
-            self.block_device.clone(),
-        )))
+    /// 获取链接数
+    pub fn nlink(&self) -> u32 {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.nlink as u32)
     }
 
     /// 遍历当前Inode下的所有文件，返回文件名列表
+    ///
+    /// 当前I结点不是目录时返回空列表
     pub fn ls(&self) -> Vec<String> {
         let _fs = self.fs.lock();
         self.read_disk_inode(|disk_inode| {
-            let file_count = (disk_inode.size as usize) / DIRENT_SZ;
-            let mut v: Vec<String> = Vec::new();
-            for i in 0..file_count {
-                let mut dirent = DirEntry::empty();
-                assert_eq!(
-                    disk_inode.read_at(i * DIRENT_SZ, dirent.as_bytes_mut(), &self.block_device),
-                    DIRENT_SZ,
-                );
-                v.push(String::from(dirent.name()));
-            }
-            v
+            if !disk_inode.is_dir() {
+                return Vec::new();
+            }
+            self.dirents(disk_inode)
+                .into_iter()
+                .map(|(_, dirent)| String::from(dirent.name()))
+                .collect()
         })
     }
 
+    /// 将文件截断或扩展到new_size，扩展的部分填零
+    ///
+    /// 当前I结点是目录、new_size超过最大文件大小或空闲数据块不足时返回false
+    pub fn truncate(&self, new_size: u64) -> bool {
+        if new_size > MAX_FILE_SIZE {
+            return false;
+        }
+        let mut fs = self.fs.lock();
+        let _txn = Transaction::begin();
+        self.modify_disk_inode(|disk_inode| {
+            if disk_inode.is_dir() {
+                return false;
+            }
+            if new_size >= disk_inode.size {
+                // 新分配的数据块已被清零
+                if !self.increase_size(new_size, disk_inode, &mut fs) {
+                    return false;
+                }
+            } else {
+                // 清零最后一个数据块中被截断的部分，之后扩展时读到的是零
+                let new_size = new_size as usize;
+                let block_end = (new_size + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE;
+                let zeros = vec![0u8; block_end.min(disk_inode.size as usize) - new_size];
+                journal::ordered(|| disk_inode.write_at(new_size, &zeros, &self.block_device));
+                for data_block in disk_inode.decrease_size(new_size as u64, &self.block_device) {
+                    fs.dealloc_data(data_block);
+                }
+            }
+            disk_inode.mtime = now();
+            true
+        })
+    }
+
+    /// 获取I结点的元数据
+    pub fn metadata(&self) -> Metadata {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| Metadata {
+            inode_id: self.inode_id,
+            type_: disk_inode.type_(),
+            size: disk_inode.size,
+            nlink: disk_inode.nlink as u32,
+            blocks: DiskInode::total_blocks(disk_inode.size),
+            ctime: disk_inode.ctime,
+            mtime: disk_inode.mtime,
+        })
+    }
+
+    /// 获取文件大小（字节）
+    pub fn size(&self) -> usize {
+        let _fs = self.fs.lock();
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
+
     /// 给定偏移，将磁盘上的I结点索引的数据块中的字节读到buf中
     pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
         let _fs = self.fs.lock();
@@ -172,19 +667,33 @@ impl Inode {
     }
 
     /// 给定偏移，将buf中的字节写入磁盘I结点索引的数据块中去
+    ///
+    /// 超过最大文件大小的部分不会写入，空闲数据块不足以扩容时写入0字节
     pub fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        if offset as u64 >= MAX_FILE_SIZE {
+            return 0;
+        }
         let mut fs = self.fs.lock();
-        let size = self.modify_disk_inode(|disk_inode| {
-            self.increase_size((offset + buf.len()) as u32, disk_inode, &mut fs);
-            disk_inode.write_at(offset, buf, &self.block_device)
-        });
-        block_cache_sync_all();
-        size
+        let _txn = Transaction::begin();
+        self.modify_disk_inode(|disk_inode| {
+            let new_size = ((offset + buf.len()) as u64).min(MAX_FILE_SIZE);
+            if !self.increase_size(new_size, disk_inode, &mut fs) {
+                return 0;
+            }
+            disk_inode.mtime = now();
+            // 目录项属于元数据，普通文件的数据不记入日志
+            if disk_inode.is_dir() {
+                disk_inode.write_at(offset, buf, &self.block_device)
+            } else {
+                journal::untracked(|| disk_inode.write_at(offset, buf, &self.block_device))
+            }
+        })
     }
 
     /// 清除此I结点索引的所有数据块，且将这些数据块中数据清除
     pub fn clear(&self) {
         let mut fs = self.fs.lock();
+        let _txn = Transaction::begin();
         self.modify_disk_inode(|disk_inode| {
             let size = disk_inode.size;
             let data_blocks_dealloc = disk_inode.clear_size(&self.block_device);
@@ -192,7 +701,7 @@ impl Inode {
             for data_block in data_blocks_dealloc.into_iter() {
                 fs.dealloc_data(data_block);
             }
+            disk_inode.mtime = now();
         });
-        block_cache_sync_all();
     }
 }
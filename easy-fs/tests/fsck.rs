@@ -0,0 +1,222 @@
+//! 在内存中的块设备上破坏文件系统，检查fsck能否发现并修复问题
+
+use easy_fs::{
+    block_cache_clear, fsck, BlockDevice, DiskInodeType, EasyFileSystem, Problem, LINK_MAX,
+};
+use std::sync::{Arc, Mutex};
+
+const BLOCK_SIZE: usize = 512;
+const TOTAL_BLOCKS: usize = 4096;
+const INODE_SIZE: usize = 128;
+
+/// 内存中的块设备
+struct MemDevice(Mutex<Vec<u8>>);
+
+impl BlockDevice for MemDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let start = block_id * BLOCK_SIZE;
+        buf.copy_from_slice(&self.0.lock().unwrap()[start..start + BLOCK_SIZE]);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let start = block_id * BLOCK_SIZE;
+        self.0.lock().unwrap()[start..start + BLOCK_SIZE].copy_from_slice(buf);
+    }
+}
+
+impl MemDevice {
+    fn read_u32(&self, pos: usize) -> u32 {
+        let bytes = &self.0.lock().unwrap()[pos..pos + 4];
+        u32::from_le_bytes(bytes.try_into().unwrap())
+    }
+
+    fn write_u32(&self, pos: usize, value: u32) {
+        self.0.lock().unwrap()[pos..pos + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    /// 修改位图中的一位
+    fn set_bit(&self, bitmap_start: usize, bit: usize, value: bool) {
+        let byte = &mut self.0.lock().unwrap()[bitmap_start * BLOCK_SIZE + bit / 8];
+        if value {
+            *byte |= 1 << (bit % 8);
+        } else {
+            *byte &= !(1 << (bit % 8));
+        }
+    }
+}
+
+/// 由超级块计算的各区域起始块号
+struct Layout {
+    inode_bitmap: usize,
+    inode_area: usize,
+    data_bitmap: usize,
+    data_area: usize,
+}
+
+impl Layout {
+    fn read(device: &MemDevice) -> Self {
+        let field = |i: usize| device.read_u32(4 * i) as usize;
+        let inode_bitmap = 1 + field(6);
+        let inode_area = inode_bitmap + field(2);
+        let data_bitmap = inode_area + field(3);
+        Self {
+            inode_bitmap,
+            inode_area,
+            data_bitmap,
+            data_area: data_bitmap + field(4),
+        }
+    }
+
+    /// I结点在设备中的字节偏移
+    fn inode(&self, inode_id: u32) -> usize {
+        (self.inode_area + inode_id as usize / 4) * BLOCK_SIZE + inode_id as usize % 4 * INODE_SIZE
+    }
+}
+
+#[test]
+fn fsck_repair_test() {
+    let device = Arc::new(MemDevice(Mutex::new(vec![0; TOTAL_BLOCKS * BLOCK_SIZE])));
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    let check = |repair| fsck(block_device.clone(), TOTAL_BLOCKS, repair);
+
+    let efs = EasyFileSystem::create(block_device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root = EasyFileSystem::root_inode(&efs);
+    let a = root.create("a").unwrap();
+    a.write_at(0, &[1u8; 3000]);
+    let b = root.create("b").unwrap();
+    b.write_at(0, &[2u8; BLOCK_SIZE]);
+    let dir = root.mkdir("dir").unwrap();
+    let c_data: Vec<u8> = (0..200 * BLOCK_SIZE).map(|i| i as u8).collect();
+    let c = dir.create("c").unwrap();
+    c.write_at(0, &c_data);
+    dir.mkdir("sub").unwrap();
+    assert!(root.mkfifo("fifo").unwrap().is_fifo());
+    let victim = dir.create("victim").unwrap();
+    victim.write_at(0, &[3u8; 2 * BLOCK_SIZE]);
+    root.sync();
+    block_cache_clear();
+    assert_eq!(check(false), Ok(Vec::new()));
+
+    // 超级块损坏时无法检查
+    let layout = Layout::read(&device);
+    device.write_u32(4, TOTAL_BLOCKS as u32 - 1);
+    block_cache_clear();
+    assert!(matches!(check(false), Err(Problem::BadSuperBlock(_))));
+    device.write_u32(4, TOTAL_BLOCKS as u32);
+    block_cache_clear();
+    assert!(matches!(
+        fsck(block_device.clone(), TOTAL_BLOCKS - 1, false),
+        Err(Problem::BadSuperBlock(_))
+    ));
+    // 没有版本号的旧格式和只有三级间接索引的旧格式
+    let version = device.read_u32(28);
+    for old in [0, 1] {
+        device.write_u32(28, old);
+        block_cache_clear();
+        assert_eq!(
+            check(false),
+            Err(Problem::BadSuperBlock("unsupported format version"))
+        );
+    }
+    device.write_u32(28, version);
+    block_cache_clear();
+
+    // 破坏各种元数据
+    let (a_id, b_id, dir_id) = (a.inode_id(), b.inode_id(), dir.inode_id());
+    let a_block = device.read_u32(layout.inode(a_id) + 8);
+    let b_block = device.read_u32(layout.inode(b_id) + 8);
+    let c_block = device.read_u32(layout.inode(c.inode_id()) + 8);
+    // 链接数，同时写入的type_仍为文件
+    device.write_u32(layout.inode(a_id) + 116, 5 << 16);
+    // b与a共用一个块，b原来的块泄漏
+    device.write_u32(layout.inode(b_id) + 8, a_block);
+    // 使用中的块在位图中空闲
+    device.set_bit(
+        layout.data_bitmap,
+        c_block as usize - layout.data_area,
+        false,
+    );
+    // 未使用的块和I结点在位图中已分配
+    device.set_bit(layout.data_bitmap, 2000, true);
+    device.set_bit(layout.inode_bitmap, 100, true);
+    // dir的`..`和指向victim的目录项
+    let dir_block = device.read_u32(layout.inode(dir_id) + 8) as usize;
+    device.write_u32(dir_block * BLOCK_SIZE + 32 + 28, 7);
+    device.write_u32(dir_block * BLOCK_SIZE + 4 * 32 + 28, 99999);
+    drop((a, b, c, victim, dir, root, efs));
+    block_cache_clear();
+
+    let problems = check(false).unwrap();
+    for problem in [
+        Problem::WrongLinkCount {
+            inode_id: a_id,
+            nlink: 5,
+            expected: 1,
+        },
+        Problem::DoubleAllocated {
+            block_id: a_block,
+            first: a_id,
+            second: b_id,
+        },
+        Problem::LeakedBlock(b_block),
+        Problem::BlockNotAllocated(c_block),
+        Problem::LeakedBlock((layout.data_area + 2000) as u32),
+        Problem::OrphanInode(100),
+        Problem::BadDirent {
+            dir: dir_id,
+            slot: 1,
+            reason: "wrong `.` or `..` entry",
+        },
+        Problem::BadDirent {
+            dir: dir_id,
+            slot: 4,
+            reason: "inode number out of range",
+        },
+    ] {
+        assert!(problems.contains(&problem), "{} not found", problem);
+    }
+
+    // 修复后没有问题
+    assert!(!check(true).unwrap().is_empty());
+    block_cache_clear();
+    assert_eq!(check(false), Ok(Vec::new()));
+
+    // 修复后的文件系统可以正常使用
+    block_cache_clear();
+    let efs = EasyFileSystem::open(block_device.clone());
+    let root = EasyFileSystem::root_inode(&efs);
+    let mut buf = vec![0u8; 200 * BLOCK_SIZE];
+    assert_eq!(root.find("a").unwrap().read_at(0, &mut buf), 3000);
+    assert!(buf[..3000].iter().all(|b| *b == 1));
+    let b = root.find("b").unwrap();
+    assert_eq!(b.read_at(0, &mut buf), BLOCK_SIZE);
+    assert!(buf[..BLOCK_SIZE].iter().all(|b| *b == 1));
+    let dir = root.lookup("/dir").unwrap();
+    assert_eq!(dir.find("c").unwrap().read_at(0, &mut buf), c_data.len());
+    assert_eq!(buf, c_data);
+    assert!(dir.find("victim").is_none());
+    assert_eq!(dir.lookup("..").unwrap().inode_id(), 0);
+    assert_eq!(dir.nlink(), 3);
+    let fifo = root.find("fifo").unwrap();
+    assert!(fifo.is_fifo());
+    assert_eq!(fifo.nlink(), 1);
+    // 根目录、a、b、dir、c、sub、fifo
+    assert_eq!(efs.lock().used_inodes(), 7);
+    // 写入b不影响a
+    b.write_at(0, &[4u8; 10]);
+    assert_eq!(root.find("a").unwrap().read_at(0, &mut buf[..10]), 10);
+    assert_eq!(buf[..10], [1u8; 10]);
+
+    // 目录的链接数已达上限时不能再新建或移入子目录
+    block_cache_clear();
+    device.write_u32(
+        layout.inode(dir.inode_id()) + 116,
+        (LINK_MAX as u32) << 16 | DiskInodeType::Directory as u32,
+    );
+    assert!(dir.mkdir("full").is_none());
+    assert!(dir.create("file").is_some());
+    root.mkdir("sub2").unwrap();
+    assert!(!root.rename("sub2", &dir, "sub2"));
+    assert!(root.find("sub2").is_some());
+    assert_eq!(dir.nlink(), LINK_MAX as u32);
+}
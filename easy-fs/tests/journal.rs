@@ -0,0 +1,221 @@
+//! 在文件模拟的块设备上模拟写入到一半时崩溃，检查重放日志后文件系统是否一致
+
+use easy_fs::{
+    block_cache_clear, fsck, set_block_cache_size, BlockDevice, DiskInodeType, EasyFileSystem,
+    Inode, Metadata,
+};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+const BLOCK_SIZE: usize = 512;
+const TOTAL_BLOCKS: u32 = 2048;
+
+/// 块设备的状态
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// 正常写入
+    Normal,
+    /// 还能完整写入的块数，之后的一次写入只写入前半块
+    Budget(usize),
+    /// 已崩溃，文件中保留崩溃时的内容
+    Crashed,
+}
+
+/// 能在任意一次写入时崩溃的块设备
+///
+/// 崩溃后的写入只保存在内存中，使崩溃前的文件系统还能继续运行，
+/// 文件中则保留崩溃时磁盘上的内容
+struct CrashDevice {
+    file: Mutex<File>,
+    state: Mutex<State>,
+    /// 崩溃后写入的块
+    overlay: Mutex<HashMap<usize, Vec<u8>>>,
+}
+
+impl CrashDevice {
+    fn set_state(&self, state: State) {
+        *self.state.lock().unwrap() = state;
+    }
+
+    fn crashed(&self) -> bool {
+        *self.state.lock().unwrap() == State::Crashed
+    }
+
+    /// 丢弃崩溃后的写入，之后读到的是崩溃时磁盘上的内容
+    fn reboot(&self) {
+        self.overlay.lock().unwrap().clear();
+        self.set_state(State::Normal);
+    }
+}
+
+impl BlockDevice for CrashDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        if let Some(data) = self.overlay.lock().unwrap().get(&block_id) {
+            buf.copy_from_slice(data);
+            return;
+        }
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SIZE) as u64))
+            .unwrap();
+        file.read_exact(buf).unwrap();
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        let len = match *state {
+            State::Normal => BLOCK_SIZE,
+            State::Budget(0) => {
+                *state = State::Crashed;
+                self.overlay.lock().unwrap().insert(block_id, buf.to_vec());
+                BLOCK_SIZE / 2
+            }
+            State::Budget(n) => {
+                *state = State::Budget(n - 1);
+                BLOCK_SIZE
+            }
+            State::Crashed => {
+                self.overlay.lock().unwrap().insert(block_id, buf.to_vec());
+                return;
+            }
+        };
+        let mut file = self.file.lock().unwrap();
+        file.seek(SeekFrom::Start((block_id * BLOCK_SIZE) as u64))
+            .unwrap();
+        file.write_all(&buf[..len]).unwrap();
+    }
+}
+
+/// 遍历目录树，检查目录的链接数，记录每个I结点的引用次数和元数据
+fn walk(dir: &Inode, inodes: &mut HashMap<u32, (u32, Metadata)>) {
+    let (entries, _) = dir.read_dir(0, usize::MAX).unwrap();
+    let mut subdirs = 0;
+    for entry in entries {
+        if entry.name == "." || entry.name == ".." {
+            continue;
+        }
+        let inode = dir.find(&entry.name).unwrap();
+        inodes
+            .entry(entry.inode_id)
+            .or_insert((0, inode.metadata()))
+            .0 += 1;
+        if inode.is_dir() {
+            subdirs += 1;
+            walk(&inode, inodes);
+        }
+    }
+    assert_eq!(dir.metadata().nlink, 2 + subdirs);
+}
+
+/// 检查位图与目录树一致：链接数正确，没有泄漏也没有丢失的I结点和数据块
+fn check_consistent(efs: &Arc<spin::Mutex<EasyFileSystem>>, root: &Inode) {
+    let mut inodes = HashMap::new();
+    walk(root, &mut inodes);
+    for (refs, metadata) in inodes.values() {
+        if metadata.type_ == DiskInodeType::File {
+            assert_eq!(metadata.nlink, *refs);
+        } else {
+            assert_eq!(*refs, 1);
+        }
+    }
+    let blocks: u32 = inodes.values().map(|(_, metadata)| metadata.blocks).sum();
+    let blocks = blocks + root.metadata().blocks;
+    let efs = efs.lock();
+    assert_eq!(efs.used_inodes(), inodes.len() + 1);
+    assert_eq!(efs.used_data_blocks(), blocks as usize);
+}
+
+/// 崩溃前执行的操作，每个操作是一个事务
+fn run_ops(root: &Inode) {
+    let new = root.create("new").unwrap();
+    new.write_at(0, &[7u8; 40 * BLOCK_SIZE]);
+    let dir = root.find("dir").unwrap();
+    assert!(dir.rename("old", root, "moved"));
+    assert!(root.unlink("victim"));
+    root.mkdir("sub").unwrap();
+    assert!(new.truncate(100));
+}
+
+/// 根据文件系统的状态判断每个操作是否完成
+fn applied_ops(root: &Inode) -> [bool; 5] {
+    let new = root.find("new");
+    let dir = root.find("dir").unwrap();
+    let moved = root.find("moved").is_some();
+    // 重命名是原子的
+    assert_ne!(moved, dir.find("old").is_some());
+    [
+        new.is_some(),
+        moved,
+        root.find("victim").is_none(),
+        root.find("sub").is_some(),
+        new.map_or(false, |new| new.size() == 100),
+    ]
+}
+
+#[test]
+fn journal_torn_write_test() {
+    let path = format!("{}/journal.img", env!("CARGO_TARGET_TMPDIR"));
+    let device = Arc::new(CrashDevice {
+        file: Mutex::new({
+            let f = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .open(path)
+                .unwrap();
+            f.set_len(TOTAL_BLOCKS as u64 * BLOCK_SIZE as u64).unwrap();
+            f
+        }),
+        state: Mutex::new(State::Normal),
+        overlay: Mutex::new(HashMap::new()),
+    });
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    // 缓存较小，事务中的块必须留在缓存中
+    set_block_cache_size(16);
+    let mut budget = 0;
+    loop {
+        // 初始状态
+        device.reboot();
+        block_cache_clear();
+        let efs = EasyFileSystem::create(block_device.clone(), TOTAL_BLOCKS, 1);
+        let root = EasyFileSystem::root_inode(&efs);
+        let dir = root.mkdir("dir").unwrap();
+        dir.create("old").unwrap().write_at(0, &[1u8; 3000]);
+        root.create("victim")
+            .unwrap()
+            .write_at(0, &[2u8; 20 * BLOCK_SIZE]);
+        root.sync();
+        drop(dir);
+
+        // 第budget + 1次写入时崩溃，之后的写入全部丢失
+        device.set_state(State::Budget(budget));
+        run_ops(&root);
+        root.sync();
+        let crashed = device.crashed();
+        drop(root);
+        drop(efs);
+        device.set_state(State::Crashed);
+        block_cache_clear();
+
+        // 重放日志后检查
+        device.reboot();
+        let efs = EasyFileSystem::open(block_device.clone());
+        let root = EasyFileSystem::root_inode(&efs);
+        check_consistent(&efs, &root);
+        assert_eq!(
+            fsck(block_device.clone(), TOTAL_BLOCKS as usize, false),
+            Ok(Vec::new())
+        );
+        let applied = applied_ops(&root);
+        // 完成的操作是所有操作的一个前缀
+        let done = applied.iter().take_while(|done| **done).count();
+        assert!(applied[done..].iter().all(|done| !done), "{:?}", applied);
+        if !crashed {
+            assert_eq!(done, applied.len());
+            break;
+        }
+        budget += 1;
+    }
+    assert!(budget > 10);
+}
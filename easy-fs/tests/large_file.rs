@@ -0,0 +1,192 @@
+//! 在内存中的块设备上读写用到三级间接索引的大文件，
+//! 并在稀疏的块设备上为超过4GiB的文件建立四级间接索引
+
+use easy_fs::{
+    block_cache_clear, fsck, BlockDevice, DiskInode, DiskInodeType, EasyFileSystem, MAX_FILE_SIZE,
+};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+
+const BLOCK_SIZE: usize = 512;
+const TOTAL_BLOCKS: usize = 20000;
+/// 直接索引的数据块数
+const DIRECT: usize = 23;
+/// 各级间接索引树中第一个数据块的索引编号
+const INDIRECT_BASES: [usize; 4] = [
+    DIRECT,
+    DIRECT + 128,
+    DIRECT + 128 + 128 * 128,
+    DIRECT + 128 + 128 * 128 + 128 * 128 * 128,
+];
+/// 直接索引、一级和二级间接索引的数据块数之和
+const INDIRECT2_BOUND: usize = INDIRECT_BASES[2];
+
+/// 内存中的块设备
+struct MemDevice(Mutex<Vec<u8>>);
+
+impl BlockDevice for MemDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        let start = block_id * BLOCK_SIZE;
+        buf.copy_from_slice(&self.0.lock().unwrap()[start..start + BLOCK_SIZE]);
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let start = block_id * BLOCK_SIZE;
+        self.0.lock().unwrap()[start..start + BLOCK_SIZE].copy_from_slice(buf);
+    }
+}
+
+/// 只保存非零块的块设备，未写入的块读出为零
+#[derive(Default)]
+struct SparseDevice(Mutex<HashMap<usize, Vec<u8>>>);
+
+impl BlockDevice for SparseDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        match self.0.lock().unwrap().get(&block_id) {
+            Some(block) => buf.copy_from_slice(block),
+            None => buf.fill(0),
+        }
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        let mut blocks = self.0.lock().unwrap();
+        if buf.iter().all(|b| *b == 0) {
+            blocks.remove(&block_id);
+        } else {
+            blocks.insert(block_id, buf.to_vec());
+        }
+    }
+}
+
+/// 数据块数为data_blocks时的总块数（包括索引块）
+fn total_blocks(data_blocks: usize) -> usize {
+    let tree = |n: usize, heights: u32| {
+        (1..=heights)
+            .map(|h| (n + 128usize.pow(h) - 1) / 128usize.pow(h))
+            .sum::<usize>()
+    };
+    let levels = INDIRECT_BASES.iter().enumerate().map(|(level, base)| {
+        let n = data_blocks
+            .saturating_sub(*base)
+            .min(128usize.pow(level as u32 + 1));
+        tree(n, level as u32 + 1)
+    });
+    data_blocks + levels.sum::<usize>()
+}
+
+#[test]
+fn large_file_test() {
+    let device = Arc::new(MemDevice(Mutex::new(vec![0; TOTAL_BLOCKS * BLOCK_SIZE])));
+    let block_device: Arc<dyn BlockDevice> = device.clone();
+    let efs = EasyFileSystem::create(block_device.clone(), TOTAL_BLOCKS as u32, 1);
+    let root = EasyFileSystem::root_inode(&efs);
+    let used = || efs.lock().used_data_blocks();
+    let used_before = used();
+
+    // 写入超过二级间接索引容量的文件
+    let size = (INDIRECT2_BOUND + 300) * BLOCK_SIZE + 5;
+    let data: Vec<u8> = (0..size).map(|i| (i / BLOCK_SIZE * 7 + i) as u8).collect();
+    let file = root.create("large").unwrap();
+    assert_eq!(file.write_at(0, &data), size);
+    let metadata = file.metadata();
+    assert_eq!(metadata.size, size as u64);
+    let data_blocks = (size + BLOCK_SIZE - 1) / BLOCK_SIZE;
+    assert_eq!(metadata.blocks as usize, total_blocks(data_blocks));
+    assert_eq!(used(), used_before + total_blocks(data_blocks));
+    let mut buf = vec![0u8; size + 1];
+    assert_eq!(file.read_at(0, &mut buf), size);
+    assert!(buf[..size] == data[..]);
+    root.sync();
+    block_cache_clear();
+    assert_eq!(
+        fsck(block_device.clone(), TOTAL_BLOCKS, false),
+        Ok(Vec::new())
+    );
+
+    // 跨越各级索引的边界截断，回收的块数与索引结构一致
+    for size in [
+        (INDIRECT2_BOUND + 129) * BLOCK_SIZE,
+        (INDIRECT2_BOUND + 1) * BLOCK_SIZE - 3,
+        INDIRECT2_BOUND * BLOCK_SIZE,
+        (DIRECT + 128 + 1) * BLOCK_SIZE,
+        10 * BLOCK_SIZE,
+    ] {
+        assert!(file.truncate(size as u64));
+        let data_blocks = (size + BLOCK_SIZE - 1) / BLOCK_SIZE;
+        assert_eq!(file.metadata().blocks as usize, total_blocks(data_blocks));
+        assert_eq!(used(), used_before + total_blocks(data_blocks));
+        assert_eq!(file.read_at(0, &mut buf), size);
+        assert!(buf[..size] == data[..size]);
+    }
+    // 再次扩展到三级间接索引，扩展的部分填零
+    let size = (INDIRECT2_BOUND + 2) * BLOCK_SIZE;
+    assert_eq!(file.write_at(size - 1, &[9]), 1);
+    assert_eq!(file.read_at(0, &mut buf), size);
+    assert!(buf[..10 * BLOCK_SIZE] == data[..10 * BLOCK_SIZE]);
+    assert!(buf[10 * BLOCK_SIZE..size - 1].iter().all(|b| *b == 0));
+    assert_eq!(buf[size - 1], 9);
+    root.sync();
+    block_cache_clear();
+    assert_eq!(
+        fsck(block_device.clone(), TOTAL_BLOCKS, false),
+        Ok(Vec::new())
+    );
+
+    // 超过最大文件大小
+    assert!(!file.truncate(MAX_FILE_SIZE + 1));
+    assert_eq!(file.write_at(MAX_FILE_SIZE as usize, &[1]), 0);
+
+    // 删除后所有块被回收
+    assert!(root.unlink("large"));
+    drop(file);
+    assert_eq!(used(), used_before);
+    root.sync();
+    block_cache_clear();
+    assert_eq!(
+        fsck(block_device.clone(), TOTAL_BLOCKS, false),
+        Ok(Vec::new())
+    );
+
+    // 跨过4GiB的文件用到四级间接索引。分配数据块太慢，
+    // 直接在I结点上用依次编号的块建立索引，数据块只有写入的几个是非零的
+    let sparse: Arc<dyn BlockDevice> = Arc::new(SparseDevice::default());
+    let mut disk_inode: DiskInode = unsafe { std::mem::zeroed() };
+    disk_inode.initialize(DiskInodeType::File, 0);
+    let four_gib = 1u64 << 32;
+    assert!(MAX_FILE_SIZE > four_gib);
+    let size = four_gib + 3 * BLOCK_SIZE as u64;
+    let data_blocks = (size / BLOCK_SIZE as u64) as usize;
+    assert!(data_blocks > INDIRECT_BASES[3]);
+    let total = total_blocks(data_blocks);
+    assert_eq!(DiskInode::total_blocks(size) as usize, total);
+    // 块编号从1开始，0不是合法的块
+    disk_inode.increase_size(size, (1..=total as u32).collect(), &sparse);
+    // 各级索引边界两侧的数据块编号互不相同
+    let boundaries: Vec<usize> = INDIRECT_BASES
+        .iter()
+        .flat_map(|base| [base - 1, *base])
+        .chain([data_blocks / 2, (four_gib as usize / BLOCK_SIZE) - 1])
+        .chain([four_gib as usize / BLOCK_SIZE, data_blocks - 1])
+        .collect();
+    let ids: BTreeSet<u32> = boundaries
+        .iter()
+        .map(|&i| disk_inode.get_block_id(i as u32, &sparse))
+        .collect();
+    assert_eq!(ids.len(), boundaries.len());
+    assert!(ids.iter().all(|&id| id >= 1 && id as usize <= total));
+    // 跨过4GiB读写
+    let offset = four_gib as usize - 3;
+    assert_eq!(disk_inode.write_at(offset, b"boundary", &sparse), 8);
+    let mut buf = [0u8; 10];
+    assert_eq!(disk_inode.read_at(offset - 1, &mut buf, &sparse), 10);
+    assert_eq!(&buf, b"\0boundary\0");
+    // 截断回三级间接索引之内，再全部回收，回收的块恰好是分配的块
+    let keep = INDIRECT_BASES[3] - 1;
+    let mut freed = disk_inode.decrease_size((keep * BLOCK_SIZE) as u64, &sparse);
+    assert_eq!(freed.len(), total - total_blocks(keep));
+    assert_eq!(disk_inode.indirect()[3], 0);
+    freed.extend(disk_inode.clear_size(&sparse));
+    freed.sort_unstable();
+    assert!(freed.into_iter().eq(1..=total as u32));
+    block_cache_clear();
+}
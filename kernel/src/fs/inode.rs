@@ -3,11 +3,13 @@ use super::File;
 use crate::{drivers::BLOCK_DEVICE, *};
 
 use alloc::sync::Arc;
-use easy_fs::{EasyFileSystem, Inode};
+use easy_fs::{DiskInodeType, EasyFileSystem, Inode, LINK_MAX, MAX_FILE_SIZE, NAME_LENGTH_LIMIT};
+use user_syscall::{Dirent, Errno, FileType, Stat, Whence};
 
 /// OS里操作的索引节点类型，封装了easy-fs中的Inode
 ///
-/// 内核以这个结构来操作一个磁盘文件
+/// 内核以这个结构来操作一个磁盘文件，它也是打开文件描述：
+/// dup和fork得到的描述符共享同一个OSInode，因此共享读写位置
 pub struct OSInode {
     /// 是否可读
     readable: bool,
@@ -19,11 +21,16 @@ pub struct OSInode {
     inode: Cell<Arc<Inode>>,
 }
 
+/// 块缓存的容量（块数）
+const BLOCK_CACHE_SIZE: usize = 256;
+
 /// 全局变量：根节点的索引节点
 pub static ROOT_INODE: Cell<Arc<Inode>> = unsafe { transmute([1u8; size_of::<Arc<Inode>>()]) };
 
 /// 文件系统初始化,创建root inode
 pub fn init() {
+    easy_fs::set_clock(rtc::now);
+    easy_fs::set_block_cache_size(BLOCK_CACHE_SIZE);
     let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
     unsafe {
         (ROOT_INODE.get_mut() as *mut Arc<Inode>).write(Arc::new(EasyFileSystem::root_inode(&efs)));
@@ -37,6 +44,7 @@ pub fn init() {
 
 impl OSInode {
     pub fn new(readable: bool, writable: bool, inode: Arc<Inode>) -> Self {
+        inode.open();
         Self {
             readable,
             writable,
@@ -45,20 +53,49 @@ impl OSInode {
         }
     }
 
-    /// 读取一个I结点索引的所有数据
-    pub fn read_all(&self) -> Vec<u8> {
-        let (offset, inode) = (self.offset.get_mut(), self.inode.get_mut());
-        let mut buffer = [0u8; 512];
-        let mut v: Vec<u8> = Vec::new();
-        loop {
-            let len = inode.read_at(*offset, &mut buffer);
-            if len == 0 {
-                break;
-            }
-            *offset += len;
-            v.extend_from_slice(&buffer[..len]);
-        }
-        v
+    /// 获取封装的easy-fs Inode
+    pub fn inode(&self) -> Arc<Inode> {
+        self.inode.get().clone()
+    }
+
+    /// 按whence移动读写位置，返回新的位置
+    ///
+    /// 新的位置为负数时返回None，位置可以超过文件末尾
+    pub fn seek(&self, offset: isize, whence: Whence) -> Option<usize> {
+        let base = match whence {
+            Whence::Set => 0,
+            Whence::Cur => *self.offset.get(),
+            Whence::End => self.inode.get().size(),
+        };
+        let pos = (base as isize)
+            .checked_add(offset)
+            .filter(|pos| *pos >= 0)?;
+        *self.offset.get_mut() = pos as usize;
+        Some(pos as usize)
+    }
+
+    /// 从读写位置开始读取至多max个目录项，读写位置为目录项槽位编号
+    ///
+    /// 不是目录时返回None
+    pub fn read_dir(&self, max: usize) -> Option<Vec<Dirent>> {
+        let offset = self.offset.get_mut();
+        let (entries, next) = self.inode.get().read_dir(*offset, max)?;
+        *offset = next;
+        Some(
+            entries
+                .into_iter()
+                .map(|entry| {
+                    let mut dirent = Dirent {
+                        ino: entry.inode_id as u64,
+                        file_type: file_type(entry.type_),
+                        name_len: entry.name.len() as u32,
+                        ..Dirent::default()
+                    };
+                    dirent.name[..entry.name.len()].copy_from_slice(entry.name.as_bytes());
+                    dirent
+                })
+                .collect(),
+        )
     }
 }
 
@@ -71,13 +108,15 @@ bitflags::bitflags! {
         const CREATE = 1 << 9;
         /// 创建时清空
         const TRUNC = 1 << 10;
+        /// 非阻塞读写
+        const NONBLOCK = 1 << 11;
     }
 }
 
 impl OpenFlags {
     /// 获取读写权限
     pub fn read_write(&self) -> (bool, bool) {
-        if self.is_empty() {
+        if !self.intersects(Self::WRONLY | Self::RDWR) {
             (true, false)
         } else if self.contains(Self::WRONLY) {
             (false, true)
@@ -87,26 +126,238 @@ impl OpenFlags {
     }
 }
 
-/// 根据OpenFlags打开根节点下的文件
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Rc<OSInode>> {
+/// 将磁盘上的I结点类型转换为文件类型
+fn file_type(type_: DiskInodeType) -> FileType {
+    match type_ {
+        DiskInodeType::File => FileType::File,
+        DiskInodeType::Directory => FileType::Directory,
+        DiskInodeType::Fifo => FileType::Fifo,
+    }
+}
+
+/// 获取I结点的元数据
+pub fn inode_stat(inode: &Inode) -> Stat {
+    let metadata = inode.metadata();
+    Stat {
+        ino: metadata.inode_id as u64,
+        file_type: file_type(metadata.type_),
+        nlink: metadata.nlink,
+        size: metadata.size,
+        blocks: metadata.blocks as u64,
+        ctime: metadata.ctime as u64,
+        mtime: metadata.mtime as u64,
+    }
+}
+
+/// 获取路径为path的文件的元数据，文件不存在返回ENOENT
+pub fn stat_file(path: &str) -> Result<Stat, Errno> {
+    ROOT_INODE
+        .lookup(path)
+        .map(|inode| inode_stat(&inode))
+        .ok_or(Errno::ENOENT)
+}
+
+/// 将路径拆分为父目录路径和最后一级文件名
+///
+/// 如"/bin/shell"拆分为("/bin", "shell")，"shell"拆分为("", "shell")
+fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches('/');
+    match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(pos) => (&path[..pos], &path[pos + 1..]),
+        None => ("", path),
+    }
+}
+
+/// 查找路径为path的文件的父目录，返回（父目录，文件名）
+///
+/// 父目录不存在返回ENOENT，不是目录返回ENOTDIR，文件名过长返回ENAMETOOLONG
+fn lookup_parent(path: &str) -> Result<(Arc<Inode>, &str), Errno> {
+    let (parent, name) = split_path(path);
+    if name.len() > NAME_LENGTH_LIMIT {
+        return Err(Errno::ENAMETOOLONG);
+    }
+    let parent = ROOT_INODE.lookup(parent).ok_or(Errno::ENOENT)?;
+    if !parent.is_dir() {
+        return Err(Errno::ENOTDIR);
+    }
+    Ok((parent, name))
+}
+
+/// 在目录parent中新建类型为type_的I结点失败时的错误码
+///
+/// 新建目录时parent的链接数已达上限返回EMLINK，空闲的I结点或数据块不足返回ENOSPC，
+/// 否则返回errno
+fn create_error(parent: &Inode, type_: DiskInodeType, errno: Errno) -> Errno {
+    if type_ == DiskInodeType::Directory && parent.nlink() >= LINK_MAX as u32 {
+        Errno::EMLINK
+    } else if !parent.has_space_for(Some(type_)) {
+        Errno::ENOSPC
+    } else {
+        errno
+    }
+}
+
+/// 根据OpenFlags打开路径为path的文件，相对路径从根目录开始解析
+///
+/// 文件不存在返回ENOENT，以可写方式打开目录返回EISDIR，创建时空间不足返回ENOSPC
+pub fn open_file(path: &str, flags: OpenFlags) -> Result<Arc<OSInode>, Errno> {
     let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
-            inode.clear();
-            Some(Rc::new(OSInode::new(readable, writable, inode)))
-        } else {
-            // create file
-            ROOT_INODE
+    let inode = match ROOT_INODE.lookup(path) {
+        Some(inode) => {
+            if inode.is_dir() {
+                if writable {
+                    return Err(Errno::EISDIR);
+                }
+            } else if flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC) {
+                // 创建已存在的文件时清空
+                inode.clear();
+            }
+            inode
+        }
+        None if flags.contains(OpenFlags::CREATE) => {
+            let (parent, name) = lookup_parent(path)?;
+            parent
                 .create(name)
-                .map(|inode| Rc::new(OSInode::new(readable, writable, inode)))
+                .ok_or_else(|| create_error(&parent, DiskInodeType::File, Errno::EEXIST))?
+        }
+        None => return Err(Errno::ENOENT),
+    };
+    Ok(Arc::new(OSInode::new(readable, writable, inode)))
+}
+
+/// 创建路径为path的目录
+///
+/// 已存在返回EEXIST，父目录不存在返回ENOENT，空间不足返回ENOSPC，
+/// 父目录的链接数已达上限返回EMLINK
+pub fn make_dir(path: &str) -> Result<(), Errno> {
+    if ROOT_INODE.lookup(path).is_some() {
+        return Err(Errno::EEXIST);
+    }
+    let (parent, name) = lookup_parent(path)?;
+    parent
+        .mkdir(name)
+        .map(|_| ())
+        .ok_or_else(|| create_error(&parent, DiskInodeType::Directory, Errno::EINVAL))
+}
+
+/// 创建路径为path的命名管道
+///
+/// 已存在返回EEXIST，父目录不存在返回ENOENT，空间不足返回ENOSPC
+pub fn make_fifo(path: &str) -> Result<(), Errno> {
+    if ROOT_INODE.lookup(path).is_some() {
+        return Err(Errno::EEXIST);
+    }
+    let (parent, name) = lookup_parent(path)?;
+    parent
+        .mkfifo(name)
+        .map(|_| ())
+        .ok_or_else(|| create_error(&parent, DiskInodeType::Fifo, Errno::EINVAL))
+}
+
+/// 删除路径为path的空目录
+///
+/// 不存在返回ENOENT，不是目录返回ENOTDIR，目录非空返回ENOTEMPTY，
+/// 删除`.`、`..`或根目录返回EINVAL
+pub fn remove_dir(path: &str) -> Result<(), Errno> {
+    let (parent, name) = lookup_parent(path)?;
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(Errno::EINVAL);
+    }
+    let dir = parent.find(name).ok_or(Errno::ENOENT)?;
+    if !dir.is_dir() {
+        return Err(Errno::ENOTDIR);
+    }
+    if parent.rmdir(name) {
+        Ok(())
+    } else {
+        Err(Errno::ENOTEMPTY)
+    }
+}
+
+/// 将块缓存中的所有脏块写回磁盘
+pub fn sync_all() {
+    ROOT_INODE.sync();
+}
+
+/// 将I结点截断或扩展到len字节
+///
+/// 是目录返回EISDIR，是命名管道返回EINVAL，超过最大文件大小返回EFBIG，
+/// 空闲数据块不足返回ENOSPC
+pub fn truncate_inode(inode: &Inode, len: usize) -> Result<(), Errno> {
+    if inode.is_dir() {
+        return Err(Errno::EISDIR);
+    }
+    if inode.is_fifo() {
+        return Err(Errno::EINVAL);
+    }
+    if len as u64 > MAX_FILE_SIZE {
+        return Err(Errno::EFBIG);
+    }
+    if !inode.truncate(len as u64) {
+        return Err(Errno::ENOSPC);
+    }
+    Ok(())
+}
+
+/// 将路径为path的文件截断或扩展到len字节，文件不存在返回ENOENT
+pub fn truncate_file(path: &str, len: usize) -> Result<(), Errno> {
+    let inode = ROOT_INODE.lookup(path).ok_or(Errno::ENOENT)?;
+    truncate_inode(&inode, len)
+}
+
+/// 删除路径为path的文件
+///
+/// 不存在返回ENOENT，是目录返回EISDIR
+pub fn unlink_file(path: &str) -> Result<(), Errno> {
+    let (parent, name) = lookup_parent(path)?;
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(Errno::EISDIR);
+    }
+    let inode = parent.find(name).ok_or(Errno::ENOENT)?;
+    if inode.is_dir() {
+        return Err(Errno::EISDIR);
+    }
+    if parent.unlink(name) {
+        Ok(())
+    } else {
+        Err(Errno::ENOENT)
+    }
+}
+
+/// 将路径为old_path的文件或目录移动到new_path，new_path已存在时将其替换
+///
+/// 源不存在返回ENOENT，用目录替换文件返回ENOTDIR，用文件替换目录返回EISDIR，
+/// 替换非空目录返回ENOTEMPTY，空间不足返回ENOSPC，目标目录的链接数已达上限返回EMLINK，
+/// 移动`.`、`..`或将目录移动到自身的子目录中返回EINVAL
+pub fn rename_file(old_path: &str, new_path: &str) -> Result<(), Errno> {
+    let (old_parent, old_name) = lookup_parent(old_path)?;
+    let (new_parent, new_name) = lookup_parent(new_path)?;
+    let src = old_parent.find(old_name).ok_or(Errno::ENOENT)?;
+    let dst = new_parent.find(new_name);
+    if let Some(dst) = &dst {
+        match (src.is_dir(), dst.is_dir()) {
+            (true, false) => return Err(Errno::ENOTDIR),
+            (false, true) => return Err(Errno::EISDIR),
+            _ => {}
         }
+    }
+    if old_parent.rename(old_name, &new_parent, new_name) {
+        Ok(())
+    } else if dst.as_ref().map_or(false, |dst| dst.is_dir()) {
+        Err(Errno::ENOTEMPTY)
+    } else if dst.is_none() && !new_parent.has_space_for(None) {
+        Err(Errno::ENOSPC)
+    } else if dst.is_none() && src.is_dir() && new_parent.nlink() >= LINK_MAX as u32 {
+        Err(Errno::EMLINK)
     } else {
-        ROOT_INODE.find(name).map(|inode| {
-            if flags.contains(OpenFlags::TRUNC) {
-                inode.clear();
-            }
-            Rc::new(OSInode::new(readable, writable, inode))
-        })
+        Err(Errno::EINVAL)
+    }
+}
+
+impl Drop for OSInode {
+    fn drop(&mut self) {
+        self.inode.get().close();
     }
 }
 
@@ -132,4 +383,8 @@ impl File for OSInode {
         *offset += n;
         n
     }
+
+    fn stat(&self) -> Stat {
+        inode_stat(self.inode.get())
+    }
 }
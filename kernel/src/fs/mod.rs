@@ -1,10 +1,17 @@
 //! 内核中的文件抽象
 
+use alloc::sync::Arc;
+use core::task::Waker;
 use downcast_rs::impl_downcast;
 use downcast_rs::DowncastSync;
+use user_syscall::{PollEvents, PollFd, Stat};
 
-pub use inode::{init, open_file, OSInode, OpenFlags, ROOT_INODE};
+pub use inode::{
+    init, make_dir, make_fifo, open_file, remove_dir, rename_file, stat_file, sync_all,
+    truncate_file, truncate_inode, unlink_file, OSInode, OpenFlags, ROOT_INODE,
+};
 pub use pipe::*;
+pub use socket::*;
 pub use stdio::*;
 
 /// OS看到的文件抽象，只关心字节流的读写
@@ -17,12 +24,49 @@ pub trait File: Sync + Send + DowncastSync {
     fn read(&self, buf: &mut [u8]) -> usize;
     /// 从buf中写入文件，返回实际写入的字节数
     fn write(&self, buf: &[u8]) -> usize;
+    /// 文件元数据
+    fn stat(&self) -> Stat;
+    /// 是否为非阻塞模式（NONBLOCK）
+    fn nonblocking(&self) -> bool {
+        false
+    }
+    /// 设置非阻塞模式，读写从不阻塞的文件忽略此标志
+    fn set_nonblocking(&self, _nonblock: bool) {}
+    /// 当前就绪的事件，默认可读的文件总能读取，可写的文件总能写入
+    fn poll(&self) -> PollEvents {
+        let mut events = PollEvents::empty();
+        events.set(PollEvents::IN, self.readable());
+        events.set(PollEvents::OUT, self.writable());
+        events
+    }
+    /// 注册唤醒器，在events中的事件可能就绪时唤醒，总是就绪的文件无需实现
+    fn register_waker(&self, _waker: &Waker, _events: PollEvents) {}
 }
 impl_downcast!(sync File);
 
+/// 检查files中每个文件的就绪事件，写入fds中对应的revents
+///
+/// files[i]为fds[i]对应的文件，不存在时revents为NVAL；
+/// 返回revents不为空的文件个数
+pub fn poll_files(files: &[Option<Arc<dyn File>>], fds: &mut [PollFd]) -> usize {
+    let mut ready = 0;
+    for (file, fd) in files.iter().zip(fds.iter_mut()) {
+        fd.revents = match file {
+            Some(file) => file.poll() & (fd.events | PollEvents::ERR | PollEvents::HUP),
+            None => PollEvents::NVAL,
+        };
+        if !fd.revents.is_empty() {
+            ready += 1;
+        }
+    }
+    ready
+}
+
 /// 内核使用的Inode类型
 mod inode;
 // / 管道抽象
 mod pipe;
+/// 本地套接字
+mod socket;
 // /// 标准输入输出抽象
 mod stdio;
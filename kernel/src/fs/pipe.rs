@@ -1,100 +1,328 @@
 //! 管道抽象
 
-use super::File;
+use super::{File, OpenFlags};
 use crate::future::executor;
 use crate::*;
-use alloc::sync::Arc;
-use alloc::sync::Weak;
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::task::Waker;
-use future::futures::fs::WaitForPipeBuffer;
+use easy_fs::Inode;
+use future::futures::fs::{WaitForPipe, WaitForPipeOpen};
+use mm::MemorySet;
+use user_syscall::{Errno, FileType, PollEvents, Stat};
+
+/// 管道缓冲区的容量（字节）
+pub const PIPE_BUFFER_SIZE: usize = 4096;
 
 /// 管道的一端
 pub struct Pipe {
     /// 是否是写端
     writable: bool,
+    /// 是否为非阻塞模式
+    nonblock: AtomicBool,
     /// 缓冲区
     buf: Arc<Cell<PipeBuffer>>,
-    /// 写端持有读端的唤醒器，当写端被关闭时唤醒读端
-    waker: Cell<Option<Waker>>,
 }
 
 impl Pipe {
-    /// 注册一个唤醒器，只能是写端
-    pub fn add_waker(&self, waker: Waker) {
-        let _ = self.waker.get_mut().insert(waker);
-    }
-
-    /// 异步读取管道，将读取的字节写回用户态
-    pub fn async_read(
-        &self,
-        pipe_end: Arc<Pipe>,
-        buf_ptr: usize,
-        buf_len: usize,
-        result_ptr: usize,
-    ) {
-        // 等待写端关闭，读入到buf中
+    /// 创建缓冲区buf的一端，登记到缓冲区中，唤醒等待这一端打开的另一端
+    fn new(writable: bool, buf: Arc<Cell<PipeBuffer>>) -> Arc<Self> {
+        let pipe_buf = buf.get_mut();
+        if writable {
+            pipe_buf.writers += 1;
+            pipe_buf.write_opens += 1;
+            wake_all(&mut pipe_buf.read_wakers);
+        } else {
+            pipe_buf.readers += 1;
+            pipe_buf.read_opens += 1;
+            wake_all(&mut pipe_buf.write_wakers);
+        }
+        Arc::new(Self {
+            writable,
+            nonblock: AtomicBool::new(false),
+            buf,
+        })
+    }
+
+    /// 异步读取管道，将读取的字节写回用户态，读取的字节数写入result_ptr
+    ///
+    /// 缓冲区中有数据或写端已全部关闭（此时读到0字节）时直接完成，
+    /// 否则当前线程进入等待，由协程在数据到达后完成读取；非阻塞模式下返回EAGAIN
+    pub fn async_read(self: &Arc<Self>, buf_ptr: usize, buf_len: usize, result_ptr: usize) {
         let current_thread = current_thread();
-        // 进入等待
+        if self.buf.can_read() {
+            read_to_user(self, &current_thread, buf_ptr, buf_len, result_ptr);
+            return;
+        }
+        if self.nonblocking() {
+            let memory_set = current_thread.proc().unwrap().memory_set();
+            memory_set.write_user_usize(result_ptr, Errno::EAGAIN.as_ret());
+            return;
+        }
         current_thread.set_state(ThreadState::Waiting);
-        // 生成协程
         executor::spawn(wait_for_pipe_and_read(
-            pipe_end,
-            self.buf.clone(),
-            current_thread.clone(),
+            self.clone(),
+            current_thread,
             buf_ptr,
             buf_len,
             result_ptr,
         ));
     }
+
+    /// 异步写入管道，写入的字节数或错误码写入result_ptr
+    ///
+    /// 缓冲区满时当前线程进入等待，由协程在读端取走数据后继续写入，直到全部写入；
+    /// 读端已全部关闭时停止写入，一个字节都没有写入时返回EPIPE；
+    /// 非阻塞模式下只写入缓冲区放得下的部分，一个字节都放不下时返回EAGAIN
+    pub fn async_write(self: &Arc<Self>, buf_ptr: usize, buf_len: usize, result_ptr: usize) {
+        let current_thread = current_thread();
+        let memory_set = current_thread.proc().unwrap().memory_set();
+        if self.buf.readers == 0 {
+            memory_set.write_user_usize(result_ptr, write_result(0, buf_len));
+            return;
+        }
+        let written = write_from_user(self, &memory_set, buf_ptr, buf_len, 0);
+        if written == buf_len {
+            memory_set.write_user_usize(result_ptr, write_result(written, buf_len));
+            return;
+        }
+        if self.nonblocking() {
+            let result = if written == 0 {
+                Errno::EAGAIN.as_ret()
+            } else {
+                written
+            };
+            memory_set.write_user_usize(result_ptr, result);
+            return;
+        }
+        current_thread.set_state(ThreadState::Waiting);
+        executor::spawn(wait_for_pipe_and_write(
+            self.clone(),
+            current_thread,
+            buf_ptr,
+            buf_len,
+            written,
+            result_ptr,
+        ));
+    }
+}
+
+/// 读取管道到用户地址buf_ptr处，将读取的字节数写入result_ptr
+///
+/// 线程所属的进程已经退出时不读取，返回false
+fn read_to_user(
+    pipe: &Pipe,
+    thread: &Thread,
+    buf_ptr: usize,
+    buf_len: usize,
+    result_ptr: usize,
+) -> bool {
+    let memory_set = match thread.proc() {
+        Some(proc) => proc.memory_set(),
+        None => return false,
+    };
+    let mut buf = vec![0u8; buf_len.min(PIPE_BUFFER_SIZE)];
+    let read_size = pipe.read(&mut buf);
+    memory_set.copy_to_user(buf_ptr, &buf[..read_size]);
+    memory_set.write_user_usize(result_ptr, read_size);
+    true
 }
 
-/// 等待写端关闭后读入buf且将读取字节数写入用户态
+/// 将用户地址buf_ptr处从written开始的数据写入管道，直到缓冲区满
+///
+/// 返回总共写入的字节数
+fn write_from_user(
+    pipe: &Pipe,
+    memory_set: &MemorySet,
+    buf_ptr: usize,
+    buf_len: usize,
+    mut written: usize,
+) -> usize {
+    let mut buf = [0u8; 256];
+    while written < buf_len {
+        let len = (buf_len - written).min(buf.len()).min(pipe.buf.free());
+        if len == 0 {
+            break;
+        }
+        memory_set.copy_from_user(buf_ptr + written, &mut buf[..len]);
+        written += pipe.write(&buf[..len]);
+    }
+    written
+}
+
+/// 写管道的结果：读端关闭时一个字节都没有写入则为EPIPE
+fn write_result(written: usize, buf_len: usize) -> usize {
+    if written == 0 && buf_len > 0 {
+        Errno::EPIPE.as_ret()
+    } else {
+        written
+    }
+}
+
+/// 等待管道中有数据或写端全部关闭后读入用户态
 async fn wait_for_pipe_and_read(
-    pipe_end: Arc<Pipe>,
-    pipe_buffer: Arc<Cell<PipeBuffer>>,
+    pipe: Arc<Pipe>,
+    thread: Arc<Thread>,
+    buf_ptr: usize,
+    buf_len: usize,
+    result_ptr: usize,
+) {
+    WaitForPipe::new(pipe.buf.clone(), false).await;
+    // 等待期间进程可能已经退出，此时不再读取
+    if read_to_user(&pipe, &thread, buf_ptr, buf_len, result_ptr) {
+        // 等待线程就绪
+        thread.set_state(ThreadState::Runnable);
+    }
+}
+
+/// 等待管道中有空间后继续写入，直到全部写入或读端全部关闭
+async fn wait_for_pipe_and_write(
+    pipe: Arc<Pipe>,
     thread: Arc<Thread>,
     buf_ptr: usize,
     buf_len: usize,
+    mut written: usize,
     result_ptr: usize,
 ) {
-    let wait_for_pipebuffer = WaitForPipeBuffer::new(pipe_buffer);
-    // 确保写端已经关闭
-    wait_for_pipebuffer.await;
-    thread.proc().unwrap().memory_set().activate();
-    // 解析buf
-    let buf_ptr = buf_ptr as *mut u8;
-    let buf = unsafe { core::slice::from_raw_parts_mut(buf_ptr, buf_len) };
-    let read_size = pipe_end.read(buf);
-    // println!("result_ptr: {:x}, read_size: {}", result_ptr, read_size);
-    // 写回用户态
-    let result_ptr = result_ptr as *mut usize;
-    unsafe { *result_ptr = read_size }
-    // 等待线程就绪
-    thread.set_state(ThreadState::Runnable);
+    while written < buf_len {
+        WaitForPipe::new(pipe.buf.clone(), true).await;
+        if pipe.buf.readers == 0 {
+            break;
+        }
+        // 等待期间进程可能已经退出，此时丢弃剩余的数据
+        let memory_set = match thread.proc() {
+            Some(proc) => proc.memory_set(),
+            None => return,
+        };
+        written = write_from_user(&pipe, &memory_set, buf_ptr, buf_len, written);
+    }
+    if let Some(proc) = thread.proc() {
+        let result = write_result(written, buf_len);
+        proc.memory_set().write_user_usize(result_ptr, result);
+        thread.set_state(ThreadState::Runnable);
+    }
 }
 
-/// 管道缓冲区
+/// 管道缓冲区，定长的环形队列
 pub struct PipeBuffer {
     /// 缓冲区
-    buf: Vec<u8>,
-    /// 写端的一个弱引用
-    write_end: Weak<Pipe>,
+    buf: [u8; PIPE_BUFFER_SIZE],
+    /// 队首位置
+    head: usize,
+    /// 缓冲区中的字节数
+    len: usize,
+    /// 未关闭的读端个数
+    readers: usize,
+    /// 未关闭的写端个数
+    writers: usize,
+    /// 读端被打开的次数
+    read_opens: usize,
+    /// 写端被打开的次数
+    write_opens: usize,
+    /// 等待数据或写端关闭的唤醒器
+    read_wakers: Vec<Waker>,
+    /// 等待空间或读端关闭的唤醒器
+    write_wakers: Vec<Waker>,
 }
 
 impl PipeBuffer {
-    /// 尝试获得写端的强引用
-    pub fn write_end(&self) -> Option<Arc<Pipe>> {
-        self.write_end.upgrade()
+    fn new() -> Self {
+        Self {
+            buf: [0; PIPE_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+            readers: 0,
+            writers: 0,
+            read_opens: 0,
+            write_opens: 0,
+            read_wakers: Vec::new(),
+            write_wakers: Vec::new(),
+        }
+    }
+
+    /// 空闲空间的字节数
+    pub fn free(&self) -> usize {
+        PIPE_BUFFER_SIZE - self.len
+    }
+
+    /// 读取是否不会阻塞，即有数据或写端已全部关闭
+    pub fn can_read(&self) -> bool {
+        self.len > 0 || self.writers == 0
+    }
+
+    /// 写入是否不会阻塞，即有空间或读端已全部关闭
+    pub fn can_write(&self) -> bool {
+        self.len < PIPE_BUFFER_SIZE || self.readers == 0
+    }
+
+    /// 写端（write为true）或读端被打开的次数
+    pub fn opens(&self, write: bool) -> usize {
+        if write {
+            self.write_opens
+        } else {
+            self.read_opens
+        }
+    }
+
+    /// 注册一个唤醒器，在can_read（write为false）或can_write（write为true）成立时唤醒
+    pub fn add_waker(&mut self, waker: Waker, write: bool) {
+        if write {
+            self.write_wakers.push(waker);
+        } else {
+            self.read_wakers.push(waker);
+        }
+    }
+
+    /// 从队首取出至多buf.len()个字节，唤醒等待空间的写者
+    fn pop(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.len);
+        for byte in buf[..n].iter_mut() {
+            *byte = self.buf[self.head];
+            self.head = (self.head + 1) % PIPE_BUFFER_SIZE;
+        }
+        self.len -= n;
+        if n > 0 {
+            wake_all(&mut self.write_wakers);
+        }
+        n
+    }
+
+    /// 在队尾放入尽可能多的字节，唤醒等待数据的读者
+    fn push(&mut self, buf: &[u8]) -> usize {
+        let n = buf.len().min(self.free());
+        for &byte in buf[..n].iter() {
+            self.buf[(self.head + self.len) % PIPE_BUFFER_SIZE] = byte;
+            self.len += 1;
+        }
+        if n > 0 {
+            wake_all(&mut self.read_wakers);
+        }
+        n
+    }
+}
+
+/// 唤醒并清空wakers中的所有唤醒器
+fn wake_all(wakers: &mut Vec<Waker>) {
+    for waker in wakers.drain(..) {
+        waker.wake();
     }
 }
 
 impl Drop for Pipe {
-    // 写端析构时唤醒阻塞的读端
+    /// 最后一个写端关闭时唤醒读者（读到文件末尾），最后一个读端关闭时唤醒写者（EPIPE）
     fn drop(&mut self) {
+        let pipe_buf = self.buf.get_mut();
         if self.writable {
-            let waker = self.waker.get().as_ref();
-            if let Some(waker) = waker {
-                waker.wake_by_ref();
+            pipe_buf.writers -= 1;
+            if pipe_buf.writers == 0 {
+                wake_all(&mut pipe_buf.read_wakers);
+            }
+        } else {
+            pipe_buf.readers -= 1;
+            if pipe_buf.readers == 0 {
+                wake_all(&mut pipe_buf.write_wakers);
             }
         }
     }
@@ -104,24 +332,72 @@ impl Drop for Pipe {
 ///
 /// 返回（读端，写端）的引用
 pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
-    let buf = Arc::new(Cell::new(PipeBuffer {
-        buf: Vec::new(),
-        write_end: Weak::new(),
-    }));
-    let write_end = Arc::new(Pipe {
-        writable: true,
-        buf: buf.clone(),
-        waker: Cell::new(None),
-    });
-    buf.get_mut().write_end = Arc::downgrade(&write_end);
-    let read_end = Arc::new(Pipe {
-        writable: false,
-        buf: buf,
-        waker: Cell::new(None),
-    });
+    let buf = Arc::new(Cell::new(PipeBuffer::new()));
+    let write_end = Pipe::new(true, buf.clone());
+    let read_end = Pipe::new(false, buf);
     (read_end, write_end)
 }
 
+/// 正在使用的FIFO的管道缓冲区，以I结点编号为键
+static FIFO_BUFFERS: Cell<BTreeMap<u32, Weak<Cell<PipeBuffer>>>> = Cell::new(BTreeMap::new());
+
+/// 以flags打开FIFO的一端加入当前进程的文件表，fd写入fd_ptr
+///
+/// 打开同一个FIFO的所有端共享一个管道缓冲区，所有端都关闭后缓冲区中的数据被丢弃。
+/// 只读打开时等待写端打开，只写打开时等待读端打开，此时当前线程进入等待；
+/// 非阻塞模式下不等待，但只写打开时没有读端返回ENXIO。读写打开返回EINVAL
+pub fn open_fifo(inode: &Inode, flags: OpenFlags, fd_ptr: usize) -> Result<(), Errno> {
+    let writable = match flags.read_write() {
+        (true, false) => false,
+        (false, true) => true,
+        _ => return Err(Errno::EINVAL),
+    };
+    let nonblock = flags.contains(OpenFlags::NONBLOCK);
+    let inode_id = inode.inode_id();
+    let buf = match FIFO_BUFFERS.get().get(&inode_id).and_then(Weak::upgrade) {
+        Some(buf) => buf,
+        None => {
+            let buf = Arc::new(Cell::new(PipeBuffer::new()));
+            FIFO_BUFFERS
+                .get_mut()
+                .insert(inode_id, Arc::downgrade(&buf));
+            buf
+        }
+    };
+    if writable && nonblock && buf.readers == 0 {
+        return Err(Errno::ENXIO);
+    }
+    // 另一端已打开的次数，之后有另一端打开时不再等待
+    let peer_opens = buf.opens(!writable);
+    let peer_open = if writable {
+        buf.readers > 0
+    } else {
+        buf.writers > 0
+    };
+    let pipe = Pipe::new(writable, buf);
+    pipe.set_nonblocking(nonblock);
+    let current_thread = current_thread();
+    let current_proc = current_thread.proc().unwrap();
+    if peer_open || nonblock {
+        let fd = current_proc.add_file(pipe);
+        current_proc.memory_set().write_user_usize(fd_ptr, fd);
+        return Ok(());
+    }
+    current_thread.set_state(ThreadState::Waiting);
+    executor::spawn(async move {
+        WaitForPipeOpen::new(pipe.buf.clone(), !writable, peer_opens).await;
+        // 等待期间进程可能已经退出，此时关闭打开的一端
+        let proc = match current_thread.proc() {
+            Some(proc) => proc,
+            None => return,
+        };
+        let fd = proc.add_file(pipe);
+        proc.memory_set().write_user_usize(fd_ptr, fd);
+        current_thread.set_state(ThreadState::Runnable);
+    });
+    Ok(())
+}
+
 impl File for Pipe {
     fn readable(&self) -> bool {
         !self.writable
@@ -131,29 +407,52 @@ impl File for Pipe {
         self.writable
     }
 
-    /// 从管道的缓冲区读取到buf中
-    ///
-    /// 此时假设写端已经关闭，同步读取
+    /// 从管道的缓冲区取出数据到buf中，不阻塞
     fn read(&self, buf: &mut [u8]) -> usize {
         assert!(self.readable());
-        let pipe_buf = self.buf.buf.as_slice();
-        let copy_len = buf.len().min(pipe_buf.len());
-        // println!(
-        //     "pipe_buf len: {}; buf len: {}, copy_len: {}",
-        //     pipe_buf.len(),
-        //     buf.len(),
-        //     copy_len
-        // );
-        let dst = &mut buf[..copy_len];
-        dst.copy_from_slice(pipe_buf);
-        // println!("dst len: {}", dst.len());
-        copy_len
-    }
-
-    /// 拓展管道的缓冲区
+        self.buf.get_mut().pop(buf)
+    }
+
+    /// 将buf中的数据放入管道的缓冲区，不阻塞，缓冲区满时只写入一部分
     fn write(&self, buf: &[u8]) -> usize {
         assert!(self.writable());
-        self.buf.get_mut().buf.extend(buf.iter().copied());
-        buf.len()
+        self.buf.get_mut().push(buf)
+    }
+
+    /// 管道的大小为缓冲区中的字节数
+    fn stat(&self) -> Stat {
+        Stat {
+            file_type: FileType::Fifo,
+            nlink: 1,
+            size: self.buf.len as u64,
+            ..Stat::default()
+        }
+    }
+
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+
+    fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+
+    /// 读端有数据时可读，写端全部关闭时为HUP；
+    /// 写端有空间时可写，读端全部关闭时为ERR
+    fn poll(&self) -> PollEvents {
+        let pipe_buf = self.buf.get();
+        let mut events = PollEvents::empty();
+        if self.writable {
+            events.set(PollEvents::OUT, pipe_buf.free() > 0 && pipe_buf.readers > 0);
+            events.set(PollEvents::ERR, pipe_buf.readers == 0);
+        } else {
+            events.set(PollEvents::IN, pipe_buf.len > 0);
+            events.set(PollEvents::HUP, pipe_buf.writers == 0);
+        }
+        events
+    }
+
+    fn register_waker(&self, waker: &Waker, _events: PollEvents) {
+        self.buf.get_mut().add_waker(waker.clone(), self.writable);
     }
 }
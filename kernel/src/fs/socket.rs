@@ -0,0 +1,270 @@
+//! 本地流式套接字
+//!
+//! 套接字的名字位于与文件系统无关的抽象命名空间中。
+//! 已连接的套接字由两个方向的管道组成，读写、非阻塞模式和poll的行为与管道相同
+
+use super::{make_pipe, File, Pipe};
+use crate::*;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Waker;
+use user_syscall::{Errno, FileType, PollEvents, Stat};
+
+/// 套接字名字的最大长度
+pub const SOCKET_NAME_MAX: usize = 108;
+
+/// 已绑定的套接字，以名字为键
+static SOCKET_NAMES: Cell<BTreeMap<String, Weak<Socket>>> = Cell::new(BTreeMap::new());
+
+/// 套接字的状态
+enum SocketState {
+    /// 新建的套接字
+    Unbound,
+    /// 已绑定名字
+    Bound(String),
+    /// 正在监听连接
+    Listening {
+        /// 绑定的名字
+        name: String,
+        /// 等待接受的连接的最大个数
+        backlog: usize,
+        /// 等待接受的连接
+        queue: VecDeque<Arc<Socket>>,
+        /// 等待连接到达的唤醒器
+        wakers: Vec<Waker>,
+    },
+    /// 已连接
+    Connected {
+        /// 接收数据的管道读端
+        rx: Arc<Pipe>,
+        /// 发送数据的管道写端
+        tx: Arc<Pipe>,
+    },
+}
+
+/// 本地流式套接字
+pub struct Socket {
+    /// 是否为非阻塞模式
+    nonblock: AtomicBool,
+    /// 状态
+    state: Cell<SocketState>,
+}
+
+/// 检查套接字的名字，为空返回EINVAL，过长返回ENAMETOOLONG
+fn check_name(name: &str) -> Result<(), Errno> {
+    if name.is_empty() {
+        Err(Errno::EINVAL)
+    } else if name.len() > SOCKET_NAME_MAX {
+        Err(Errno::ENAMETOOLONG)
+    } else {
+        Ok(())
+    }
+}
+
+impl Socket {
+    /// 新建一个未绑定的套接字
+    pub fn new() -> Arc<Self> {
+        Self::with_state(SocketState::Unbound)
+    }
+
+    fn with_state(state: SocketState) -> Arc<Self> {
+        Arc::new(Self {
+            nonblock: AtomicBool::new(false),
+            state: Cell::new(state),
+        })
+    }
+
+    /// 将套接字绑定到名字name
+    ///
+    /// 已绑定、正在监听或已连接返回EINVAL，名字已被使用返回EADDRINUSE
+    pub fn bind(self: &Arc<Self>, name: String) -> Result<(), Errno> {
+        check_name(&name)?;
+        if !matches!(self.state.get(), SocketState::Unbound) {
+            return Err(Errno::EINVAL);
+        }
+        let names = SOCKET_NAMES.get_mut();
+        if names.get(&name).and_then(Weak::upgrade).is_some() {
+            return Err(Errno::EADDRINUSE);
+        }
+        names.insert(name.clone(), Arc::downgrade(self));
+        *self.state.get_mut() = SocketState::Bound(name);
+        Ok(())
+    }
+
+    /// 开始监听连接，backlog为等待接受的连接的最大个数，至少为1
+    ///
+    /// 未绑定或已连接返回EINVAL，已在监听时只修改backlog
+    pub fn listen(&self, new_backlog: usize) -> Result<(), Errno> {
+        let new_backlog = new_backlog.max(1);
+        let state = self.state.get_mut();
+        match state {
+            SocketState::Bound(name) => {
+                *state = SocketState::Listening {
+                    name: core::mem::take(name),
+                    backlog: new_backlog,
+                    queue: VecDeque::new(),
+                    wakers: Vec::new(),
+                };
+                Ok(())
+            }
+            SocketState::Listening { backlog, .. } => {
+                *backlog = new_backlog;
+                Ok(())
+            }
+            _ => Err(Errno::EINVAL),
+        }
+    }
+
+    /// 连接到名为name的正在监听的套接字，连接进入其等待队列，不等待被接受
+    ///
+    /// 已连接返回EISCONN，正在监听返回EINVAL，
+    /// 名字不存在、对方不在监听或等待队列已满返回ECONNREFUSED
+    pub fn connect(&self, name: &str) -> Result<(), Errno> {
+        check_name(name)?;
+        match self.state.get() {
+            SocketState::Connected { .. } => return Err(Errno::EISCONN),
+            SocketState::Listening { .. } => return Err(Errno::EINVAL),
+            _ => (),
+        }
+        let listener = SOCKET_NAMES
+            .get()
+            .get(name)
+            .and_then(Weak::upgrade)
+            .ok_or(Errno::ECONNREFUSED)?;
+        let (queue, wakers) = match listener.state.get_mut() {
+            SocketState::Listening {
+                backlog,
+                queue,
+                wakers,
+                ..
+            } if queue.len() < *backlog => (queue, wakers),
+            _ => return Err(Errno::ECONNREFUSED),
+        };
+        let (server_rx, tx) = make_pipe();
+        let (rx, server_tx) = make_pipe();
+        queue.push_back(Self::with_state(SocketState::Connected {
+            rx: server_rx,
+            tx: server_tx,
+        }));
+        for waker in wakers.drain(..) {
+            waker.wake();
+        }
+        // 连接后不再占用绑定的名字
+        if let SocketState::Bound(name) = self.state.get() {
+            SOCKET_NAMES.get_mut().remove(name);
+        }
+        rx.set_nonblocking(self.nonblocking());
+        tx.set_nonblocking(self.nonblocking());
+        *self.state.get_mut() = SocketState::Connected { rx, tx };
+        Ok(())
+    }
+
+    /// 取出一个等待接受的连接，没有时返回None
+    ///
+    /// 不在监听返回EINVAL
+    pub fn accept(&self) -> Result<Option<Arc<Socket>>, Errno> {
+        match self.state.get_mut() {
+            SocketState::Listening { queue, .. } => Ok(queue.pop_front()),
+            _ => Err(Errno::EINVAL),
+        }
+    }
+
+    /// 已连接时用于发送（write为true）或接收数据的管道端
+    ///
+    /// 未连接返回ENOTCONN
+    pub fn stream(&self, write: bool) -> Result<Arc<Pipe>, Errno> {
+        match self.state.get() {
+            SocketState::Connected { tx, .. } if write => Ok(tx.clone()),
+            SocketState::Connected { rx, .. } => Ok(rx.clone()),
+            _ => Err(Errno::ENOTCONN),
+        }
+    }
+}
+
+impl Drop for Socket {
+    /// 关闭已绑定的套接字时释放它的名字，未被接受的连接随之关闭，
+    /// 唤醒等待连接的协程使其结束
+    fn drop(&mut self) {
+        match self.state.get_mut() {
+            SocketState::Bound(name) => {
+                SOCKET_NAMES.get_mut().remove(name);
+            }
+            SocketState::Listening { name, wakers, .. } => {
+                SOCKET_NAMES.get_mut().remove(name);
+                for waker in wakers.drain(..) {
+                    waker.wake();
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl File for Socket {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    /// 从接收管道中取出数据，不阻塞，未连接时读到0字节
+    fn read(&self, buf: &mut [u8]) -> usize {
+        self.stream(false).map_or(0, |rx| rx.read(buf))
+    }
+
+    /// 将数据放入发送管道，不阻塞，未连接时写入0字节
+    fn write(&self, buf: &[u8]) -> usize {
+        self.stream(true).map_or(0, |tx| tx.write(buf))
+    }
+
+    /// 套接字的大小为接收管道中的字节数
+    fn stat(&self) -> Stat {
+        Stat {
+            file_type: FileType::Socket,
+            nlink: 1,
+            size: self.stream(false).map_or(0, |rx| rx.stat().size),
+            ..Stat::default()
+        }
+    }
+
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+
+    /// 已连接时同时设置两个方向的管道
+    fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+        if let SocketState::Connected { rx, tx } = self.state.get() {
+            rx.set_nonblocking(nonblock);
+            tx.set_nonblocking(nonblock);
+        }
+    }
+
+    /// 监听的套接字有等待接受的连接时可读；
+    /// 已连接的套接字的读事件来自接收管道，写事件来自发送管道；未连接时为HUP
+    fn poll(&self) -> PollEvents {
+        match self.state.get() {
+            SocketState::Listening { queue, .. } if !queue.is_empty() => PollEvents::IN,
+            SocketState::Listening { .. } => PollEvents::empty(),
+            SocketState::Connected { rx, tx } => {
+                (rx.poll() & (PollEvents::IN | PollEvents::HUP))
+                    | (tx.poll() & (PollEvents::OUT | PollEvents::ERR))
+            }
+            _ => PollEvents::HUP,
+        }
+    }
+
+    fn register_waker(&self, waker: &Waker, events: PollEvents) {
+        match self.state.get_mut() {
+            SocketState::Listening { wakers, .. } => wakers.push(waker.clone()),
+            SocketState::Connected { rx, tx } => {
+                rx.register_waker(waker, events);
+                tx.register_waker(waker, events);
+            }
+            _ => (),
+        }
+    }
+}
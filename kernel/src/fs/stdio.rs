@@ -1,11 +1,28 @@
 //! 定义标准输入输出，为其实现文件访问接口
-use serial::serial_receive;
+use serial::{serial_can_receive, serial_receive};
 
 use super::File;
 use crate::*;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Waker;
+use future::timer::add_timer;
+use user_syscall::{FileType, PollEvents, Stat};
+
+/// 标准输入输出的元数据
+fn char_device_stat() -> Stat {
+    Stat {
+        file_type: FileType::CharDevice,
+        nlink: 1,
+        ..Stat::default()
+    }
+}
 
 /// 标准输入
-pub struct Stdin;
+#[derive(Default)]
+pub struct Stdin {
+    /// 是否为非阻塞模式
+    nonblock: AtomicBool,
+}
 /// 标准输出
 pub struct Stdout;
 
@@ -20,16 +37,44 @@ impl File for Stdin {
 
     /// 从串口读取一个字符到buf中
     fn read(&self, buf: &mut [u8]) -> usize {
-        assert_eq!(buf.len(), 1);
+        if buf.is_empty() {
+            return 0;
+        }
         let c = serial_receive();
         buf[0] = c as _;
         return 1;
     }
 
+    fn stat(&self) -> Stat {
+        char_device_stat()
+    }
+
     #[allow(unused)]
     fn write(&self, buf: &[u8]) -> usize {
         panic!("Cannot write to stdin!");
     }
+
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+
+    fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+
+    /// 串口收到字符时可以读取
+    fn poll(&self) -> PollEvents {
+        if serial_can_receive() {
+            PollEvents::IN
+        } else {
+            PollEvents::empty()
+        }
+    }
+
+    /// 串口没有接收中断，每个时钟中断唤醒一次以重新检查
+    fn register_waker(&self, waker: &Waker, _events: PollEvents) {
+        add_timer(*pic::TICKS + 1, waker.clone());
+    }
 }
 
 impl File for Stdout {
@@ -55,4 +100,8 @@ impl File for Stdout {
             0
         }
     }
+
+    fn stat(&self) -> Stat {
+        char_device_stat()
+    }
 }
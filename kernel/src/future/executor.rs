@@ -1,5 +1,6 @@
 //! 协程执行器
 use super::futures::*;
+use super::timer;
 use crate::*;
 use alloc::sync::Arc;
 use core::{
@@ -72,6 +73,8 @@ impl Executor {
 /// 运行执行器直到没有就绪任务
 pub fn run_util_idle() {
     EXECUTOR.get().set_state(ExecutorState::NeedRun);
+    // 唤醒到期的定时器
+    timer::wake_expired(*pic::TICKS);
     // 轮讯协程，直到任务队列中无就绪任务才停止
     EXECUTOR.run_until_idle();
     // 此时执行器任务队列中无就绪任务
@@ -80,7 +83,12 @@ pub fn run_util_idle() {
 
 /// 是否需要调度执行
 pub fn need_schedule() -> bool {
-    EXECUTOR.get().state() == ExecutorState::NeedRun
+    EXECUTOR.get().state() == ExecutorState::NeedRun || timer::timer_pending()
+}
+
+/// 通知执行器需要运行，可以在中断中调用
+pub fn notify() {
+    EXECUTOR.get().set_state(ExecutorState::NeedRun);
 }
 
 /// 添加协程到执行器队列中
@@ -6,41 +6,163 @@ use core::task::Context;
 use core::task::Poll;
 
 use crate::fs::*;
-use crate::Cell;
+use crate::future::timer::add_timer;
+use crate::{pic, Cell};
 
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
+use user_syscall::{PollEvents, PollFd};
 
-use super::println;
+/// 等待管道可以读取（有数据或写端全部关闭）或可以写入（有空间或读端全部关闭）
+pub struct WaitForPipe {
+    /// 管道的缓冲区
+    pipe_buffer: Arc<Cell<PipeBuffer>>,
+    /// 是否等待可以写入
+    write: bool,
+}
+
+impl WaitForPipe {
+    /// 新建协程
+    pub fn new(pipe_buffer: Arc<Cell<PipeBuffer>>, write: bool) -> Self {
+        WaitForPipe { pipe_buffer, write }
+    }
+}
+
+impl Future for WaitForPipe {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let pipe_buffer = self.pipe_buffer.get_mut();
+        let ready = if self.write {
+            pipe_buffer.can_write()
+        } else {
+            pipe_buffer.can_read()
+        };
+        if ready {
+            Poll::Ready(())
+        } else {
+            // 将唤醒器注册到缓冲区中，另一端读写或关闭时唤醒
+            pipe_buffer.add_waker(cx.waker().clone(), self.write);
+            Poll::Pending
+        }
+    }
+}
 
-/// 管道的读端等待管道写端关闭
-pub struct WaitForPipeBuffer {
+/// 等待FIFO的另一端被打开
+pub struct WaitForPipeOpen {
     /// 管道的缓冲区
     pipe_buffer: Arc<Cell<PipeBuffer>>,
+    /// 是否等待写端打开
+    write: bool,
+    /// 开始等待时另一端被打开的次数
+    opens: usize,
 }
 
-impl WaitForPipeBuffer {
+impl WaitForPipeOpen {
     /// 新建协程
-    pub fn new(pipe_buffer: Arc<Cell<PipeBuffer>>) -> Self {
-        WaitForPipeBuffer { pipe_buffer }
+    pub fn new(pipe_buffer: Arc<Cell<PipeBuffer>>, write: bool, opens: usize) -> Self {
+        WaitForPipeOpen {
+            pipe_buffer,
+            write,
+            opens,
+        }
     }
 }
 
-impl Future for WaitForPipeBuffer {
+impl Future for WaitForPipeOpen {
     type Output = ();
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        if self.pipe_buffer.write_end().is_none() {
-            // [Debug]
-            println!("[Executor] WaitForPipeBuffer poll ready");
-            // 写端已经被drop了，返回Ready
+        let pipe_buffer = self.pipe_buffer.get_mut();
+        // 另一端打开后即使已经关闭也不再等待
+        if pipe_buffer.opens(self.write) != self.opens {
             Poll::Ready(())
         } else {
-            // [Debug]
-            println!("[Executor] WaitForPipeBuffer poll pending");
-            // 写端还没退出，将唤醒器注册到写端中去
-            let write_end = self.pipe_buffer.get().write_end();
-            assert!(write_end.is_some());
-            write_end.unwrap().add_waker(cx.waker().clone());
+            // 等待写端打开的是读者，写端打开时唤醒读者
+            pipe_buffer.add_waker(cx.waker().clone(), !self.write);
             Poll::Pending
         }
     }
 }
+
+/// 等待一个文件的events中任一事件就绪，ERR和HUP总是被等待
+///
+/// 只持有文件的弱引用，不会阻止文件被关闭，文件被关闭后也完成
+pub struct WaitForFile {
+    /// 等待的文件
+    file: Weak<dyn File>,
+    /// 等待的事件
+    events: PollEvents,
+}
+
+impl WaitForFile {
+    /// 新建协程
+    pub fn new(file: Weak<dyn File>, events: PollEvents) -> Self {
+        WaitForFile { file, events }
+    }
+}
+
+impl Future for WaitForFile {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let file = match self.file.upgrade() {
+            Some(file) => file,
+            None => return Poll::Ready(()),
+        };
+        if file
+            .poll()
+            .intersects(self.events | PollEvents::ERR | PollEvents::HUP)
+        {
+            Poll::Ready(())
+        } else {
+            file.register_waker(cx.waker(), self.events);
+            Poll::Pending
+        }
+    }
+}
+
+/// 等待poll的任一文件就绪或超时，完成时返回就绪的文件个数
+pub struct WaitForPoll<'a> {
+    /// 等待的文件，与fds一一对应
+    files: &'a [Option<Arc<dyn File>>],
+    /// 等待的事件，完成时revents中为就绪的事件
+    fds: &'a mut [PollFd],
+    /// 超时的时钟中断次数，None表示一直等待
+    deadline: Option<usize>,
+}
+
+impl<'a> WaitForPoll<'a> {
+    /// 新建协程
+    pub fn new(
+        files: &'a [Option<Arc<dyn File>>],
+        fds: &'a mut [PollFd],
+        deadline: Option<usize>,
+    ) -> Self {
+        WaitForPoll {
+            files,
+            fds,
+            deadline,
+        }
+    }
+}
+
+impl Future for WaitForPoll<'_> {
+    type Output = usize;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let ready = poll_files(this.files, this.fds);
+        let timeout = this
+            .deadline
+            .is_some_and(|deadline| *pic::TICKS >= deadline);
+        if ready > 0 || timeout {
+            return Poll::Ready(ready);
+        }
+        // 将唤醒器注册到每个文件和定时器中，任一文件可能就绪或超时时唤醒
+        for (file, fd) in this.files.iter().zip(this.fds.iter()) {
+            if let Some(file) = file {
+                file.register_waker(cx.waker(), fd.events);
+            }
+        }
+        if let Some(deadline) = this.deadline {
+            add_timer(deadline, cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
@@ -14,6 +14,8 @@ use super::trap::{Process, Thread};
 /// 用户线程等待一个进程结束
 ///
 /// 这个协程是顶层协程，直接被执行器轮讯，需要保证在返回Ready前将等待中线程状态设置为Runnable
+///
+/// 进程退出后将其退出码写回等待线程的第二个返回值，避免负的退出码被当作错误码
 pub struct WaitForProc {
     /// 正在等待的线程
     thread: Arc<Thread>,
@@ -37,7 +39,13 @@ impl Future for WaitForProc {
         // 等待其根线程结束
         let waited_root_thread = self.waited_process.root_thread();
         if waited_root_thread.state() == ThreadState::Exited {
-            // 已经退出，将等待的线程设置为就绪态
+            // 已经退出，写回退出码并将等待的线程设置为就绪态
+            let exit_code = self.waited_process.exit_code().unwrap_or(0);
+            self.thread.set_syscall_ret(0, exit_code);
+            // 退出码已经交给等待者，不再由父进程保留
+            if let Some(parent) = self.waited_process.parent() {
+                parent.take_exited_child(self.waited_process.pid());
+            }
             self.thread.set_state(ThreadState::Runnable);
             Poll::Ready(())
         } else {
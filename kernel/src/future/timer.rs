@@ -0,0 +1,55 @@
+//! 定时器，在指定的时钟中断次数后唤醒协程
+//!
+//! 时钟中断中只检查是否有定时器到期，到期的唤醒器由执行器在轮讯前唤醒，
+//! 中断中不访问定时器队列
+use super::executor;
+use crate::*;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use core::task::Waker;
+
+/// 定时器队列，元素为（到期时的时钟中断次数，唤醒器）
+static TIMERS: Cell<Vec<(usize, Waker)>> = Cell::new(Vec::new());
+
+/// 最早到期的时间，没有定时器时为usize::MAX
+static NEXT_DEADLINE: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// 是否有定时器到期但还未唤醒
+static TIMER_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// 注册一个定时器，时钟中断次数达到deadline后唤醒waker
+pub fn add_timer(deadline: usize, waker: Waker) {
+    TIMERS.get_mut().push((deadline, waker));
+    NEXT_DEADLINE.fetch_min(deadline, Ordering::AcqRel);
+}
+
+/// 在时钟中断中调用，有定时器到期时通知执行器运行
+pub fn timer_tick(ticks: usize) {
+    if ticks >= NEXT_DEADLINE.load(Ordering::Acquire) {
+        TIMER_PENDING.store(true, Ordering::Release);
+        executor::notify();
+    }
+}
+
+/// 是否有定时器到期但还未唤醒
+pub fn timer_pending() -> bool {
+    TIMER_PENDING.load(Ordering::Acquire)
+}
+
+/// 唤醒所有在ticks之前到期的定时器，由执行器在轮讯前调用
+pub fn wake_expired(ticks: usize) {
+    if !TIMER_PENDING.swap(false, Ordering::AcqRel) {
+        return;
+    }
+    let timers = TIMERS.get_mut();
+    let mut next_deadline = usize::MAX;
+    timers.retain(|(deadline, waker)| {
+        if *deadline <= ticks {
+            waker.wake_by_ref();
+            false
+        } else {
+            next_deadline = next_deadline.min(*deadline);
+            true
+        }
+    });
+    NEXT_DEADLINE.store(next_deadline, Ordering::Release);
+}
@@ -26,6 +26,8 @@ pub fn processor_entry() {
             None => {
                 // 请求队列为空，则设置自己为Idle，放弃CPU直到请求入队时改变状态为NeedRun
                 kthread.set_state(KthreadState::Idle);
+                // 先设置Idle再做后台工作，期间到来的请求不会丢失唤醒
+                processor.process_idle();
                 Scheduler::yield_current_kthread();
                 continue;
             }
@@ -39,6 +39,8 @@ fn kernel_main(boot_info: &'static mut BootInfo) -> ! {
     kernel::mm::init(&mut boot_info.memory_regions);
     // 初始化中断
     kernel::pic::init();
+    // 读取实时时钟
+    kernel::rtc::init();
     // 初始化驱动
     kernel::drivers::init();
     // 初始化文件系统
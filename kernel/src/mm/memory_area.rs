@@ -1,6 +1,7 @@
 //! 虚拟内存区域
 
 use alloc::sync::Arc;
+use easy_fs::Inode;
 use hashbrown::{hash_map::Entry, HashMap};
 use x86_64::structures::paging::PageTableFlags;
 
@@ -9,6 +10,7 @@ use super::*;
 use crate::mm::{align_down, is_aligned, phys_to_virt, PAGE_SIZE};
 use crate::Cell;
 use alloc::string::String;
+use alloc::vec;
 use alloc::vec::Vec;
 use core::mem::size_of;
 
@@ -19,9 +21,95 @@ pub enum MemAreaType {
     ELF,
     /// 用户栈
     USERSTACK,
+    /// 堆，由brk调整大小
+    HEAP,
+    /// 私有匿名映射（mmap）
+    ANONYMOUS,
+    /// 私有文件映射（mmap），写入不会写回文件
+    FILE,
+    /// 共享映射（mmap），fork后父子进程共享页帧，文件映射在解除映射时写回文件
+    SHARED,
+}
+
+/// 虚存区域的后备文件
+///
+/// 区域中的虚页在第一次被访问时才分配页帧，
+/// 落在文件数据范围内的部分从文件中读入，其余部分填零
+pub struct FileBacking {
+    /// 后备文件
+    inode: Arc<Inode>,
+    /// 文件数据在虚存中的起始地址
+    vaddr: usize,
+    /// 文件数据在文件中的偏移
+    offset: usize,
+    /// 文件数据长度
+    size: usize,
+}
+
+impl Clone for FileBacking {
+    fn clone(&self) -> Self {
+        Self::new(self.inode.clone(), self.vaddr, self.offset, self.size)
+    }
+}
+
+impl Drop for FileBacking {
+    fn drop(&mut self) {
+        self.inode.close();
+    }
+}
+
+impl FileBacking {
+    /// 将文件中offset处长度为size的数据作为从vaddr开始的虚存的后备
+    ///
+    /// 映射期间文件保持打开，被删除后仍可访问
+    pub fn new(inode: Arc<Inode>, vaddr: usize, offset: usize, size: usize) -> Self {
+        inode.open();
+        Self {
+            inode,
+            vaddr,
+            offset,
+            size,
+        }
+    }
+
+    /// 将虚页page_vaddr中属于文件数据的部分从文件读入页面
+    fn fill_page(&self, page_vaddr: usize, page: &mut [u8]) {
+        let start = page_vaddr.max(self.vaddr);
+        let end = (page_vaddr + PAGE_SIZE).min(self.vaddr + self.size);
+        if start >= end {
+            return;
+        }
+        self.inode.read_at(
+            self.offset + start - self.vaddr,
+            &mut page[start - page_vaddr..end - page_vaddr],
+        );
+    }
+
+    /// 将虚页page_vaddr中属于文件数据的部分写回文件
+    fn write_page(&self, page_vaddr: usize, page: &[u8]) {
+        let start = page_vaddr.max(self.vaddr);
+        let end = (page_vaddr + PAGE_SIZE).min(self.vaddr + self.size);
+        if start >= end {
+            return;
+        }
+        self.inode.write_at(
+            self.offset + start - self.vaddr,
+            &page[start - page_vaddr..end - page_vaddr],
+        );
+    }
+
+    /// 是否是同一个文件的同一段映射
+    fn same_as(&self, other: &FileBacking) -> bool {
+        Arc::ptr_eq(&self.inode, &other.inode)
+            && self.vaddr == other.vaddr
+            && self.offset == other.offset
+            && self.size == other.size
+    }
 }
 
 /// 虚存区域
+///
+/// 区域在创建时只保留虚地址范围，页帧在缺页时按需分配
 pub struct MemoryArea {
     /// 起始虚地址
     start_vaddr: usize,
@@ -29,19 +117,32 @@ pub struct MemoryArea {
     size: usize,
     /// 映射标识
     flags: PageTableFlags,
-    /// 映射关系
-    mapper: Cell<HashMap<usize, PhysFrame>>,
+    /// 映射关系，页帧可能在fork出的多个区域间共享（写时复制）
+    mapper: Cell<HashMap<usize, Arc<PhysFrame>>>,
     /// 类型
     mtype: MemAreaType,
+    /// 后备文件，为None时为匿名区域，缺页时填零
+    file: Option<FileBacking>,
 }
 
 impl MemoryArea {
-    /// 新建一块虚存区域
+    /// 新建一块匿名虚存区域
     pub fn new(
         start_vaddr: usize,
         size: usize,
         flags: PageTableFlags,
         mtype: MemAreaType,
+    ) -> Arc<Self> {
+        Self::new_with_file(start_vaddr, size, flags, mtype, None)
+    }
+
+    /// 新建一块虚存区域，可以指定后备文件
+    pub fn new_with_file(
+        start_vaddr: usize,
+        size: usize,
+        flags: PageTableFlags,
+        mtype: MemAreaType,
+        file: Option<FileBacking>,
     ) -> Arc<Self> {
         assert!(is_aligned(start_vaddr) && is_aligned(size));
         Arc::new(MemoryArea {
@@ -50,15 +151,154 @@ impl MemoryArea {
             flags,
             mapper: Cell::new(HashMap::new()),
             mtype,
+            file,
         })
     }
 
-    /// 获取一个虚地址映射的物理页帧，若没有则分配一个页帧
-    pub fn map(&self, vaddr: usize) -> usize {
+    /// 新建一块共享映射区域，从start_vaddr开始依次映射到给定的页帧
+    pub fn new_shared(
+        start_vaddr: usize,
+        flags: PageTableFlags,
+        frames: Vec<Arc<PhysFrame>>,
+    ) -> Arc<Self> {
+        let area = Self::new(
+            start_vaddr,
+            frames.len() * PAGE_SIZE,
+            flags,
+            MemAreaType::SHARED,
+        );
+        let mapper = area.mapper.get_mut();
+        for (i, frame) in frames.into_iter().enumerate() {
+            mapper.insert(start_vaddr + i * PAGE_SIZE, frame);
+        }
+        area
+    }
+
+    /// 获取一个虚地址映射的物理页帧，若没有则分配一个页帧并填充页面内容
+    ///
+    /// 没有空闲页帧时返回None
+    pub fn map(&self, vaddr: usize) -> Option<usize> {
         assert!(is_aligned(vaddr));
         match self.mapper.get_mut().entry(vaddr) {
-            Entry::Occupied(e) => e.get().0,
-            Entry::Vacant(e) => e.insert(PhysFrame::alloc_zero().unwrap()).start_paddr(),
+            Entry::Occupied(e) => Some(e.get().start_paddr()),
+            Entry::Vacant(e) => {
+                let frame = PhysFrame::alloc_zero()?;
+                if let Some(file) = &self.file {
+                    file.fill_page(vaddr, frame.as_slice());
+                }
+                Some(e.insert(Arc::new(frame)).start_paddr())
+            }
+        }
+    }
+
+    /// 查询一个虚页映射的物理页帧，若还未分配则返回None
+    pub fn query(&self, vaddr: usize) -> Option<usize> {
+        self.mapper
+            .get()
+            .get(&vaddr)
+            .map(|frame| frame.start_paddr())
+    }
+
+    /// 获取所有已经分配了页帧的虚页及其物理地址
+    pub fn mapped_pages(&self) -> Vec<(usize, usize)> {
+        self.mapper
+            .get()
+            .iter()
+            .map(|(&vaddr, frame)| (vaddr, frame.start_paddr()))
+            .collect()
+    }
+
+    /// 虚页是否需要写时复制，即页帧与其他区域共享且不是共享映射
+    pub fn is_copy_on_write(&self, vaddr: usize) -> bool {
+        self.mtype != MemAreaType::SHARED
+            && self
+                .mapper
+                .get()
+                .get(&vaddr)
+                .map_or(false, |frame| Arc::strong_count(frame) > 1)
+    }
+
+    /// 写时复制，返回虚页现在映射的物理地址
+    ///
+    /// 若虚页的页帧与其他区域共享，则分配新页帧并复制数据；
+    /// 若已没有其他区域共享或是共享映射，则直接使用原页帧。
+    /// 没有空闲页帧时返回None，原映射不变
+    pub fn copy_on_write(&self, vaddr: usize) -> Option<usize> {
+        let frame = self.mapper.get_mut().get_mut(&vaddr).unwrap();
+        if self.mtype != MemAreaType::SHARED && Arc::strong_count(frame) > 1 {
+            let new_frame = PhysFrame::alloc()?;
+            new_frame.as_slice().copy_from_slice(frame.as_slice());
+            *frame = Arc::new(new_frame);
+        }
+        Some(frame.start_paddr())
+    }
+
+    /// 虚地址是否落在这个区域中
+    pub fn contains(&self, vaddr: usize) -> bool {
+        vaddr >= self.start_vaddr && vaddr < self.end_vaddr()
+    }
+
+    /// 区域是否与[start, end)相交
+    pub fn overlaps(&self, start: usize, end: usize) -> bool {
+        self.start_vaddr < end && start < self.end_vaddr()
+    }
+
+    /// 截取区域中[start, end)的部分作为新区域，映射标志改为flags
+    ///
+    /// 新区域与原区域共享已分配的页帧和后备文件
+    pub fn sub_area(&self, start: usize, end: usize, flags: PageTableFlags) -> Arc<MemoryArea> {
+        assert!(start >= self.start_vaddr && end <= self.end_vaddr() && start < end);
+        let mapper = self
+            .mapper
+            .get()
+            .iter()
+            .filter(|(&vaddr, _)| vaddr >= start && vaddr < end)
+            .map(|(&vaddr, frame)| (vaddr, frame.clone()))
+            .collect();
+        Arc::new(Self {
+            start_vaddr: start,
+            size: end - start,
+            flags,
+            mapper: Cell::new(mapper),
+            mtype: self.mtype.clone(),
+            file: self.file.clone(),
+        })
+    }
+
+    /// 尝试与紧随其后的区域next合并，两者类型、映射标志、后备文件都相同时才能合并
+    pub fn try_merge(&self, next: &MemoryArea) -> Option<Arc<MemoryArea>> {
+        if self.end_vaddr() != next.start_vaddr
+            || self.mtype != next.mtype
+            || self.flags != next.flags
+        {
+            return None;
+        }
+        match (&self.file, &next.file) {
+            (None, None) => {}
+            (Some(a), Some(b)) if a.same_as(b) => {}
+            _ => return None,
+        }
+        let mut mapper = self.mapper.get().clone();
+        mapper.extend(next.mapper.get().iter().map(|(&v, f)| (v, f.clone())));
+        Some(Arc::new(Self {
+            start_vaddr: self.start_vaddr,
+            size: self.size + next.size,
+            flags: self.flags,
+            mapper: Cell::new(mapper),
+            mtype: self.mtype.clone(),
+            file: self.file.clone(),
+        }))
+    }
+
+    /// 将共享文件映射中已分配的页面写回文件
+    pub fn sync(&self) {
+        if self.mtype != MemAreaType::SHARED {
+            return;
+        }
+        if let Some(file) = &self.file {
+            for (&vaddr, frame) in self.mapper.get() {
+                file.write_page(vaddr, frame.as_slice());
+            }
         }
     }
 
@@ -77,6 +317,11 @@ impl MemoryArea {
         self.mtype.clone()
     }
 
+    /// 获取终止虚地址（不含）
+    pub fn end_vaddr(&self) -> usize {
+        self.start_vaddr + self.size
+    }
+
     /// 获取虚存区域长度
     pub fn size(&self) -> usize {
         self.size
@@ -99,7 +344,7 @@ impl MemoryArea {
             // 本次复制的长度
             let n = (PAGE_SIZE - page_offset).min(remain);
             // 获取（可能创建页帧）物理地址
-            let paddr = self.map(self.start_vaddr + start_align);
+            let paddr = self.map(self.start_vaddr + start_align).unwrap();
             // 写入
             unsafe {
                 core::slice::from_raw_parts_mut(
@@ -115,26 +360,26 @@ impl MemoryArea {
     }
 
     /// 克隆这个虚存区域
+    ///
+    /// 不复制页帧，而是与原区域共享已经分配的页帧，在写入时再复制；
+    /// 未分配的虚页在子区域中同样按需分配
     pub fn clone_myself(&self) -> Arc<MemoryArea> {
-        let mut mapper = Cell::new(HashMap::new());
-        // 为每个虚地址分配新的物理地址，且复制原数据
-        for (&vaddr, frame) in self.mapper.get() {
-            let new_frame = PhysFrame::alloc().unwrap();
-            new_frame.as_slice().copy_from_slice(frame.as_slice());
-            mapper.insert(vaddr, new_frame);
-        }
         Arc::new(Self {
             start_vaddr: self.start_vaddr,
             size: self.size,
             flags: self.flags,
-            mapper,
+            mapper: Cell::new(self.mapper.get().clone()),
             mtype: self.mtype.clone(),
+            file: self.file.clone(),
         })
     }
 }
 
 /// 将命令行参数压入线程的用户栈中，返回（top, argc，argv）
 ///
+/// 参数在内核中按栈的布局构造好，再通过memory_set复制到用户栈顶，
+/// 不需要切换到线程所在的地址空间
+///
 /// 栈的情况：
 ///
 /// ----high
@@ -150,20 +395,31 @@ impl MemoryArea {
 /// str2
 ///
 /// ----low
-pub fn push_to_stack(_stack: Arc<MemoryArea>, args: Option<Vec<String>>) -> (usize, usize, usize) {
-    let args = args.unwrap();
-    let mut top =
-        (USER_STACK_BASE + USER_STACK_SIZE - (args.len() + 1) * size_of::<usize>()) as *mut u8;
-    let argv = top as *mut usize;
-    unsafe {
-        for (i, arg) in args.iter().enumerate() {
-            top = top.sub(arg.len() + 1);
-            core::ptr::copy_nonoverlapping(arg.as_ptr(), top, arg.len());
-            *(top.add(arg.len())) = 0; // '\0'
-            *argv.add(i) = top as _;
-        }
-        // argv[argc] = NULL
-        *argv.add(args.len()) = 0;
+pub fn push_to_stack(
+    memory_set: &MemorySet,
+    stack: Arc<MemoryArea>,
+    args: Option<Vec<String>>,
+) -> (usize, usize, usize) {
+    let stack_top = stack.end_vaddr();
+    let args = match args {
+        Some(args) => args,
+        None => return (stack_top, 0, 0),
+    };
+    let argv = stack_top - (args.len() + 1) * size_of::<usize>();
+    let strs_len: usize = args.iter().map(|arg| arg.len() + 1).sum();
+    let top = argv - strs_len;
+    // 内核中的栈镜像，对应用户地址[top, stack_top)
+    let mut image = vec![0u8; stack_top - top];
+    let mut str_ptr = argv;
+    for (i, arg) in args.iter().enumerate() {
+        str_ptr -= arg.len() + 1;
+        let offset = str_ptr - top;
+        // '\0'已经由vec!填好
+        image[offset..offset + arg.len()].copy_from_slice(arg.as_bytes());
+        let offset = argv + i * size_of::<usize>() - top;
+        image[offset..offset + size_of::<usize>()].copy_from_slice(&str_ptr.to_ne_bytes());
     }
-    return (top as usize & !0xF, args.len(), argv as usize);
+    // argv[argc] = NULL同样已经填好
+    memory_set.copy_to_user(top, &image).unwrap();
+    (top & !0xF, args.len(), argv)
 }
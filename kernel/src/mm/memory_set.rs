@@ -4,15 +4,30 @@ use x86_64::registers::control::Cr3;
 use x86_64::registers::control::Cr3Flags;
 use x86_64::structures::paging::PageTableFlags;
 use x86_64::structures::paging::PhysFrame;
-use xmas_elf::{
-    program::{SegmentData, Type},
-    {header, ElfFile},
-};
+use xmas_elf::{header, program::Type, ElfFile};
 
-use super::memory_area::MemoryArea;
+use super::memory_area::{FileBacking, MemoryArea};
 use super::PageTable;
-use crate::Cell;
-use alloc::{sync::Arc, vec::Vec};
+use crate::{println, Cell};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use easy_fs::Inode;
+use hashbrown::HashMap;
+use spin::Lazy;
+use x86_64::instructions::tlb;
+use x86_64::VirtAddr;
+
+/// 全局变量，页表根物理地址到地址空间的映射
+///
+/// 内核态访问用户地址触发缺页时，通过cr3找到当前的地址空间
+static MEMORY_SET_MAP: Lazy<Cell<HashMap<usize, Weak<MemorySet>>>> =
+    Lazy::new(|| Cell::new(HashMap::new()));
+
+/// 获取当前cr3对应的用户地址空间
+pub fn current_memory_set() -> Option<Arc<MemorySet>> {
+    let root_paddr = Cr3::read().0.start_address().as_u64() as usize;
+    MEMORY_SET_MAP.get().get(&root_paddr)?.upgrade()
+}
 
 /// 进程地址空间
 #[derive(Default)]
@@ -21,15 +36,25 @@ pub struct MemorySet {
     areas: Cell<Vec<Arc<MemoryArea>>>,
     /// 页表
     page_table: Arc<Cell<PageTable>>,
+    /// 堆的起始地址，位于ELF之后
+    heap_base: Cell<usize>,
+    /// 当前堆顶（program break）
+    brk: Cell<usize>,
 }
 
 impl MemorySet {
     /// 新建地址空间
     pub fn new() -> Arc<MemorySet> {
-        Arc::new(MemorySet {
+        let ms = Arc::new(MemorySet {
             areas: Cell::new(Vec::new()),
             page_table: Arc::new(Cell::new(PageTable::new())),
-        })
+            heap_base: Cell::new(0),
+            brk: Cell::new(0),
+        });
+        MEMORY_SET_MAP
+            .get_mut()
+            .insert(ms.page_table.paddr(), Arc::downgrade(&ms));
+        ms
     }
 
     /// 插入一段虚存区域
@@ -39,6 +64,66 @@ impl MemorySet {
         self.page_table.get_mut().map_area(area);
     }
 
+    /// 处理缺页，write表示是否是写访问触发的缺页
+    ///
+    /// 若虚页还未分配页帧，则分配页帧并映射；
+    /// 若是写入只读映射的共享页帧，则进行写时复制；
+    /// 若虚地址不在任何虚存区域中、区域不可访问或访问权限不符（非法访问），
+    /// 或没有空闲页帧，返回false
+    pub fn handle_page_fault(&self, vaddr: usize, write: bool) -> bool {
+        // 已分配页帧的虚页只有写时复制这一种合法的缺页
+        if !write
+            && self
+                .find_area(vaddr)
+                .map_or(false, |area| area.query(align_down(vaddr)).is_some())
+        {
+            return false;
+        }
+        self.translate(vaddr, write).is_some()
+    }
+
+    /// 获取虚地址所在虚页映射的物理地址，处理方式与缺页相同：
+    /// 必要时分配页帧，写访问时进行写时复制
+    ///
+    /// 若虚地址不在任何虚存区域中、访问权限不符或没有空闲页帧，返回None
+    pub(super) fn translate(&self, vaddr: usize, write: bool) -> Option<usize> {
+        let vaddr = align_down(vaddr);
+        let area = self.find_area(vaddr)?;
+        // 不可访问（PROT_NONE）的区域，如保护页
+        if !area.flags().contains(PageTableFlags::PRESENT)
+            || (write && !area.flags().contains(PageTableFlags::WRITABLE))
+        {
+            return None;
+        }
+        let page_table = self.page_table.get_mut();
+        let paddr = match area.query(vaddr) {
+            None => {
+                let paddr = area.map(vaddr)?;
+                page_table.map(vaddr, paddr, area.flags());
+                paddr
+            }
+            Some(_) if write => {
+                // 写时复制，重新映射为可写
+                let paddr = area.copy_on_write(vaddr)?;
+                page_table.unmap(vaddr);
+                page_table.map(vaddr, paddr, area.flags());
+                paddr
+            }
+            Some(paddr) => return Some(paddr),
+        };
+        tlb::flush(VirtAddr::new(vaddr as _));
+        Some(paddr)
+    }
+
+    /// 查找虚地址所在的虚存区域
+    pub(super) fn find_area(&self, vaddr: usize) -> Option<Arc<MemoryArea>> {
+        self.areas
+            .get()
+            .iter()
+            .find(|area| area.contains(vaddr))
+            .cloned()
+    }
+
     /// 切换为当前地址空间，即修改cr3寄存器
     pub fn activate(&self) {
         let frame =
@@ -48,11 +133,12 @@ impl MemorySet {
         }
     }
 
-    /// 清理地址空间中ELF类型的区域，取消映射
-    pub fn clear_elf(&self) {
+    /// 清理地址空间中除用户栈之外的区域（ELF、mmap映射），取消映射
+    pub fn clear(&self) {
         let areas = self.areas.get_mut();
         areas.retain(|area| {
-            if area.mtype() == MemAreaType::ELF {
+            if area.mtype() != MemAreaType::USERSTACK {
+                area.sync();
                 // 取消页表映射
                 self.page_table.get_mut().unmap_area(area.clone());
                 return false;
@@ -61,6 +147,230 @@ impl MemorySet {
         });
     }
 
+    /// 映射一段区域，返回区域起始地址
+    ///
+    /// file为后备文件及文件偏移，为None时是匿名映射。
+    /// fixed为true时必须映射在addr处，并覆盖原有的映射；
+    /// 否则addr只作为提示，若不可用则另外寻找一段空闲地址。
+    /// 共享映射立即分配所有页帧，使fork后父子进程共享同一组页帧，
+    /// 页帧不足时返回None（固定映射的原有映射已被解除）
+    pub fn mmap(
+        &self,
+        addr: usize,
+        len: usize,
+        flags: PageTableFlags,
+        mtype: MemAreaType,
+        file: Option<(Arc<Inode>, usize)>,
+        fixed: bool,
+    ) -> Option<usize> {
+        let len = range_end(0, len)?;
+        let start = self.reserve_range(addr, len, fixed)?;
+        // 文件末尾之后的部分填零
+        let file = file.map(|(inode, offset)| {
+            let size = inode.size().saturating_sub(offset).min(len);
+            FileBacking::new(inode, start, offset, size)
+        });
+        let area = MemoryArea::new_with_file(start, len, flags, mtype.clone(), file);
+        if mtype == MemAreaType::SHARED {
+            // 页帧不足时区域还未插入，已分配的页帧随区域释放
+            for vaddr in (start..start + len).step_by(PAGE_SIZE) {
+                area.map(vaddr)?;
+            }
+        }
+        self.insert_area(area);
+        self.merge_areas();
+        Some(start)
+    }
+
+    /// 将一组页帧共享映射到地址空间中，返回区域起始地址
+    ///
+    /// addr和fixed的含义与mmap相同，各地址空间中映射同一组页帧的虚页看到相同的数据
+    pub fn mmap_shared(
+        &self,
+        addr: usize,
+        flags: PageTableFlags,
+        frames: Vec<Arc<super::physframe::PhysFrame>>,
+        fixed: bool,
+    ) -> Option<usize> {
+        let start = self.reserve_range(addr, frames.len() * PAGE_SIZE, fixed)?;
+        self.insert_area(MemoryArea::new_shared(start, flags, frames));
+        self.merge_areas();
+        Some(start)
+    }
+
+    /// 为长度为len（已页对齐）的映射选定起始地址
+    ///
+    /// fixed为true时必须使用addr，并解除原有的映射
+    fn reserve_range(&self, addr: usize, len: usize, fixed: bool) -> Option<usize> {
+        if len == 0 {
+            return None;
+        }
+        if fixed {
+            // 固定映射也只能位于mmap区域内，不能覆盖ELF和用户栈
+            if !is_aligned(addr)
+                || addr < MMAP_BASE
+                || range_end(addr, len).map_or(true, |end| end > USER_STACK_BASE)
+            {
+                return None;
+            }
+            self.munmap(addr, len);
+            Some(addr)
+        } else {
+            self.find_free_range(addr, len)
+        }
+    }
+
+    /// 解除[start, start + len)的映射，可能拆分区域
+    ///
+    /// 范围内没有映射的部分直接忽略，不允许解除用户栈的映射
+    pub fn munmap(&self, start: usize, len: usize) -> bool {
+        self.split_range(start, len, None)
+    }
+
+    /// 修改[start, start + len)的映射标志，可能拆分或合并区域
+    ///
+    /// 范围必须全部已经映射，不允许修改用户栈
+    pub fn mprotect(&self, start: usize, len: usize, flags: PageTableFlags) -> bool {
+        let end = match range_end(start, len) {
+            Some(end) => end,
+            None => return false,
+        };
+        let mut vaddr = start;
+        while vaddr < end {
+            match self.areas.get().iter().find(|area| area.contains(vaddr)) {
+                Some(area) => vaddr = area.end_vaddr(),
+                None => return false,
+            }
+        }
+        if !self.split_range(start, len, Some(flags)) {
+            return false;
+        }
+        self.merge_areas();
+        true
+    }
+
+    /// 将与[start, start + len)相交的区域拆开，相交部分的映射标志改为flags，
+    /// flags为None时删除相交部分
+    fn split_range(&self, start: usize, len: usize, flags: Option<PageTableFlags>) -> bool {
+        let end = match range_end(start, len) {
+            Some(end) if is_aligned(start) && len != 0 && end <= USER_STACK_BASE => end,
+            _ => return false,
+        };
+        let areas = self.areas.get_mut();
+        let (overlapped, others): (Vec<_>, Vec<_>) =
+            areas.drain(..).partition(|area| area.overlaps(start, end));
+        *areas = others;
+        let page_table = self.page_table.get_mut();
+        for area in overlapped {
+            let mut pieces = Vec::new();
+            if area.start_vaddr() < start {
+                pieces.push(area.sub_area(area.start_vaddr(), start, area.flags()));
+            }
+            let middle = area.sub_area(
+                area.start_vaddr().max(start),
+                area.end_vaddr().min(end),
+                flags.unwrap_or(area.flags()),
+            );
+            match flags {
+                Some(_) => pieces.push(middle),
+                None => middle.sync(),
+            }
+            if area.end_vaddr() > end {
+                pieces.push(area.sub_area(end, area.end_vaddr(), area.flags()));
+            }
+            // 先取消原区域的映射，再映射拆分后的区域，保证写时复制的引用计数正确
+            page_table.unmap_area(area);
+            for piece in pieces {
+                self.insert_area(piece);
+            }
+        }
+        tlb::flush_all();
+        true
+    }
+
+    /// 合并地址相邻且属性相同的区域，页表映射不变
+    fn merge_areas(&self) {
+        let areas = self.areas.get_mut();
+        areas.sort_by_key(|area| area.start_vaddr());
+        let mut merged: Vec<Arc<MemoryArea>> = Vec::with_capacity(areas.len());
+        for area in areas.drain(..) {
+            if let Some(last) = merged.last_mut() {
+                if let Some(new_area) = last.try_merge(&area) {
+                    *last = new_area;
+                    continue;
+                }
+            }
+            merged.push(area);
+        }
+        *areas = merged;
+    }
+
+    /// 设置堆顶为new_brk，返回新的堆顶；new_brk为0时返回当前堆顶
+    ///
+    /// 堆向上增长时追加一段堆区域并与原堆区域合并，收缩时解除多余部分的映射。
+    /// 堆顶不能低于堆的起始地址，也不能与其他区域重叠或进入mmap区域
+    pub fn brk(&self, new_brk: usize) -> Option<usize> {
+        if new_brk == 0 {
+            return Some(*self.brk);
+        }
+        if new_brk < *self.heap_base || new_brk > MMAP_BASE {
+            return None;
+        }
+        let (old_end, new_end) = (align_up(*self.brk), align_up(new_brk));
+        if new_end > old_end {
+            if self
+                .areas
+                .get()
+                .iter()
+                .any(|area| area.overlaps(old_end, new_end))
+            {
+                return None;
+            }
+            let flags = PageTableFlags::PRESENT
+                | PageTableFlags::USER_ACCESSIBLE
+                | PageTableFlags::WRITABLE
+                | PageTableFlags::NO_EXECUTE;
+            self.insert_area(MemoryArea::new(
+                old_end,
+                new_end - old_end,
+                flags,
+                MemAreaType::HEAP,
+            ));
+            self.merge_areas();
+        } else if new_end < old_end {
+            self.split_range(new_end, old_end - new_end, None);
+        }
+        *self.brk.get_mut() = new_brk;
+        Some(new_brk)
+    }
+
+    /// 在mmap区域中寻找一段长度为len的空闲地址，优先使用hint处
+    fn find_free_range(&self, hint: usize, len: usize) -> Option<usize> {
+        let is_free = |start: usize| {
+            start >= MMAP_BASE
+                && range_end(start, len).map_or(false, |end| end <= USER_STACK_BASE)
+                && !self
+                    .areas
+                    .get()
+                    .iter()
+                    .any(|area| area.overlaps(start, start + len))
+        };
+        if is_aligned(hint) && is_free(hint) {
+            return Some(hint);
+        }
+        // 从MMAP_BASE开始，依次尝试每个区域的结尾
+        let mut candidates: Vec<usize> = self
+            .areas
+            .get()
+            .iter()
+            .map(|area| area.end_vaddr())
+            .filter(|&end| end >= MMAP_BASE)
+            .collect();
+        candidates.push(MMAP_BASE);
+        candidates.sort();
+        candidates.into_iter().find(|&start| is_free(start))
+    }
+
     /// 克隆一个地址空间时，克隆其中所有的虚存区域
     ///
     /// 用户栈不复制而是在进程复制时手动复制(因为一个地址空间有多个)
@@ -69,12 +379,30 @@ impl MemorySet {
         for area in self.areas.get() {
             // 不复制用户栈，fork时手动复制
             if area.mtype() != MemAreaType::USERSTACK {
-                ms.insert_area(area.clone_myself());
+                ms.insert_area(self.clone_area(area));
             }
         }
+        *ms.heap_base.get_mut() = *self.heap_base;
+        *ms.brk.get_mut() = *self.brk;
         ms
     }
 
+    /// 克隆本地址空间中的一块虚存区域，两者共享页帧
+    ///
+    /// 本地址空间中该区域已映射的虚页改为只读，写入时再复制；共享映射保持可写
+    pub fn clone_area(&self, area: &Arc<MemoryArea>) -> Arc<MemoryArea> {
+        let new_area = area.clone_myself();
+        if area.mtype() == MemAreaType::SHARED {
+            return new_area;
+        }
+        let page_table = self.page_table.get_mut();
+        for (vaddr, _) in area.mapped_pages() {
+            page_table.set_writable(vaddr, false);
+        }
+        tlb::flush_all();
+        new_area
+    }
+
     /// 获取页表
     pub fn page_table(&self) -> Arc<Cell<PageTable>> {
         self.page_table.clone()
@@ -85,15 +413,48 @@ impl Drop for MemorySet {
     /// 析构时取消映射所有虚存区域
     fn drop(&mut self) {
         for area in self.areas.get() {
+            area.sync();
             self.page_table.get_mut().unmap_area(area.clone());
         }
         self.areas.clear();
+        MEMORY_SET_MAP.get_mut().remove(&self.page_table.paddr());
         // println!("[Rust] MemorySet dropped now");
     }
 }
 
+/// ELF文件头和程序头表的最大长度
+const MAX_ELF_HEADER_SIZE: usize = 4 * PAGE_SIZE;
+
+/// 从ELF文件中读出文件头和程序头表，用于构造`ElfFile`
+///
+/// 段数据在缺页时才从文件读入，因此加载时不读出整个文件。
+/// 不是ELF文件或程序头表过大时返回None
+pub fn read_elf_header(inode: &Inode) -> Option<Vec<u8>> {
+    // 64位ELF的文件头为64字节
+    let mut data = alloc::vec![0u8; 64];
+    if inode.read_at(0, &mut data) != data.len() {
+        return None;
+    }
+    let pt2 = header::parse_header(&data).ok()?.pt2;
+    // 文件头中的值不可信，计算溢出时同样视为程序头表过大
+    let end = (pt2.ph_count() as usize)
+        .checked_mul(pt2.ph_entry_size() as usize)
+        .and_then(|size| size.checked_add(usize::try_from(pt2.ph_offset()).ok()?))
+        .filter(|&end| end <= MAX_ELF_HEADER_SIZE)?;
+    if end > data.len() {
+        data.resize(end, 0);
+        if inode.read_at(0, &mut data) != end {
+            return None;
+        }
+    }
+    Some(data)
+}
+
 /// 解析elf文件，为其中的每个Load段创建虚拟内存块
-pub fn load_app(ms: Arc<MemorySet>, elf: &ElfFile) {
+///
+/// 段数据不在加载时复制，而是以ELF文件作为后备，在缺页时读入。
+/// 堆紧接在最高的段之后，初始为空
+pub fn load_app(ms: Arc<MemorySet>, elf: &ElfFile, inode: Arc<Inode>) {
     assert_eq!(
         elf.header.pt1.class(),
         header::Class::SixtyFour,
@@ -109,32 +470,44 @@ pub fn load_app(ms: Arc<MemorySet>, elf: &ElfFile) {
         header::Machine::X86_64,
         "invalid ELF arch"
     );
+    let mut elf_end = 0;
     for ph in elf.program_iter() {
         if ph.get_type() != Ok(Type::Load) {
             continue;
         }
-        // 准备映射标志
+        // 准备映射标志，可写的段不可执行（W^X）
         let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
-        if flags.contains(PageTableFlags::WRITABLE) {
+        if ph.flags().is_write() {
             flags |= PageTableFlags::WRITABLE;
+            if ph.flags().is_execute() {
+                println!(
+                    "[Kernel] ELF segment at {:#x} is both writable and executable, mapped as non-executable",
+                    ph.virtual_addr()
+                );
+            }
+        }
+        if ph.flags().is_write() || !ph.flags().is_execute() {
+            flags |= PageTableFlags::NO_EXECUTE;
         }
-        let offset = ph.virtual_addr() as usize & (PAGE_SIZE - 1);
         let vaddr_start = align_down(ph.virtual_addr() as usize);
         let vaddr_end = align_up(ph.virtual_addr() as usize + ph.mem_size() as usize);
-        // 读取ELF段数据并写入物理页帧组
-        let data = match ph.get_data(&elf).unwrap() {
-            SegmentData::Undefined(data) => data,
-            _ => panic!("failed to get ELF segment data"),
-        };
-        let memory_area = MemoryArea::new(
+        // 段数据在ELF文件中的位置，超出file_size的部分（.bss）填零
+        let file = FileBacking::new(
+            inode.clone(),
+            ph.virtual_addr() as usize,
+            ph.offset() as usize,
+            ph.file_size() as usize,
+        );
+        let memory_area = MemoryArea::new_with_file(
             vaddr_start,
             vaddr_end - vaddr_start,
             flags,
             MemAreaType::ELF,
+            Some(file),
         );
-
-        // 数据写入虚存区域中
-        memory_area.write_data(offset, data);
         ms.insert_area(memory_area);
+        elf_end = elf_end.max(vaddr_end);
     }
+    *ms.heap_base.get_mut() = elf_end;
+    *ms.brk.get_mut() = elf_end;
 }
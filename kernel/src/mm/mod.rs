@@ -2,7 +2,8 @@
 use bootloader_api::info::MemoryRegions;
 pub use frame_allocator::*;
 pub use page_table::*;
-use x86_64::registers::control::Cr3;
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr3};
+use x86_64::registers::model_specific::{Efer, EferFlags};
 pub use x86_64::structures::paging::PageTableFlags;
 
 mod frame_allocator;
@@ -11,6 +12,8 @@ mod memory_area;
 mod memory_set;
 mod page_table;
 mod physframe;
+mod shm;
+mod user_access;
 
 // mod physical_frame_group;
 // mod virtual_memory_block;
@@ -19,6 +22,8 @@ mod physframe;
 pub use heap_allocator::heap_init;
 pub use memory_area::*;
 pub use memory_set::*;
+pub use shm::*;
+pub use user_access::*;
 
 /// 内核映射物理内存偏移
 pub const PHYS_OFFSET: usize = 0xFFFF_8000_0000_0000;
@@ -47,6 +52,9 @@ pub const USER_STACK_SIZE: usize = 0x40_0000;
 /// 用户栈（最低地址处）
 pub const USER_STACK_BASE: usize = 0x0000_7E80_0000_0000;
 
+/// mmap映射区域的最低地址，映射区域向上分配，不超过USER_STACK_BASE
+pub const MMAP_BASE: usize = 0x0000_6000_0000_0000;
+
 /// 任意级页表含的页表项个数
 pub const ENTRY_COUNT: usize = 512;
 
@@ -54,6 +62,10 @@ pub const ENTRY_COUNT: usize = 512;
 pub fn init(memory_regions: &'static mut MemoryRegions) {
     frame_allocator::init(memory_regions);
     page_table::init();
+    // 内核态写只读页同样触发页错误，写时复制依赖于此
+    unsafe { Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT)) };
+    // 启用页表项中的NX位
+    unsafe { Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE)) };
 }
 
 /// 物理地址转虚拟地址
@@ -78,6 +90,16 @@ pub const fn align_up(p: usize) -> usize {
     (p + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
 }
 
+/// 区域[start, start + len)向上页对齐后的终止地址，溢出时返回None
+///
+/// 用于检查用户传入的地址范围
+pub const fn range_end(start: usize, len: usize) -> Option<usize> {
+    match len.checked_add(PAGE_SIZE - 1) {
+        Some(len) => start.checked_add(len & !(PAGE_SIZE - 1)),
+        None => None,
+    }
+}
+
 /// 获取在当前页中的偏移量
 pub const fn page_offset(p: usize) -> usize {
     p & (PAGE_SIZE - 1)
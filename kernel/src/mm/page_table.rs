@@ -18,8 +18,8 @@ static KERNEL_STACK_PTE: Cell<usize> = zero();
 /// 物理内存0在当前四级页表中对应的页表项
 static PHYS_PTE: Cell<usize> = zero();
 
-/// 1111..._0000_0000_0000
-const PHYS_ADDR_MASK: usize = !(PAGE_SIZE - 1);
+/// 页表项中的物理地址位（12～51位）
+const PHYS_ADDR_MASK: usize = 0x000F_FFFF_FFFF_F000;
 
 /// 页表
 #[derive(Default)]
@@ -75,13 +75,24 @@ impl PageTable {
         p1e
     }
 
-    /// 将一对虚地址和物理地址映射写入页表
-    pub fn map(&self, vaddr: usize, paddr: usize, _flags: PageTableFlags) {
+    /// 将一对虚地址和物理地址按照给定的映射标志写入页表
+    pub fn map(&self, vaddr: usize, paddr: usize, flags: PageTableFlags) {
         let entry = self.get_entry_or_create(vaddr);
         if !pte_is_empty(entry) {
             panic!("vaddr: 0x{:x} is mapped before", vaddr);
         }
-        pte_set_table(entry, paddr);
+        pte_set_page(entry, paddr, flags);
+    }
+
+    /// 设置一个已映射虚页的写权限
+    pub fn set_writable(&self, vaddr: usize, writable: bool) {
+        let entry = self.get_entry_or_create(vaddr);
+        let bit = PageTableFlags::WRITABLE.bits() as usize;
+        if writable {
+            *entry |= bit;
+        } else {
+            *entry &= !bit;
+        }
     }
 
     /// 取消映射一个虚拟地址，清除对应的页表项
@@ -92,27 +103,29 @@ impl PageTable {
         *entry = 0;
     }
 
-    /// 将一块虚存区域映射写入页表
+    /// 将一块虚存区域中已经分配页帧的虚页映射写入页表
+    ///
+    /// 其余虚页在第一次访问触发缺页时再映射，共享的页帧映射为只读（写时复制）
     pub fn map_area(&mut self, area: Arc<MemoryArea>) {
-        let mut vaddr = area.start_vaddr();
-        let size = area.size();
-        assert!(vaddr + size < PHYS_OFFSET);
-        let end = vaddr + size;
-        while vaddr < end {
-            let paddr = area.map(vaddr);
-            self.map(vaddr, paddr, area.flags());
-            vaddr += PAGE_SIZE;
+        assert!(area.start_vaddr() + area.size() < PHYS_OFFSET);
+        // 不可访问的区域不写入页表，页帧保留在区域中
+        if !area.flags().contains(PageTableFlags::PRESENT) {
+            return;
+        }
+        for (vaddr, paddr) in area.mapped_pages() {
+            let mut flags = area.flags();
+            if area.is_copy_on_write(vaddr) {
+                flags.remove(PageTableFlags::WRITABLE);
+            }
+            self.map(vaddr, paddr, flags);
         }
     }
 
-    /// 取消映射一块虚存区域，清除对应的页表项
+    /// 取消映射一块虚存区域，清除已映射虚页对应的页表项
     pub fn unmap_area(&mut self, area: Arc<MemoryArea>) {
-        let mut vaddr = area.start_vaddr();
-        let end = vaddr + area.size();
-        while vaddr < end {
+        for (vaddr, _) in area.mapped_pages() {
             area.unmap(vaddr);
             self.unmap(vaddr);
-            vaddr += PAGE_SIZE;
         }
     }
 }
@@ -167,13 +180,20 @@ fn pte_is_empty(pte: &usize) -> bool {
     (*pte == 0) || (*pte == usize::MAX)
 }
 
-/// 物理地址写入页表项
+/// 下一级页表的物理地址写入页表项
+///
+/// 中间级页表项不限制权限，访问权限由最后一级页表项决定
 pub fn pte_set_table(pte: &mut usize, paddr: usize) {
     *pte = paddr & PHYS_ADDR_MASK
         | (PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE)
             .bits() as usize;
 }
 
+/// 物理页帧地址及映射标志写入最后一级页表项
+pub fn pte_set_page(pte: &mut usize, paddr: usize, flags: PageTableFlags) {
+    *pte = paddr & PHYS_ADDR_MASK | (flags | PageTableFlags::PRESENT).bits() as usize;
+}
+
 /// 获取一个虚拟地址的四级页表项，可能创建低级页表
 ///
 /// [Debug]用
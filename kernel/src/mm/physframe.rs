@@ -17,7 +17,7 @@ impl PhysFrame {
     }
     /// 分配一个物理页帧且清零
     pub fn alloc_zero() -> Option<Self> {
-        let frame = allocate_frame().map(Self).unwrap();
+        let frame = Self::alloc()?;
         clear_frames(frame.0, 1);
         Some(frame)
     }
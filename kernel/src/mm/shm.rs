@@ -0,0 +1,145 @@
+//! 命名共享内存对象
+//!
+//! 共享内存对象是一组按名字查找的页帧，映射到多个进程的地址空间中时
+//! 各进程的虚页对应同一组页帧。名字被删除后对象仍然有效，
+//! 页帧在最后一个打开的文件和映射都释放后才被回收
+
+use super::physframe::PhysFrame;
+use super::*;
+use crate::fs::File;
+use crate::*;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use user_syscall::{Errno, FileType, Stat};
+
+/// 共享内存对象名字的最大长度
+pub const SHM_NAME_MAX: usize = 255;
+
+/// 共享内存对象的最大长度
+pub const SHM_SIZE_MAX: usize = 0x400_0000;
+
+/// 已创建的共享内存对象，以名字为键
+static SHM_OBJECTS: Cell<BTreeMap<String, Arc<SharedMemory>>> = Cell::new(BTreeMap::new());
+
+/// 共享内存对象
+///
+/// 以文件的形式打开，总是可读写，只能通过mmap访问其内容
+pub struct SharedMemory {
+    /// 对象长度
+    size: Cell<usize>,
+    /// 后备页帧，每页一个
+    frames: Cell<Vec<Arc<PhysFrame>>>,
+}
+
+/// 检查共享内存对象的名字，为空返回EINVAL，过长返回ENAMETOOLONG
+fn check_name(name: &str) -> Result<(), Errno> {
+    if name.is_empty() {
+        Err(Errno::EINVAL)
+    } else if name.len() > SHM_NAME_MAX {
+        Err(Errno::ENAMETOOLONG)
+    } else {
+        Ok(())
+    }
+}
+
+impl SharedMemory {
+    /// 打开名为name的共享内存对象，create为true时不存在则新建一个长度为0的对象
+    ///
+    /// 不存在且不新建时返回ENOENT
+    pub fn open(name: String, create: bool) -> Result<Arc<Self>, Errno> {
+        check_name(&name)?;
+        let objects = SHM_OBJECTS.get_mut();
+        if let Some(shm) = objects.get(&name) {
+            return Ok(shm.clone());
+        }
+        if !create {
+            return Err(Errno::ENOENT);
+        }
+        let shm = Arc::new(Self {
+            size: Cell::new(0),
+            frames: Cell::new(Vec::new()),
+        });
+        objects.insert(name, shm.clone());
+        Ok(shm)
+    }
+
+    /// 删除名字，已打开和已映射的对象不受影响
+    ///
+    /// 不存在返回ENOENT
+    pub fn unlink(name: &str) -> Result<(), Errno> {
+        check_name(name)?;
+        SHM_OBJECTS
+            .get_mut()
+            .remove(name)
+            .map(|_| ())
+            .ok_or(Errno::ENOENT)
+    }
+
+    /// 将对象截断或扩展到len字节，扩展的部分填零
+    ///
+    /// 已建立的映射仍使用原来的页帧。超过最大长度返回EFBIG，
+    /// 没有足够的页帧返回ENOMEM，此时对象保持不变
+    pub fn truncate(&self, len: usize) -> Result<(), Errno> {
+        if len > SHM_SIZE_MAX {
+            return Err(Errno::EFBIG);
+        }
+        let frames = self.frames.get_mut();
+        let old_count = frames.len();
+        let count = align_up(len) / PAGE_SIZE;
+        if count > old_count {
+            frames
+                .try_reserve(count - old_count)
+                .map_err(|_| Errno::ENOMEM)?;
+        }
+        while frames.len() < count {
+            match PhysFrame::alloc_zero() {
+                Some(frame) => frames.push(Arc::new(frame)),
+                None => {
+                    frames.truncate(old_count);
+                    return Err(Errno::ENOMEM);
+                }
+            }
+        }
+        frames.truncate(count);
+        *self.size.get_mut() = len;
+        Ok(())
+    }
+
+    /// 获取对象中从offset开始长度为len的部分的页帧，超出对象范围时返回None
+    pub fn frames(&self, offset: usize, len: usize) -> Option<Vec<Arc<PhysFrame>>> {
+        let end = range_end(offset, len)?;
+        if !is_aligned(offset) || end > align_up(*self.size) {
+            return None;
+        }
+        Some(self.frames.get()[offset / PAGE_SIZE..end / PAGE_SIZE].to_vec())
+    }
+}
+
+impl File for SharedMemory {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        true
+    }
+
+    /// 不支持读，读到0字节
+    fn read(&self, _buf: &mut [u8]) -> usize {
+        0
+    }
+
+    /// 不支持写，写入0字节
+    fn write(&self, _buf: &[u8]) -> usize {
+        0
+    }
+
+    fn stat(&self) -> Stat {
+        Stat {
+            file_type: FileType::File,
+            nlink: 1,
+            size: *self.size as u64,
+            ..Stat::default()
+        }
+    }
+}
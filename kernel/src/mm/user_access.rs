@@ -0,0 +1,143 @@
+//! 内核访问用户地址空间
+//!
+//! 内核不直接解引用用户传入的指针，而是先检查地址是否落在地址空间的虚存区域中，
+//! 再通过页帧的物理地址复制数据，非法地址返回None而不会在内核中触发页错误
+
+use super::*;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+/// 用户字符串的最大长度
+pub const MAX_USER_STR_LEN: usize = 4096;
+
+/// 命令行参数的最大个数
+pub const MAX_USER_ARGS: usize = 64;
+
+impl MemorySet {
+    /// 对用户地址[start, start + len)逐页调用f(页内物理地址, 本次长度, 已处理长度)
+    ///
+    /// 地址不合法或权限不符时返回None，此时可能已经处理了前面的部分
+    fn for_each_user_page(
+        &self,
+        start: usize,
+        len: usize,
+        write: bool,
+        mut f: impl FnMut(usize, usize, usize),
+    ) -> Option<()> {
+        let end = start.checked_add(len)?;
+        if end > PHYS_OFFSET {
+            return None;
+        }
+        let mut vaddr = start;
+        while vaddr < end {
+            let n = (PAGE_SIZE - page_offset(vaddr)).min(end - vaddr);
+            let paddr = self.translate(vaddr, write)?;
+            f(phys_to_virt(paddr) + page_offset(vaddr), n, vaddr - start);
+            vaddr += n;
+        }
+        Some(())
+    }
+
+    /// 检查用户地址[start, start + len)是否全部合法
+    pub fn check_user_range(&self, start: usize, len: usize, write: bool) -> bool {
+        let end = match start.checked_add(len) {
+            Some(end) if end <= PHYS_OFFSET => end,
+            _ => return false,
+        };
+        let mut vaddr = align_down(start);
+        while vaddr < end {
+            match self.find_area(vaddr) {
+                Some(area) if !write || area.flags().contains(PageTableFlags::WRITABLE) => {
+                    vaddr = area.end_vaddr();
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// 从用户地址src复制数据到内核缓冲区dst
+    pub fn copy_from_user(&self, src: usize, dst: &mut [u8]) -> Option<()> {
+        if !self.check_user_range(src, dst.len(), false) {
+            return None;
+        }
+        self.for_each_user_page(src, dst.len(), false, |kaddr, n, done| unsafe {
+            core::ptr::copy_nonoverlapping(kaddr as *const u8, dst[done..].as_mut_ptr(), n);
+        })
+    }
+
+    /// 将内核缓冲区src中的数据复制到用户地址dst
+    pub fn copy_to_user(&self, dst: usize, src: &[u8]) -> Option<()> {
+        if !self.check_user_range(dst, src.len(), true) {
+            return None;
+        }
+        self.for_each_user_page(dst, src.len(), true, |kaddr, n, done| unsafe {
+            core::ptr::copy_nonoverlapping(src[done..].as_ptr(), kaddr as *mut u8, n);
+        })
+    }
+
+    /// 从用户地址读取一个usize
+    pub fn read_user_usize(&self, src: usize) -> Option<usize> {
+        let mut bytes = [0u8; size_of::<usize>()];
+        self.copy_from_user(src, &mut bytes)?;
+        Some(usize::from_ne_bytes(bytes))
+    }
+
+    /// 向用户地址写入一个usize
+    pub fn write_user_usize(&self, dst: usize, value: usize) -> Option<()> {
+        self.copy_to_user(dst, &value.to_ne_bytes())
+    }
+
+    /// 读取用户地址ptr处长度为len的UTF-8字符串
+    pub fn read_user_str(&self, ptr: usize, len: usize) -> Option<String> {
+        if len > MAX_USER_STR_LEN {
+            return None;
+        }
+        let mut buf = vec![0u8; len];
+        self.copy_from_user(ptr, &mut buf)?;
+        String::from_utf8(buf).ok()
+    }
+
+    /// 读取用户地址ptr处以'\0'结尾的字符串，最长max_len字节（不含'\0'）
+    pub fn read_user_cstr(&self, ptr: usize, max_len: usize) -> Option<String> {
+        let mut buf = Vec::new();
+        let mut vaddr = ptr;
+        loop {
+            // 每次最多读到页末尾，避免越过合法区域
+            let n = PAGE_SIZE - page_offset(vaddr);
+            let mut chunk = vec![0u8; n];
+            self.copy_from_user(vaddr, &mut chunk)?;
+            if let Some(pos) = chunk.iter().position(|&b| b == 0) {
+                buf.extend_from_slice(&chunk[..pos]);
+                break;
+            }
+            buf.extend_from_slice(&chunk);
+            if buf.len() > max_len {
+                return None;
+            }
+            vaddr += n;
+        }
+        if buf.len() > max_len {
+            return None;
+        }
+        String::from_utf8(buf).ok()
+    }
+
+    /// 读取用户地址argv处以0结尾的字符串指针数组，每个字符串以'\0'结尾
+    pub fn read_user_argv(&self, argv: usize) -> Option<Vec<String>> {
+        let mut args = Vec::new();
+        loop {
+            let ptr = self.read_user_usize(argv + args.len() * size_of::<usize>())?;
+            if ptr == 0 {
+                break;
+            }
+            if args.len() == MAX_USER_ARGS {
+                return None;
+            }
+            args.push(self.read_user_cstr(ptr, MAX_USER_STR_LEN)?);
+        }
+        Some(args)
+    }
+}
@@ -1,16 +1,49 @@
 //! 文件系统内核线程的响应器
 
 use core::{
+    mem::size_of,
     panic,
-    sync::atomic::{AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
 };
 
-use crate::{activate_proc_ms, println, task::PROCESS_MAP};
+use crate::{
+    mm::MemorySet,
+    println,
+    rtc::TICKS_PER_SECOND,
+    task::{KthreadState, KthreadType, Process, KTHREAD_MAP, PROCESS_MAP},
+};
 
 use super::*;
 use crate::fs::*;
 use alloc::sync::Arc;
+use alloc::vec;
+use num::FromPrimitive;
 use requests_info::{fsreqinfo::FsReqDescription, CastBytes};
+use user_syscall::{Dirent, Errno, Whence};
+
+/// 读写文件时内核缓冲区的大小
+///
+/// 文件数据先读写到内核缓冲区，再与用户态复制，读写文件时不访问用户地址空间
+const BUFFER_SIZE: usize = 4096;
+
+/// 定期写回块缓存的间隔（时钟中断次数）
+const FLUSH_INTERVAL: usize = 5 * TICKS_PER_SECOND;
+
+/// 是否需要定期写回块缓存
+static FLUSH_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// 在时钟中断中调用，每隔FLUSH_INTERVAL通知文件系统内核线程写回块缓存
+///
+/// 中断中不能获取文件系统的锁，写回由文件系统内核线程在空闲时完成
+pub fn flush_tick(ticks: usize) {
+    if ticks % FLUSH_INTERVAL != 0 {
+        return;
+    }
+    if let Some(fs_kthread) = KTHREAD_MAP.get().get(&KthreadType::FS) {
+        FLUSH_PENDING.store(true, Ordering::Release);
+        fs_kthread.set_state(KthreadState::NeedRun);
+    }
+}
 
 /// 文件系统请求处理器
 pub struct FsProcessor;
@@ -31,126 +64,222 @@ impl Processor for FsProcessor {
         match fs_req {
             // Read请求，进程Pid读文件表中fd对应的文件到buf中
             FsReqDescription::Read(pid, fd, buf_ptr, buf_len, result_ptr) => {
-                // 切换到进程所在地址空间
-                activate_proc_ms(pid.clone());
-                // 解析buf
-                let buf_ptr = (*buf_ptr) as *mut u8;
-                let buf = unsafe { core::slice::from_raw_parts_mut(buf_ptr, *buf_len) };
-                // 获取磁盘文件
-                let proc = PROCESS_MAP.get().get(pid);
-                assert!(proc.is_some());
-                let proc = proc.unwrap();
-                // sys_read中已保证file存在文件表中
-                let file = proc
-                    .file_table()
-                    .get(*fd)
-                    .unwrap()
-                    .as_ref()
-                    .unwrap()
-                    .clone();
+                let (proc, file) = get_file(*pid, *fd);
+                let memory_set = proc.memory_set();
                 let read_size = if !file.readable() {
                     println!("[Fs server] Error reading file, not readable!");
-                    usize::MAX
+                    Errno::EBADF.as_ret()
                 } else {
-                    file.read(buf)
+                    read_to_user(&memory_set, *buf_ptr, *buf_len, |_, buf| file.read(buf))
                 };
                 // 将read_size写入到用户态中
-                let result_ptr = (*result_ptr) as *mut usize;
-                unsafe {
-                    *result_ptr = read_size;
-                }
+                memory_set.write_user_usize(*result_ptr, read_size);
             }
             // Write请求，进程Pid将buf中的数据写入文件表中fd对应的文件
             FsReqDescription::Write(pid, fd, buf_ptr, buf_len, result_ptr) => {
-                // 切换到进程所在地址空间
-                activate_proc_ms(pid.clone());
                 // [模拟致命错误]
                 if PROCESSED_COUNT.load(Ordering::Relaxed) % 5 == 0 {
                     PROCESSED_COUNT.fetch_add(1, Ordering::Relaxed);
                     panic!("[Fs Processor] Fatal error in write request!");
                 }
 
-                // 解析buf
-                let buf_ptr = (*buf_ptr) as *const u8;
-                let buf = unsafe { core::slice::from_raw_parts(buf_ptr, *buf_len) };
-                // 获取磁盘文件
-                let proc = PROCESS_MAP.get().get(pid);
-                assert!(proc.is_some());
-                let proc = proc.unwrap();
-                // sys_read中已保证file存在文件表中
-                let file = proc
-                    .file_table()
-                    .get(*fd)
-                    .unwrap()
-                    .as_ref()
-                    .unwrap()
-                    .clone();
+                let (proc, file) = get_file(*pid, *fd);
+                let memory_set = proc.memory_set();
                 let write_size = if !file.writable() {
-                    println!("[Fs server] Error writing file, not readable!");
-                    usize::MAX
+                    println!("[Fs server] Error writing file, not writable!");
+                    Errno::EBADF.as_ret()
                 } else {
-                    file.write(buf)
+                    write_from_user(&memory_set, *buf_ptr, *buf_len, |_, buf| file.write(buf))
                 };
-                // 将read_size写入到用户态中
-                let result_ptr = (*result_ptr) as *mut usize;
-                unsafe {
-                    *result_ptr = write_size;
-                }
+                // 将write_size写入到用户态中
+                memory_set.write_user_usize(*result_ptr, write_size);
+            }
+            // Pread请求，从offset处读取，不移动读写位置
+            FsReqDescription::Pread(pid, fd, buf_ptr, buf_len, offset, result_ptr) => {
+                let (proc, file) = get_file(*pid, *fd);
+                let memory_set = proc.memory_set();
+                // sys_pread中已保证是可读的磁盘文件
+                let inode = file.downcast_arc::<OSInode>().ok().unwrap().inode();
+                let read_size = read_to_user(&memory_set, *buf_ptr, *buf_len, |done, buf| {
+                    inode.read_at(*offset + done, buf)
+                });
+                memory_set.write_user_usize(*result_ptr, read_size);
+            }
+            // Pwrite请求，从offset处写入，不移动读写位置
+            FsReqDescription::Pwrite(pid, fd, buf_ptr, buf_len, offset, result_ptr) => {
+                let (proc, file) = get_file(*pid, *fd);
+                let memory_set = proc.memory_set();
+                // sys_pwrite中已保证是可写的磁盘文件
+                let inode = file.downcast_arc::<OSInode>().ok().unwrap().inode();
+                let write_size = write_from_user(&memory_set, *buf_ptr, *buf_len, |done, buf| {
+                    inode.write_at(*offset + done, buf)
+                });
+                memory_set.write_user_usize(*result_ptr, write_size);
+            }
+            // Lseek请求，移动读写位置并将新的位置写入用户态
+            FsReqDescription::Lseek(pid, fd, offset, whence, result_ptr) => {
+                let (proc, file) = get_file(*pid, *fd);
+                // sys_lseek中已保证是磁盘文件且whence合法
+                let osinode = file.downcast_arc::<OSInode>().ok().unwrap();
+                let whence = Whence::from_usize(*whence).unwrap();
+                let pos = osinode
+                    .seek(*offset as isize, whence)
+                    .unwrap_or(Errno::EINVAL.as_ret());
+                proc.memory_set().write_user_usize(*result_ptr, pos);
+            }
+            // Getdents请求，读取目录项并将写入的字节数写入用户态
+            FsReqDescription::Getdents(pid, fd, buf_ptr, buf_len, result_ptr) => {
+                let (proc, file) = get_file(*pid, *fd);
+                let memory_set = proc.memory_set();
+                // sys_getdents中已保证是目录
+                let osinode = file.downcast_arc::<OSInode>().ok().unwrap();
+                let dirents = osinode.read_dir(*buf_len / size_of::<Dirent>()).unwrap();
+                let bytes = unsafe {
+                    core::slice::from_raw_parts(
+                        dirents.as_ptr() as *const u8,
+                        dirents.len() * size_of::<Dirent>(),
+                    )
+                };
+                let read_size = match memory_set.copy_to_user(*buf_ptr, bytes) {
+                    Some(()) => bytes.len(),
+                    None => Errno::EFAULT.as_ret(),
+                };
+                memory_set.write_user_usize(*result_ptr, read_size);
+            }
+            // Truncate请求，按路径截断文件并将结果写入用户态
+            FsReqDescription::Truncate(pid, path_ptr, path_len, len, result_ptr) => {
+                let proc = PROCESS_MAP.get().get(pid).unwrap().clone();
+                let memory_set = proc.memory_set();
+                let result = match memory_set.read_user_str(*path_ptr, *path_len) {
+                    Some(path) => truncate_file(&path, *len),
+                    None => Err(Errno::EFAULT),
+                };
+                memory_set.write_user_usize(*result_ptr, result.map_or_else(Errno::as_ret, |_| 0));
+            }
+            // Ftruncate请求，截断磁盘文件并将结果写入用户态
+            FsReqDescription::Ftruncate(pid, fd, len, result_ptr) => {
+                let (proc, file) = get_file(*pid, *fd);
+                // sys_ftruncate中已保证是可写的磁盘文件
+                let inode = file.downcast_arc::<OSInode>().ok().unwrap().inode();
+                let result = truncate_inode(&inode, *len);
+                proc.memory_set()
+                    .write_user_usize(*result_ptr, result.map_or_else(Errno::as_ret, |_| 0));
+            }
+            // Sync请求，写回所有脏块
+            FsReqDescription::Sync(pid, result_ptr) => {
+                sync_all();
+                let proc = PROCESS_MAP.get().get(pid).unwrap().clone();
+                proc.memory_set().write_user_usize(*result_ptr, 0);
+            }
+            // Fsync请求，写回文件的脏块
+            FsReqDescription::Fsync(pid, fd, result_ptr) => {
+                let (proc, file) = get_file(*pid, *fd);
+                // sys_fsync中已保证是磁盘文件
+                file.downcast_arc::<OSInode>().ok().unwrap().inode().sync();
+                proc.memory_set().write_user_usize(*result_ptr, 0);
             }
             // 处理Open请求，打开一个磁盘文件并加入pid进程的文件表中
-            FsReqDescription::Open(pid, path_ptr, flags, fd_ptr) => {
-                // 切换到进程所在地址空间
-                activate_proc_ms(pid.clone());
-                // 解析路径
-                let flags = OpenFlags::from_bits(*flags).unwrap();
-                let path_ptr = (*path_ptr) as *const &str;
-                let path = unsafe { *path_ptr };
-                let (readable, writable) = flags.read_write();
-                // 可以创建文件
-                let file = if flags.contains(OpenFlags::CREATE) {
-                    // 文件已存在
-                    if let Some(inode) = ROOT_INODE.find(path) {
-                        // clear size
-                        inode.clear();
-                        Some(Arc::new(OSInode::new(readable, writable, inode)))
-                    // 不存在，需要创建
-                    } else {
-                        ROOT_INODE
-                            .create(path)
-                            .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
-                    }
-                // 不能创建文件
-                } else {
-                    ROOT_INODE.find(path).map(|inode| {
-                        if flags.contains(OpenFlags::TRUNC) {
-                            inode.clear();
-                        }
-                        Arc::new(OSInode::new(readable, writable, inode))
-                    })
+            FsReqDescription::Open(pid, path_ptr, path_len, flags, fd_ptr) => {
+                let process = PROCESS_MAP.get().get(pid);
+                assert!(process.is_some());
+                let process = process.unwrap();
+                let memory_set = process.memory_set();
+                // 从用户态复制路径
+                let path = memory_set.read_user_str(*path_ptr, *path_len);
+                let flags = OpenFlags::from_bits(*flags);
+                let file = match (&path, flags) {
+                    (Some(path), Some(flags)) => open_file(path, flags),
+                    (None, _) => Err(Errno::EFAULT),
+                    (_, None) => Err(Errno::EINVAL),
                 };
                 let fd = match file {
-                    Some(file) => {
-                        // 将文件添加到进程文件表中
-                        let process = PROCESS_MAP.get().get(&*pid);
-                        assert!(process.is_some());
-                        process.unwrap().add_file(file)
-                    }
-                    None => {
+                    // 将文件添加到进程文件表中
+                    Ok(file) => process.add_file(file),
+                    Err(errno) => {
                         println!(
-                            "[Fs Kthread] Open request failed, cannot open file with path {}",
+                            "[Fs Kthread] Open request failed, cannot open file with path {:?}",
                             path
                         );
-                        // 未能打开文件时返回usize::MAX
-                        usize::MAX
+                        // 未能打开文件时返回错误码
+                        errno.as_ret()
                     }
                 };
                 // 将结果写入fd指针
-                let fd_ptr = (*fd_ptr) as *mut usize;
-                unsafe {
-                    *fd_ptr = fd;
-                }
+                memory_set.write_user_usize(*fd_ptr, fd);
             }
         }
         PROCESSED_COUNT.fetch_add(1, Ordering::Relaxed);
     }
+
+    /// 空闲时完成定期写回
+    fn process_idle(&self) {
+        if FLUSH_PENDING.swap(false, Ordering::AcqRel) {
+            sync_all();
+        }
+    }
+}
+
+/// 获取pid进程及其文件表中fd对应的文件
+///
+/// 系统调用中已保证文件存在于文件表中
+fn get_file(pid: usize, fd: usize) -> (Arc<Process>, Arc<dyn File>) {
+    let proc = PROCESS_MAP.get().get(&pid);
+    assert!(proc.is_some());
+    let proc = proc.unwrap().clone();
+    let file = proc.file_table().get(fd).unwrap().as_ref().unwrap().clone();
+    (proc, file)
+}
+
+/// 经由内核缓冲区将文件数据读到用户地址buf_ptr处
+///
+/// read(已读取长度, 缓冲区)从文件中读取数据，返回读取的总字节数，用户地址不合法返回EFAULT
+fn read_to_user(
+    memory_set: &MemorySet,
+    buf_ptr: usize,
+    buf_len: usize,
+    mut read: impl FnMut(usize, &mut [u8]) -> usize,
+) -> usize {
+    if !memory_set.check_user_range(buf_ptr, buf_len, true) {
+        println!("[Fs server] Error reading file, invalid user buffer!");
+        return Errno::EFAULT.as_ret();
+    }
+    let mut buf = vec![0u8; buf_len.min(BUFFER_SIZE)];
+    let mut total = 0;
+    while total < buf_len {
+        let len = (buf_len - total).min(BUFFER_SIZE);
+        let n = read(total, &mut buf[..len]);
+        memory_set.copy_to_user(buf_ptr + total, &buf[..n]);
+        total += n;
+        if n < len {
+            break;
+        }
+    }
+    total
+}
+
+/// 经由内核缓冲区将用户地址buf_ptr处的数据写入文件
+///
+/// write(已写入长度, 缓冲区)将数据写入文件，返回写入的总字节数，用户地址不合法返回EFAULT
+fn write_from_user(
+    memory_set: &MemorySet,
+    buf_ptr: usize,
+    buf_len: usize,
+    mut write: impl FnMut(usize, &[u8]) -> usize,
+) -> usize {
+    if !memory_set.check_user_range(buf_ptr, buf_len, false) {
+        println!("[Fs server] Error writing file, invalid user buffer!");
+        return Errno::EFAULT.as_ret();
+    }
+    let mut buf = vec![0u8; buf_len.min(BUFFER_SIZE)];
+    let mut total = 0;
+    while total < buf_len {
+        let len = (buf_len - total).min(BUFFER_SIZE);
+        memory_set.copy_from_user(buf_ptr + total, &mut buf[..len]);
+        let n = write(total, &buf[..len]);
+        total += n;
+        if n < len {
+            break;
+        }
+    }
+    total
 }
@@ -5,4 +5,7 @@ use super::*;
 pub trait Processor: Send + Sync {
     /// 处理当前请求，完毕后唤醒相应的等待协程
     fn process_request(&self, request: Request);
+
+    /// 请求队列为空时调用，执行后台工作
+    fn process_idle(&self) {}
 }
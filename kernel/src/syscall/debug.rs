@@ -1,54 +1,78 @@
 //! 一些调试用的系统调用
+use super::SyscallResult;
 use crate::fs::ROOT_INODE;
 use crate::print;
 use crate::println;
 use crate::serial::serial_receive;
-use alloc::string::String;
+use crate::task::current_proc;
+use core::mem::size_of;
+use user_syscall::Errno;
 
-/// 输出用户态内容
+/// 输出用户态地址msg_ptr处长度为msg_len的字符串
 ///
-/// TODO: 将用户和内核的数据传输封装起来提高安全性
-pub fn sys_debug_write(msg_ptr: usize) -> (usize, usize) {
-    let msg_ptr = msg_ptr as *const &str;
-    let msg = unsafe { *(msg_ptr) };
+/// 地址不合法时返回EFAULT
+pub fn sys_debug_write(msg_ptr: usize, msg_len: usize) -> SyscallResult {
+    let msg = current_proc()
+        .memory_set()
+        .read_user_str(msg_ptr, msg_len)
+        .ok_or(Errno::EFAULT)?;
     print!("{}", msg);
-    (msg.len(), 0)
+    Ok((msg.len(), 0))
 }
 
 /// 从串口读入一个字节并写入用户态buf
-pub fn sys_serial_read(buf_ptr: usize) -> (usize, usize) {
-    let buf = unsafe { core::slice::from_raw_parts_mut(buf_ptr as *mut u8, 1) };
+pub fn sys_serial_read(buf_ptr: usize) -> SyscallResult {
+    let memory_set = current_proc().memory_set();
+    if !memory_set.check_user_range(buf_ptr, 1, true) {
+        return Err(Errno::EFAULT);
+    }
     let char = serial_receive();
-    buf[0] = char;
-    (1, char as _)
+    memory_set.copy_to_user(buf_ptr, &[char]);
+    Ok((1, char as _))
 }
 
 /// 用户态数据传输
-pub fn sys_debug_data_transport(bufs_ptr: usize, ret_ptr: usize) -> (usize, usize) {
-    let bufs_ptr = bufs_ptr as *const &[&[u8]];
-    let buffers = unsafe { *bufs_ptr };
-    // let mut buffer_vector: Vec<Arc<DataBuffer>> = Vec::with_capacity(buffers.len());
-    for &buffer in buffers {
-        println!("[In sys_debug_transport] buf_ptr = {:#x?}", buffer.as_ptr());
-        let buffer = buffer.to_vec();
-        let read_str = String::from_utf8(buffer).ok();
-        println!("Kernel read str {}", read_str.unwrap());
+///
+/// bufs_ptr处为bufs_count个(ptr, len)，内核读取每个缓冲区，
+/// 并将内核中的字符串写入ret_ptr处长度为ret_len的缓冲区
+pub fn sys_debug_data_transport(
+    bufs_ptr: usize,
+    bufs_count: usize,
+    ret_ptr: usize,
+    ret_len: usize,
+) -> SyscallResult {
+    let memory_set = current_proc().memory_set();
+    let pair_size = 2 * size_of::<usize>();
+    for i in 0..bufs_count {
+        let buf_ptr = memory_set
+            .read_user_usize(bufs_ptr + i * pair_size)
+            .ok_or(Errno::EFAULT)?;
+        let buf_len = memory_set
+            .read_user_usize(bufs_ptr + i * pair_size + size_of::<usize>())
+            .ok_or(Errno::EFAULT)?;
+        println!("[In sys_debug_transport] buf_ptr = {:#x?}", buf_ptr);
+        let read_str = memory_set
+            .read_user_str(buf_ptr, buf_len)
+            .ok_or(Errno::EFAULT)?;
+        println!("Kernel read str {}", read_str);
     }
 
     let kernel_str = "This is str form kernel!".as_bytes();
-    let ret_ptr = ret_ptr as *mut u8;
-    unsafe {
-        ret_ptr.copy_from_nonoverlapping(kernel_str.as_ptr(), kernel_str.len());
-    }
-    (0, 0)
+    let len = kernel_str.len().min(ret_len);
+    memory_set
+        .copy_to_user(ret_ptr, &kernel_str[..len])
+        .ok_or(Errno::EFAULT)?;
+    Ok((0, 0))
 }
 
 /// 测试是否能通过ROOT_INODE查找
-pub fn sys_debug_open(name_ptr: usize) -> (usize, usize) {
-    let name_ptr = name_ptr as *const &str;
-    let name = unsafe { *name_ptr };
+pub fn sys_debug_open(name_ptr: usize, name_len: usize) -> SyscallResult {
+    let name = current_proc()
+        .memory_set()
+        .read_user_str(name_ptr, name_len)
+        .ok_or(Errno::EFAULT)?;
     println!("[In sys_debug_open] Kernel received: {}", name);
-    let inode = ROOT_INODE.find(name);
+    let inode = ROOT_INODE.find(&name);
     assert!(inode.is_some());
-    (0, 0)
+    Ok((0, 0))
 }
@@ -1,44 +1,102 @@
 //! 文件相关系统调用
 
+use super::SyscallResult;
 use crate::*;
+use alloc::sync::Arc;
+use alloc::vec;
+use core::mem::size_of;
 use fs::*;
-use future::{executor, futures::WaitForKthread};
+use future::executor;
+use future::futures::{fs::WaitForPoll, WaitForKthread};
+use mm::{MemorySet, SharedMemory};
+use num::FromPrimitive;
 use requests_info::fsreqinfo::FsReqDescription;
 use requests_info::CastBytes;
 use task::CURRENT_THREAD;
 use trap::{KthreadType, KTHREAD_MAP};
+use user_syscall::{Dirent, Errno, FcntlCmd, PollEvents, PollFd, Stat, Whence};
 
-/// 当前进程打开文件
+/// 同步读写标准输入输出和管道时内核缓冲区的大小
+const BUFFER_SIZE: usize = 4096;
+
+/// poll一次最多等待的文件个数
+const POLL_MAX_FDS: usize = 1024;
+
+/// poll一直等待时的timeout
+const POLL_INFINITE: usize = usize::MAX;
+
+/// 将文件系统请求发送给fs内核线程，当前线程进入异步等待
+///
+/// 请求完成后fs线程将结果写入用户态，fs线程不存在返回EIO
+fn send_fs_request(fsreq: impl FnOnce(usize) -> FsReqDescription) -> SyscallResult {
+    let fs_kthread = match KTHREAD_MAP.get().get(&KthreadType::FS) {
+        Some(fs_kthread) => fs_kthread.clone(),
+        None => {
+            println!("[Kernel] Error when sending fs request, FS kthread not exist!");
+            return Err(Errno::EIO);
+        }
+    };
+    let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
+    let pid = current_thread.proc().unwrap().pid();
+    // 构造fsreq并发送给fskthread
+    let req_id = fs_kthread.add_request(fsreq(pid).as_bytes().to_vec());
+    // 当前线程进入异步等待
+    current_thread.set_state(trap::ThreadState::Waiting);
+    // 生成等待协程
+    executor::spawn(WaitForKthread::new(current_thread, fs_kthread, req_id));
+    Ok((0, 0))
+}
+
+/// 获取当前进程fd对应的磁盘文件
+///
+/// 不存在此文件返回EBADF，不是磁盘文件返回ESPIPE
+fn get_osinode(fd: usize) -> Result<Arc<OSInode>, Errno> {
+    match current_proc().file_table().get(fd) {
+        Some(Some(file)) => file
+            .clone()
+            .downcast_arc::<OSInode>()
+            .map_err(|_| Errno::ESPIPE),
+        _ => Err(Errno::EBADF),
+    }
+}
+
+/// 获取管道或已连接的套接字中用于写入（write为true）或读取的管道端
+///
+/// 不是管道或套接字返回None，套接字未连接返回ENOTCONN
+fn stream_pipe(file: &Arc<dyn File>, write: bool) -> Result<Option<Arc<Pipe>>, Errno> {
+    if let Ok(pipe) = file.clone().downcast_arc::<Pipe>() {
+        Ok(Some(pipe))
+    } else if let Ok(socket) = file.clone().downcast_arc::<Socket>() {
+        socket.stream(write).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// 当前进程打开文件，路径为用户地址path_ptr处长度为path_len的字符串
 ///
 /// 异步系统调用，发送请求给fs内核线程并异步等待被唤醒
 /// 所以不能直接使用寄存器传递返回fd，需要将fd指针传递
 /// 给内核线程，服务完成后线程将fd写入用户态
 ///
-/// 若fs线程不存在或发生其他错误，则返回(usize::MAX)
-pub fn sys_open(path_ptr: usize, flags: usize, fd_ptr: usize) -> (usize, usize) {
-    let fs_kthread = KTHREAD_MAP.get().get(&KthreadType::FS);
-    match fs_kthread {
-        Some(fs_kthread) => {
-            let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
-            let pid = current_thread.proc().unwrap().pid();
-            // 构造fsreq
-            let fsreq = FsReqDescription::Open(pid, path_ptr, flags as _, fd_ptr)
-                .as_bytes()
-                .to_vec();
-            // 发送请求给fskthread
-            let fs_kthread = fs_kthread.clone();
-            let req_id = fs_kthread.add_request(fsreq);
-            // 当前线程进入异步等待
-            current_thread.set_state(trap::ThreadState::Waiting);
-            // 生成等待协程
-            executor::spawn(WaitForKthread::new(current_thread, fs_kthread, req_id));
-            return (0, 0);
-        }
-        None => {
-            println!("[Kernel] Error when sys_open, FS kthread not exist!");
-            return (usize::MAX, 0);
-        }
+/// 用户地址不合法返回EFAULT，fs线程不存在返回EIO；
+/// 命名管道不需要读写磁盘，直接打开，可能等待另一端打开
+pub fn sys_open(path_ptr: usize, path_len: usize, flags: usize, fd_ptr: usize) -> SyscallResult {
+    let memory_set = current_proc().memory_set();
+    if !memory_set.check_user_range(path_ptr, path_len, false)
+        || !memory_set.check_user_range(fd_ptr, size_of::<usize>(), true)
+    {
+        return Err(Errno::EFAULT);
     }
+    let fifo = ROOT_INODE
+        .lookup(&read_path(path_ptr, path_len)?)
+        .filter(|inode| inode.is_fifo());
+    if let Some(fifo) = fifo {
+        let flags = OpenFlags::from_bits(flags as u32).ok_or(Errno::EINVAL)?;
+        open_fifo(&fifo, flags, fd_ptr)?;
+        return Ok((0, 0));
+    }
+    send_fs_request(|pid| FsReqDescription::Open(pid, path_ptr, path_len, flags as _, fd_ptr))
 }
 
 /// 读取当前进程的fd对应的文件
@@ -46,10 +104,10 @@ pub fn sys_open(path_ptr: usize, flags: usize, fd_ptr: usize) -> (usize, usize)
 /// 若是标准输入输出则直接读取，
 /// 磁盘文件则发送请求给内核线程
 ///
-/// 不存在此文件则返回usize::MAX
+/// 不存在此文件或文件不可读返回EBADF，用户地址不合法返回EFAULT
 /// (0, 0)表示异步，返回值还未写入;
 /// (read_size, 0)表示同步，可以直接使用返回值
-pub fn sys_read(fd: usize, buf_ptr: usize, buf_len: usize, result_ptr: usize) -> (usize, usize) {
+pub fn sys_read(fd: usize, buf_ptr: usize, buf_len: usize, result_ptr: usize) -> SyscallResult {
     let current_proc = current_proc();
     let file_table = current_proc.file_table();
     // [Debug]
@@ -59,44 +117,42 @@ pub fn sys_read(fd: usize, buf_ptr: usize, buf_len: usize, result_ptr: usize) ->
         f.clone()
     // 不存在这个文件，直接返回不进行任何处理
     } else {
-        return (usize::MAX, 0);
+        return Err(Errno::EBADF);
     };
+    if !file.readable() {
+        return Err(Errno::EBADF);
+    }
+    // 检查用户缓冲区
+    if !current_proc
+        .memory_set()
+        .check_user_range(buf_ptr, buf_len, true)
+    {
+        return Err(Errno::EFAULT);
+    }
     // 磁盘文件OSInode，则发送请求给fs内核线程
-    if let Ok(_osinode) = file.clone().downcast_arc::<OSInode>() {
-        let fs_kthread = KTHREAD_MAP.get().get(&KthreadType::FS);
-        match fs_kthread {
-            Some(fs_kthread) => {
-                let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
-                let pid = current_thread.proc().unwrap().pid();
-                // 构造fsreq
-                let fsreq = FsReqDescription::Read(pid, fd, buf_ptr, buf_len, result_ptr)
-                    .as_bytes()
-                    .to_vec();
-                // 发送请求给fskthread
-                let fs_kthread = fs_kthread.clone();
-                let req_id = fs_kthread.add_request(fsreq);
-                // 当前线程进入异步等待
-                current_thread.set_state(trap::ThreadState::Waiting);
-                // 生成等待协程
-                executor::spawn(WaitForKthread::new(current_thread, fs_kthread, req_id));
-                return (0, 0);
-            }
-            // fs-server线程不存在，返回usize::MAX
-            None => {
-                println!("[Kernel] Error when sys_open, FS kthread not exist!");
-                return (usize::MAX, 0);
-            }
+    if file.clone().downcast_arc::<OSInode>().is_ok() {
+        send_fs_request(|pid| FsReqDescription::Read(pid, fd, buf_ptr, buf_len, result_ptr))
+    // 管道或已连接的套接字，读取的字节数写入result_ptr，没有数据时异步等待
+    } else if let Some(pipe) = stream_pipe(&file, false)? {
+        if !current_proc
+            .memory_set()
+            .check_user_range(result_ptr, size_of::<usize>(), true)
+        {
+            return Err(Errno::EFAULT);
         }
-    // 管道，需要异步读取
-    } else if let Ok(pipe) = file.clone().downcast_arc::<Pipe>() {
-        pipe.async_read(pipe.clone(), buf_ptr, buf_len, result_ptr);
-        return (0, 0);
+        pipe.async_read(buf_ptr, buf_len, result_ptr);
+        Ok((0, 0))
+    // 非阻塞模式下没有数据时返回EAGAIN
+    } else if file.nonblocking() && !file.poll().contains(PollEvents::IN) {
+        Err(Errno::EAGAIN)
     // 若是标准输入输出则直接读取不发送请求
     } else {
-        let buf_ptr = buf_ptr as *mut u8;
-        let buf = unsafe { core::slice::from_raw_parts_mut(buf_ptr, buf_len) };
-        let read_size = file.read(buf);
-        (read_size, 0)
+        let mut buf = vec![0u8; buf_len.min(BUFFER_SIZE)];
+        let read_size = file.read(&mut buf);
+        current_proc
+            .memory_set()
+            .copy_to_user(buf_ptr, &buf[..read_size]);
+        Ok((read_size, 0))
     }
 }
 
@@ -105,10 +161,10 @@ pub fn sys_read(fd: usize, buf_ptr: usize, buf_len: usize, result_ptr: usize) ->
 /// 若是标准输入输出则直接写入
 /// 磁盘文件则发送请求给内核线程
 ///
-/// 出错则返回usize::MAX
+/// 不存在此文件或文件不可写返回EBADF，用户地址不合法返回EFAULT
 /// (0, 0)表示异步，返回值还未写入;
 /// (read_size, 0)表示同步，可以直接使用返回值
-pub fn sys_write(fd: usize, buf_ptr: usize, buf_len: usize, result_ptr: usize) -> (usize, usize) {
+pub fn sys_write(fd: usize, buf_ptr: usize, buf_len: usize, result_ptr: usize) -> SyscallResult {
     let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
     let current_proc = current_thread.proc().unwrap();
     let file_table = current_proc.file_table();
@@ -116,95 +172,421 @@ pub fn sys_write(fd: usize, buf_ptr: usize, buf_len: usize, result_ptr: usize) -
         file.clone()
     // 不存在这个文件，直接返回不进行任何处理
     } else {
-        return (usize::MAX, 0);
+        return Err(Errno::EBADF);
     };
+    if !file.writable() {
+        return Err(Errno::EBADF);
+    }
+    // 检查用户缓冲区
+    if !current_proc
+        .memory_set()
+        .check_user_range(buf_ptr, buf_len, false)
+    {
+        return Err(Errno::EFAULT);
+    }
     // 磁盘文件OSInode，则发送请求给fs内核线程
-    if let Ok(_osinode) = file.clone().downcast_arc::<OSInode>() {
-        let fs_kthread = KTHREAD_MAP.get().get(&KthreadType::FS);
-        match fs_kthread {
-            Some(fs_kthread) => {
-                let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
-                let pid = current_thread.proc().unwrap().pid();
-                // 构造fsreq
-                let fsreq = FsReqDescription::Write(pid, fd, buf_ptr, buf_len, result_ptr)
-                    .as_bytes()
-                    .to_vec();
-                // 发送请求给fskthread
-                let fs_kthread = fs_kthread.clone();
-                let req_id = fs_kthread.add_request(fsreq);
-                // 当前线程进入异步等待
-                current_thread.set_state(trap::ThreadState::Waiting);
-                // 生成等待协程
-                executor::spawn(WaitForKthread::new(current_thread, fs_kthread, req_id));
-                return (0, 0);
-            }
-            // fs-server线程不存在，返回usize::MAX
-            None => {
-                println!("[Kernel] Error when sys_open, FS kthread not exist!");
-                return (usize::MAX, 0);
-            }
+    if file.clone().downcast_arc::<OSInode>().is_ok() {
+        send_fs_request(|pid| FsReqDescription::Write(pid, fd, buf_ptr, buf_len, result_ptr))
+    // 管道或已连接的套接字，写入的字节数或错误码写入result_ptr，缓冲区满时异步等待
+    } else if let Some(pipe) = stream_pipe(&file, true)? {
+        if !current_proc
+            .memory_set()
+            .check_user_range(result_ptr, size_of::<usize>(), true)
+        {
+            return Err(Errno::EFAULT);
         }
-    // 若是标准输入输出或管道则直接写入（同步）不发送请求
+        pipe.async_write(buf_ptr, buf_len, result_ptr);
+        Ok((0, 0))
+    // 若是标准输入输出则直接写入（同步）不发送请求
     } else {
-        let buf_ptr = buf_ptr as *const u8;
-        let buf = unsafe { core::slice::from_raw_parts(buf_ptr, buf_len) };
-        let read_size = file.write(buf);
-        (read_size, 0)
+        let memory_set = current_proc.memory_set();
+        let mut buf = vec![0u8; buf_len.min(BUFFER_SIZE)];
+        let mut write_size = 0;
+        while write_size < buf_len {
+            let len = (buf_len - write_size).min(BUFFER_SIZE);
+            memory_set.copy_from_user(buf_ptr + write_size, &mut buf[..len]);
+            let n = file.write(&buf[..len]);
+            write_size += n;
+            if n < len {
+                break;
+            }
+        }
+        Ok((write_size, 0))
+    }
+}
+
+/// 移动fd对应的磁盘文件的读写位置，offset为有符号偏移
+///
+/// 异步系统调用，新的位置由fs内核线程写入result_ptr处；
+/// whence不合法返回EINVAL，不是磁盘文件返回ESPIPE
+pub fn sys_lseek(fd: usize, offset: usize, whence: usize, result_ptr: usize) -> SyscallResult {
+    get_osinode(fd)?;
+    Whence::from_usize(whence).ok_or(Errno::EINVAL)?;
+    if !current_proc()
+        .memory_set()
+        .check_user_range(result_ptr, size_of::<usize>(), true)
+    {
+        return Err(Errno::EFAULT);
+    }
+    send_fs_request(|pid| FsReqDescription::Lseek(pid, fd, offset, whence, result_ptr))
+}
+
+/// 从fd对应的磁盘文件的offset处读取，不移动读写位置
+///
+/// 异步系统调用，读取的字节数由fs内核线程写入result_ptr处；
+/// 文件不可读返回EBADF，不是磁盘文件返回ESPIPE
+pub fn sys_pread(
+    fd: usize,
+    buf_ptr: usize,
+    buf_len: usize,
+    offset: usize,
+    result_ptr: usize,
+) -> SyscallResult {
+    if !get_osinode(fd)?.readable() {
+        return Err(Errno::EBADF);
+    }
+    let memory_set = current_proc().memory_set();
+    if !memory_set.check_user_range(buf_ptr, buf_len, true)
+        || !memory_set.check_user_range(result_ptr, size_of::<usize>(), true)
+    {
+        return Err(Errno::EFAULT);
     }
+    send_fs_request(|pid| FsReqDescription::Pread(pid, fd, buf_ptr, buf_len, offset, result_ptr))
+}
+
+/// 从fd对应的磁盘文件的offset处写入，不移动读写位置
+///
+/// 异步系统调用，写入的字节数由fs内核线程写入result_ptr处；
+/// 文件不可写返回EBADF，不是磁盘文件返回ESPIPE
+pub fn sys_pwrite(
+    fd: usize,
+    buf_ptr: usize,
+    buf_len: usize,
+    offset: usize,
+    result_ptr: usize,
+) -> SyscallResult {
+    if !get_osinode(fd)?.writable() {
+        return Err(Errno::EBADF);
+    }
+    let memory_set = current_proc().memory_set();
+    if !memory_set.check_user_range(buf_ptr, buf_len, false)
+        || !memory_set.check_user_range(result_ptr, size_of::<usize>(), true)
+    {
+        return Err(Errno::EFAULT);
+    }
+    send_fs_request(|pid| FsReqDescription::Pwrite(pid, fd, buf_ptr, buf_len, offset, result_ptr))
+}
+
+/// 读取目录fd中的目录项到用户缓冲区，每个目录项为一个Dirent
+///
+/// 异步系统调用，写入的字节数由fs内核线程写入result_ptr处；
+/// 文件不存在返回EBADF，不是目录返回ENOTDIR，缓冲区放不下一个目录项返回EINVAL
+pub fn sys_getdents(fd: usize, buf_ptr: usize, buf_len: usize, result_ptr: usize) -> SyscallResult {
+    let osinode = get_osinode(fd).map_err(|errno| match errno {
+        Errno::ESPIPE => Errno::ENOTDIR,
+        errno => errno,
+    })?;
+    if !osinode.inode().is_dir() {
+        return Err(Errno::ENOTDIR);
+    }
+    if buf_len < size_of::<Dirent>() {
+        return Err(Errno::EINVAL);
+    }
+    let memory_set = current_proc().memory_set();
+    if !memory_set.check_user_range(buf_ptr, buf_len, true)
+        || !memory_set.check_user_range(result_ptr, size_of::<usize>(), true)
+    {
+        return Err(Errno::EFAULT);
+    }
+    send_fs_request(|pid| FsReqDescription::Getdents(pid, fd, buf_ptr, buf_len, result_ptr))
+}
+
+/// 将路径为用户地址path_ptr处长度为path_len的字符串的文件截断或扩展到len字节
+///
+/// 异步系统调用，结果由fs内核线程写入result_ptr处；用户地址不合法返回EFAULT
+pub fn sys_truncate(
+    path_ptr: usize,
+    path_len: usize,
+    len: usize,
+    result_ptr: usize,
+) -> SyscallResult {
+    let memory_set = current_proc().memory_set();
+    if !memory_set.check_user_range(path_ptr, path_len, false)
+        || !memory_set.check_user_range(result_ptr, size_of::<usize>(), true)
+    {
+        return Err(Errno::EFAULT);
+    }
+    send_fs_request(|pid| FsReqDescription::Truncate(pid, path_ptr, path_len, len, result_ptr))
+}
+
+/// 将fd对应的磁盘文件或共享内存对象截断或扩展到len字节，不移动读写位置
+///
+/// 磁盘文件为异步系统调用，结果由fs内核线程写入result_ptr处；
+/// 文件不存在返回EBADF，不是磁盘文件或共享内存对象、不可写返回EINVAL，
+/// 共享内存对象超过最大长度返回EFBIG、没有足够的页帧返回ENOMEM
+pub fn sys_ftruncate(fd: usize, len: usize, result_ptr: usize) -> SyscallResult {
+    if let Some(Some(file)) = current_proc().file_table().get(fd) {
+        // 共享内存对象直接在内存中截断
+        if let Ok(shm) = file.clone().downcast_arc::<SharedMemory>() {
+            shm.truncate(len)?;
+            current_proc()
+                .memory_set()
+                .write_user_usize(result_ptr, 0)
+                .ok_or(Errno::EFAULT)?;
+            return Ok((0, 0));
+        }
+    }
+    let osinode = get_osinode(fd).map_err(|errno| match errno {
+        Errno::ESPIPE => Errno::EINVAL,
+        errno => errno,
+    })?;
+    if !osinode.writable() {
+        return Err(Errno::EINVAL);
+    }
+    if !current_proc()
+        .memory_set()
+        .check_user_range(result_ptr, size_of::<usize>(), true)
+    {
+        return Err(Errno::EFAULT);
+    }
+    send_fs_request(|pid| FsReqDescription::Ftruncate(pid, fd, len, result_ptr))
+}
+
+/// 将块缓存中的所有脏块写回磁盘
+///
+/// 异步系统调用，结果由fs内核线程写入result_ptr处
+pub fn sys_sync(result_ptr: usize) -> SyscallResult {
+    if !current_proc()
+        .memory_set()
+        .check_user_range(result_ptr, size_of::<usize>(), true)
+    {
+        return Err(Errno::EFAULT);
+    }
+    send_fs_request(|pid| FsReqDescription::Sync(pid, result_ptr))
+}
+
+/// 将fd对应的磁盘文件写回磁盘
+///
+/// 异步系统调用，结果由fs内核线程写入result_ptr处；
+/// 文件不存在返回EBADF，不是磁盘文件返回EINVAL
+pub fn sys_fsync(fd: usize, result_ptr: usize) -> SyscallResult {
+    get_osinode(fd).map_err(|errno| match errno {
+        Errno::ESPIPE => Errno::EINVAL,
+        errno => errno,
+    })?;
+    if !current_proc()
+        .memory_set()
+        .check_user_range(result_ptr, size_of::<usize>(), true)
+    {
+        return Err(Errno::EFAULT);
+    }
+    send_fs_request(|pid| FsReqDescription::Fsync(pid, fd, result_ptr))
 }
 
 /// 当前进程关闭描述符为fd的文件
 ///
-/// 成功返回0，否则返回usize::MAX
-pub fn sys_close(fd: usize) -> (usize, usize) {
+/// 成功返回0，文件不存在返回EBADF
+pub fn sys_close(fd: usize) -> SyscallResult {
     let current_proc = current_proc();
     let file_table = current_proc.file_table();
     if let Some(file) = file_table.get_mut(fd) {
         if core::mem::replace(file, None).is_some() {
-            return (0, 0);
+            return Ok((0, 0));
         }
     }
-    (usize::MAX, 0)
+    Err(Errno::EBADF)
 }
 
 /// 创建管道，返回读端和写端的fd
-pub fn sys_pipe() -> (usize, usize) {
+pub fn sys_pipe() -> SyscallResult {
     let current_proc = current_proc();
     let (read_end, write_end) = make_pipe();
     let (read_fd, write_fd) = (
         current_proc.add_file(read_end),
         current_proc.add_file(write_end),
     );
-    (read_fd, write_fd)
+    Ok((read_fd, write_fd))
+}
+
+/// 获取或设置fd对应的打开文件的标志
+///
+/// GetFl返回访问模式和NONBLOCK，SetFl按arg设置NONBLOCK，其余标志被忽略；
+/// 不存在此文件返回EBADF，cmd不合法返回EINVAL
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> SyscallResult {
+    let file = match current_proc().file_table().get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return Err(Errno::EBADF),
+    };
+    match FcntlCmd::from_usize(cmd).ok_or(Errno::EINVAL)? {
+        FcntlCmd::GetFl => {
+            let mut flags = match (file.readable(), file.writable()) {
+                (true, true) => OpenFlags::RDWR,
+                (false, true) => OpenFlags::WRONLY,
+                _ => OpenFlags::RDONLY,
+            };
+            flags.set(OpenFlags::NONBLOCK, file.nonblocking());
+            Ok((flags.bits() as usize, 0))
+        }
+        FcntlCmd::SetFl => {
+            let flags = OpenFlags::from_bits_truncate(arg as u32);
+            file.set_nonblocking(flags.contains(OpenFlags::NONBLOCK));
+            Ok((0, 0))
+        }
+    }
+}
+
+/// 等待用户地址fds_ptr处的nfds个PollFd中任一文件就绪，timeout为等待的毫秒数
+///
+/// 已有文件就绪或timeout为0时直接完成，否则当前线程进入等待，
+/// 由协程在文件就绪或超时后完成；完成时将revents写回用户态，就绪的文件个数写入result_ptr。
+/// nfds过大返回EINVAL，用户地址不合法返回EFAULT
+pub fn sys_poll(fds_ptr: usize, nfds: usize, timeout: usize, result_ptr: usize) -> SyscallResult {
+    if nfds > POLL_MAX_FDS {
+        return Err(Errno::EINVAL);
+    }
+    let current_thread = current_thread();
+    let current_proc = current_thread.proc().unwrap();
+    let memory_set = current_proc.memory_set();
+    let fds_len = nfds * size_of::<PollFd>();
+    if !memory_set.check_user_range(fds_ptr, fds_len, true)
+        || !memory_set.check_user_range(result_ptr, size_of::<usize>(), true)
+    {
+        return Err(Errno::EFAULT);
+    }
+    let mut bytes = vec![0u8; fds_len];
+    memory_set.copy_from_user(fds_ptr, &mut bytes);
+    let mut fds: Vec<PollFd> = bytes
+        .chunks_exact(size_of::<PollFd>())
+        .map(|chunk| unsafe { (chunk.as_ptr() as *const PollFd).read_unaligned() })
+        .collect();
+    let file_table = current_proc.file_table();
+    let files: Vec<Option<Arc<dyn File>>> = fds
+        .iter()
+        .map(|fd| file_table.get(fd.fd).cloned().flatten())
+        .collect();
+    let ready = poll_files(&files, &mut fds);
+    if ready > 0 || timeout == 0 {
+        copy_poll_result(&memory_set, fds_ptr, &fds, ready, result_ptr);
+        return Ok((0, 0));
+    }
+    let deadline = if timeout == POLL_INFINITE {
+        None
+    } else {
+        Some(pic::TICKS.saturating_add(timeout))
+    };
+    current_thread.set_state(ThreadState::Waiting);
+    executor::spawn(async move {
+        let ready = WaitForPoll::new(&files, &mut fds, deadline).await;
+        // 等待期间进程可能已经退出，此时丢弃结果
+        if let Some(proc) = current_thread.proc() {
+            copy_poll_result(&proc.memory_set(), fds_ptr, &fds, ready, result_ptr);
+            current_thread.set_state(ThreadState::Runnable);
+        }
+    });
+    Ok((0, 0))
+}
+
+/// 将poll的结果写回用户态
+fn copy_poll_result(
+    memory_set: &MemorySet,
+    fds_ptr: usize,
+    fds: &[PollFd],
+    ready: usize,
+    result_ptr: usize,
+) {
+    let bytes = unsafe { core::slice::from_raw_parts(fds.as_ptr() as *const u8, size_of_val(fds)) };
+    memory_set.copy_to_user(fds_ptr, bytes);
+    memory_set.write_user_usize(result_ptr, ready);
 }
 
 /// 复制一份文件，一般与close一起使用
 ///
-/// 若文件不存在则返回usize::MAX
-pub fn sys_dup(fd: usize) -> (usize, usize) {
+/// 若文件不存在则返回EBADF
+pub fn sys_dup(fd: usize) -> SyscallResult {
     let current_proc = current_proc();
     let file_table = current_proc.file_table();
     let file = if let Some(Some(f)) = file_table.get(fd) {
         f.clone()
     } else {
-        return (usize::MAX, 0);
+        return Err(Errno::EBADF);
     };
-    (current_proc.add_file(file), 0)
-}
-
-/// 列出可用用户app
-pub fn sys_ls() -> (usize, usize) {
-    let step = 7;
-    let apps = ROOT_INODE.ls();
-    for i in (0..apps.len()).step_by(step) {
-        for j in i..i + step {
-            if j < apps.len() {
-                print!("{:<20}", apps[j]);
-            } else {
-                break;
-            }
-        }
-        println!("");
-    }
-    (0, 0)
+    Ok((current_proc.add_file(file), 0))
+}
+
+/// 读取用户地址path_ptr处长度为path_len的路径，地址不合法返回EFAULT
+fn read_path(path_ptr: usize, path_len: usize) -> Result<String, Errno> {
+    current_proc()
+        .memory_set()
+        .read_user_str(path_ptr, path_len)
+        .ok_or(Errno::EFAULT)
+}
+
+/// 创建目录，路径为用户地址path_ptr处长度为path_len的字符串
+///
+/// 成功返回0，用户地址不合法返回EFAULT
+pub fn sys_mkdir(path_ptr: usize, path_len: usize) -> SyscallResult {
+    make_dir(&read_path(path_ptr, path_len)?)?;
+    Ok((0, 0))
+}
+
+/// 创建命名管道，路径为用户地址path_ptr处长度为path_len的字符串
+///
+/// 成功返回0，用户地址不合法返回EFAULT
+pub fn sys_mkfifo(path_ptr: usize, path_len: usize) -> SyscallResult {
+    make_fifo(&read_path(path_ptr, path_len)?)?;
+    Ok((0, 0))
+}
+
+/// 删除空目录，路径为用户地址path_ptr处长度为path_len的字符串
+///
+/// 成功返回0，用户地址不合法返回EFAULT
+pub fn sys_rmdir(path_ptr: usize, path_len: usize) -> SyscallResult {
+    remove_dir(&read_path(path_ptr, path_len)?)?;
+    Ok((0, 0))
+}
+
+/// 获取路径为用户地址path_ptr处长度为path_len的字符串的文件元数据，写入stat_ptr处
+///
+/// 成功返回0，用户地址不合法返回EFAULT
+pub fn sys_stat(path_ptr: usize, path_len: usize, stat_ptr: usize) -> SyscallResult {
+    let stat = stat_file(&read_path(path_ptr, path_len)?)?;
+    copy_stat_to_user(stat_ptr, &stat)
+}
+
+/// 获取当前进程fd对应的文件的元数据，写入stat_ptr处
+///
+/// 成功返回0，文件不存在返回EBADF，用户地址不合法返回EFAULT
+pub fn sys_fstat(fd: usize, stat_ptr: usize) -> SyscallResult {
+    let stat = match current_proc().file_table().get(fd) {
+        Some(Some(file)) => file.stat(),
+        _ => return Err(Errno::EBADF),
+    };
+    copy_stat_to_user(stat_ptr, &stat)
+}
+
+/// 将元数据写入用户地址stat_ptr处
+fn copy_stat_to_user(stat_ptr: usize, stat: &Stat) -> SyscallResult {
+    let bytes =
+        unsafe { core::slice::from_raw_parts(stat as *const Stat as *const u8, size_of::<Stat>()) };
+    current_proc()
+        .memory_set()
+        .copy_to_user(stat_ptr, bytes)
+        .ok_or(Errno::EFAULT)?;
+    Ok((0, 0))
+}
+
+/// 删除文件，路径为用户地址path_ptr处长度为path_len的字符串
+///
+/// 成功返回0，用户地址不合法返回EFAULT
+pub fn sys_unlink(path_ptr: usize, path_len: usize) -> SyscallResult {
+    unlink_file(&read_path(path_ptr, path_len)?)?;
+    Ok((0, 0))
+}
+
+/// 重命名文件或目录，新旧路径均为用户地址处的字符串
+///
+/// 成功返回0，用户地址不合法返回EFAULT
+pub fn sys_rename(old_ptr: usize, old_len: usize, new_ptr: usize, new_len: usize) -> SyscallResult {
+    rename_file(&read_path(old_ptr, old_len)?, &read_path(new_ptr, new_len)?)?;
+    Ok((0, 0))
 }
@@ -0,0 +1,175 @@
+//! 内存管理相关系统调用
+
+use super::SyscallResult;
+use crate::*;
+use fs::{File, OSInode};
+use mm::{is_aligned, range_end, MemAreaType, PageTableFlags, SharedMemory};
+use user_syscall::{Errno, MmapFlags, MmapProt, OpenFlags};
+
+/// 将访问权限转换为页表映射标志，可写且可执行的映射违反W^X，返回None
+///
+/// 没有任何权限（PROT_NONE）时不含PRESENT，区域中的虚页不可访问
+fn prot_to_flags(prot: MmapProt) -> Option<PageTableFlags> {
+    if prot.contains(MmapProt::WRITE | MmapProt::EXEC) {
+        return None;
+    }
+    if prot.is_empty() {
+        return Some(PageTableFlags::NO_EXECUTE);
+    }
+    let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if prot.contains(MmapProt::WRITE) {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if !prot.contains(MmapProt::EXEC) {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    Some(flags)
+}
+
+/// 映射一段内存区域，返回区域起始地址
+///
+/// 匿名映射忽略fd和offset，文件映射的fd必须是磁盘文件或共享内存对象，
+/// 共享内存对象只能共享映射，且映射范围不能超出对象长度
+///
+/// 参数不合法返回EINVAL，fd不合法返回EBADF，文件权限不符返回EACCES，
+/// 没有足够的地址空间返回ENOMEM
+pub fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> SyscallResult {
+    let prot = MmapProt::from_bits(prot as _).ok_or(Errno::EINVAL)?;
+    let flags = MmapFlags::from_bits(flags as _).ok_or(Errno::EINVAL)?;
+    // 共享和私有必须指定且只能指定一个
+    if flags.contains(MmapFlags::SHARED) == flags.contains(MmapFlags::PRIVATE)
+        || len == 0
+        || !is_aligned(offset)
+    {
+        return Err(Errno::EINVAL);
+    }
+    // 长度对齐后溢出时不可能有足够的地址空间，固定映射的范围必须不溢出
+    range_end(0, len).ok_or(Errno::ENOMEM)?;
+    if flags.contains(MmapFlags::FIXED) && range_end(addr, len).is_none() {
+        return Err(Errno::EINVAL);
+    }
+    let pt_flags = prot_to_flags(prot).ok_or(Errno::EACCES)?;
+    let current_proc = current_proc();
+    let file = if flags.contains(MmapFlags::ANONYMOUS) {
+        None
+    } else {
+        let file = match current_proc.file_table().get(fd) {
+            Some(Some(file)) => file.clone(),
+            _ => return Err(Errno::EBADF),
+        };
+        if let Ok(shm) = file.clone().downcast_arc::<SharedMemory>() {
+            if !flags.contains(MmapFlags::SHARED) {
+                return Err(Errno::EINVAL);
+            }
+            let frames = shm.frames(offset, len).ok_or(Errno::EINVAL)?;
+            let start = current_proc
+                .memory_set()
+                .mmap_shared(addr, pt_flags, frames, flags.contains(MmapFlags::FIXED))
+                .ok_or(Errno::ENOMEM)?;
+            return Ok((start, 0));
+        }
+        let osinode = file.downcast_arc::<OSInode>().map_err(|_| Errno::EACCES)?;
+        // 可写的共享文件映射会写回文件，要求文件可写
+        if !osinode.readable()
+            || (flags.contains(MmapFlags::SHARED)
+                && prot.contains(MmapProt::WRITE)
+                && !osinode.writable())
+        {
+            return Err(Errno::EACCES);
+        }
+        Some((osinode.inode(), offset))
+    };
+    let mtype = if flags.contains(MmapFlags::SHARED) {
+        MemAreaType::SHARED
+    } else if file.is_some() {
+        MemAreaType::FILE
+    } else {
+        MemAreaType::ANONYMOUS
+    };
+    let start = current_proc
+        .memory_set()
+        .mmap(
+            addr,
+            len,
+            pt_flags,
+            mtype,
+            file,
+            flags.contains(MmapFlags::FIXED),
+        )
+        .ok_or(Errno::ENOMEM)?;
+    Ok((start, 0))
+}
+
+/// 解除[addr, addr + len)的映射
+///
+/// 成功返回0，范围不合法返回EINVAL
+pub fn sys_munmap(addr: usize, len: usize) -> SyscallResult {
+    range_end(addr, len).ok_or(Errno::EINVAL)?;
+    if current_proc().memory_set().munmap(addr, len) {
+        Ok((0, 0))
+    } else {
+        Err(Errno::EINVAL)
+    }
+}
+
+/// 修改[addr, addr + len)的访问权限
+///
+/// 成功返回0，违反W^X返回EACCES，范围溢出返回EINVAL，范围未全部映射返回ENOMEM
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> SyscallResult {
+    range_end(addr, len).ok_or(Errno::EINVAL)?;
+    let prot = MmapProt::from_bits(prot as _).ok_or(Errno::EINVAL)?;
+    let flags = prot_to_flags(prot).ok_or(Errno::EACCES)?;
+    if current_proc().memory_set().mprotect(addr, len, flags) {
+        Ok((0, 0))
+    } else {
+        Err(Errno::ENOMEM)
+    }
+}
+
+/// 设置堆顶，addr为0时查询当前堆顶
+///
+/// 成功返回新的堆顶，否则返回ENOMEM
+pub fn sys_brk(addr: usize) -> SyscallResult {
+    let brk = current_proc().memory_set().brk(addr).ok_or(Errno::ENOMEM)?;
+    Ok((brk, 0))
+}
+
+/// 读取用户地址name_ptr处长度为name_len的共享内存对象名字，地址不合法返回EFAULT
+fn read_name(name_ptr: usize, name_len: usize) -> Result<String, Errno> {
+    current_proc()
+        .memory_set()
+        .read_user_str(name_ptr, name_len)
+        .ok_or(Errno::EFAULT)
+}
+
+/// 打开用户地址name_ptr处长度为name_len的名字对应的共享内存对象，返回fd
+///
+/// flags含CREATE时不存在则新建，含TRUNC时截断为0字节。
+/// 对象不存在返回ENOENT，flags不合法返回EINVAL
+pub fn sys_shm_open(name_ptr: usize, name_len: usize, flags: usize) -> SyscallResult {
+    let flags = OpenFlags::from_bits(flags as _).ok_or(Errno::EINVAL)?;
+    let shm = SharedMemory::open(
+        read_name(name_ptr, name_len)?,
+        flags.contains(OpenFlags::CREATE),
+    )?;
+    if flags.contains(OpenFlags::TRUNC) {
+        shm.truncate(0)?;
+    }
+    Ok((current_proc().add_file(shm), 0))
+}
+
+/// 删除用户地址name_ptr处长度为name_len的名字对应的共享内存对象
+///
+/// 已打开和已映射的对象仍然有效，直到最后一个文件和映射被释放。
+/// 成功返回0，对象不存在返回ENOENT
+pub fn sys_shm_unlink(name_ptr: usize, name_len: usize) -> SyscallResult {
+    SharedMemory::unlink(&read_name(name_ptr, name_len)?)?;
+    Ok((0, 0))
+}
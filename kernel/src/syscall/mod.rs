@@ -1,30 +1,42 @@
 //! 系统调用模块
 mod debug;
 mod fs;
+mod mm;
+mod socket;
 mod sync;
 mod task;
 
 use crate::*;
 use debug::*;
 use fs::*;
+use mm::*;
+use socket::*;
 use sync::*;
 use task::*;
-use user_syscall::SyscallNum::*;
+use user_syscall::{Errno, SyscallNum::*};
+
+/// 系统调用的返回值，出错时为错误码
+pub type SyscallResult = Result<(usize, usize), Errno>;
 
 /// 系统调用总控函数
+///
+/// 出错时第一个返回值为错误码的相反数，不存在的系统调用返回ENOSYS
 pub fn syscall(syscall_id: usize, args: [usize; 6]) -> (usize, usize) {
-    let syscall_id = num::FromPrimitive::from_usize(syscall_id).unwrap();
+    let syscall_id = match num::FromPrimitive::from_usize(syscall_id) {
+        Some(syscall_id) => syscall_id,
+        None => return (Errno::ENOSYS.as_ret(), 0),
+    };
     let ret = match syscall_id {
         // 调试用
-        DebugWrite => sys_debug_write(args[0]),
-        DebugDataTransport => sys_debug_data_transport(args[0], args[1]),
-        DebugOpen => sys_debug_open(args[0]),
+        DebugWrite => sys_debug_write(args[0], args[1]),
+        DebugDataTransport => sys_debug_data_transport(args[0], args[1], args[2], args[3]),
+        DebugOpen => sys_debug_open(args[0], args[1]),
         SerialRead => sys_serial_read(args[0]),
-        GetTime => (*pic::TICKS as _, 0),
+        GetTime => Ok((*pic::TICKS as _, 0)),
 
         // 任务相关
         ProcExit => sys_proc_exit(args[0]),
-        ProcCreate => sys_proc_create(args[0], args[1], args[2]),
+        ProcCreate => sys_proc_create(args[0], args[1], args[2], args[3], args[4]),
         ProcWait => sys_proc_wait(args[0]),
         Yield => sys_yield(),
         ThreadCreate => sys_thread_create(args[0], args[1], args[2]),
@@ -33,16 +45,41 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> (usize, usize) {
         GetPid => sys_get_pid(),
         GetTid => sys_get_tid(),
         Fork => sys_fork(),
-        Exec => sys_exec(args[0], args[1]),
+        Exec => sys_exec(args[0], args[1], args[2]),
 
         // 文件相关
-        Open => sys_open(args[0], args[1], args[2]),
+        Open => sys_open(args[0], args[1], args[2], args[3]),
         Close => sys_close(args[0]),
         Read => sys_read(args[0], args[1], args[2], args[3]),
         Write => sys_write(args[0], args[1], args[2], args[3]),
         Pipe => sys_pipe(),
         Dup => sys_dup(args[0]),
-        Ls => sys_ls(),
+        Mkdir => sys_mkdir(args[0], args[1]),
+        Rmdir => sys_rmdir(args[0], args[1]),
+        Mkfifo => sys_mkfifo(args[0], args[1]),
+        Unlink => sys_unlink(args[0], args[1]),
+        Rename => sys_rename(args[0], args[1], args[2], args[3]),
+        Lseek => sys_lseek(args[0], args[1], args[2], args[3]),
+        Pread => sys_pread(args[0], args[1], args[2], args[3], args[4]),
+        Pwrite => sys_pwrite(args[0], args[1], args[2], args[3], args[4]),
+        Stat => sys_stat(args[0], args[1], args[2]),
+        Fstat => sys_fstat(args[0], args[1]),
+        Getdents => sys_getdents(args[0], args[1], args[2], args[3]),
+        Truncate => sys_truncate(args[0], args[1], args[2], args[3]),
+        Ftruncate => sys_ftruncate(args[0], args[1], args[2]),
+        Sync => sys_sync(args[0]),
+        Fsync => sys_fsync(args[0], args[1]),
+        Fcntl => sys_fcntl(args[0], args[1], args[2]),
+        Poll => sys_poll(args[0], args[1], args[2], args[3]),
+
+        // 套接字
+        Socket => sys_socket(),
+        Bind => sys_bind(args[0], args[1], args[2]),
+        Listen => sys_listen(args[0], args[1]),
+        Accept => sys_accept(args[0], args[1]),
+        Connect => sys_connect(args[0], args[1], args[2]),
+        Send => sys_send(args[0], args[1], args[2], args[3]),
+        Recv => sys_recv(args[0], args[1], args[2], args[3]),
 
         // 同步互斥
         MutexCreate => sys_mutex_create(),
@@ -54,6 +91,17 @@ pub fn syscall(syscall_id: usize, args: [usize; 6]) -> (usize, usize) {
         CondvarCreate => sys_condvar_create(),
         CondvarWait => sys_condvar_wait(args[0], args[1]),
         CondvarSignal => sys_condvar_signal(args[0]),
+
+        // 内存管理
+        Mmap => sys_mmap(args[0], args[1], args[2], args[3], args[4], args[5]),
+        Munmap => sys_munmap(args[0], args[1]),
+        Mprotect => sys_mprotect(args[0], args[1], args[2]),
+        Brk => sys_brk(args[0]),
+        ShmOpen => sys_shm_open(args[0], args[1], args[2]),
+        ShmUnlink => sys_shm_unlink(args[0], args[1]),
     };
-    ret
+    match ret {
+        Ok(ret) => ret,
+        Err(errno) => (errno.as_ret(), 0),
+    }
 }
@@ -0,0 +1,127 @@
+//! 套接字相关系统调用
+
+use super::{sys_read, sys_write, SyscallResult};
+use crate::*;
+use alloc::sync::{Arc, Weak};
+use core::mem::size_of;
+use fs::{File, Socket};
+use future::executor;
+use future::futures::fs::WaitForFile;
+use user_syscall::{Errno, PollEvents};
+
+/// 获取当前进程fd对应的套接字
+///
+/// 不存在此文件返回EBADF，不是套接字返回ENOTSOCK
+fn get_socket(fd: usize) -> Result<Arc<Socket>, Errno> {
+    match current_proc().file_table().get(fd) {
+        Some(Some(file)) => file
+            .clone()
+            .downcast_arc::<Socket>()
+            .map_err(|_| Errno::ENOTSOCK),
+        _ => Err(Errno::EBADF),
+    }
+}
+
+/// 读取用户地址name_ptr处长度为name_len的套接字名字，地址不合法返回EFAULT
+fn read_name(name_ptr: usize, name_len: usize) -> Result<String, Errno> {
+    current_proc()
+        .memory_set()
+        .read_user_str(name_ptr, name_len)
+        .ok_or(Errno::EFAULT)
+}
+
+/// 创建一个套接字，返回fd
+pub fn sys_socket() -> SyscallResult {
+    Ok((current_proc().add_file(Socket::new()), 0))
+}
+
+/// 将套接字fd绑定到用户地址name_ptr处长度为name_len的名字
+///
+/// 成功返回0，名字已被使用返回EADDRINUSE
+pub fn sys_bind(fd: usize, name_ptr: usize, name_len: usize) -> SyscallResult {
+    get_socket(fd)?.bind(read_name(name_ptr, name_len)?)?;
+    Ok((0, 0))
+}
+
+/// 开始监听套接字fd，backlog为等待接受的连接的最大个数
+///
+/// 成功返回0，未绑定返回EINVAL
+pub fn sys_listen(fd: usize, backlog: usize) -> SyscallResult {
+    get_socket(fd)?.listen(backlog)?;
+    Ok((0, 0))
+}
+
+/// 连接到用户地址name_ptr处长度为name_len的名字上正在监听的套接字
+///
+/// 成功返回0，连接被拒绝返回ECONNREFUSED
+pub fn sys_connect(fd: usize, name_ptr: usize, name_len: usize) -> SyscallResult {
+    get_socket(fd)?.connect(&read_name(name_ptr, name_len)?)?;
+    Ok((0, 0))
+}
+
+/// 接受套接字fd上的一个连接，新连接的fd写入fd_ptr
+///
+/// 没有等待接受的连接时当前线程进入等待，由协程在连接到达后完成；
+/// 非阻塞模式下返回EAGAIN，不在监听返回EINVAL
+pub fn sys_accept(fd: usize, fd_ptr: usize) -> SyscallResult {
+    let listener = get_socket(fd)?;
+    let current_thread = current_thread();
+    let current_proc = current_thread.proc().unwrap();
+    if !current_proc
+        .memory_set()
+        .check_user_range(fd_ptr, size_of::<usize>(), true)
+    {
+        return Err(Errno::EFAULT);
+    }
+    if let Some(conn) = listener.accept()? {
+        let new_fd = current_proc.add_file(conn);
+        current_proc.memory_set().write_user_usize(fd_ptr, new_fd);
+        return Ok((0, 0));
+    }
+    if listener.nonblocking() {
+        return Err(Errno::EAGAIN);
+    }
+    current_thread.set_state(ThreadState::Waiting);
+    // 协程只持有套接字的弱引用，进程退出后套接字随文件表关闭，名字被释放
+    let listener: Weak<Socket> = Arc::downgrade(&listener);
+    executor::spawn(async move {
+        // 多个线程同时等待时连接可能已被取走，继续等待
+        let result = loop {
+            let accepted = match listener.upgrade() {
+                Some(listener) => listener.accept(),
+                None => return,
+            };
+            match accepted {
+                Ok(Some(conn)) => break Ok(conn),
+                Ok(None) => WaitForFile::new(listener.clone(), PollEvents::IN).await,
+                Err(errno) => break Err(errno),
+            }
+        };
+        // 等待期间进程可能已经退出，此时丢弃结果，连接随之关闭
+        if let Some(current_proc) = current_thread.proc() {
+            let result = match result {
+                Ok(conn) => current_proc.add_file(conn),
+                Err(errno) => errno.as_ret(),
+            };
+            current_proc.memory_set().write_user_usize(fd_ptr, result);
+            current_thread.set_state(ThreadState::Runnable);
+        }
+    });
+    Ok((0, 0))
+}
+
+/// 通过套接字fd发送用户地址buf_ptr处长度为buf_len的数据，除只接受套接字外与write相同
+///
+/// 不是套接字返回ENOTSOCK
+pub fn sys_send(fd: usize, buf_ptr: usize, buf_len: usize, result_ptr: usize) -> SyscallResult {
+    get_socket(fd)?;
+    sys_write(fd, buf_ptr, buf_len, result_ptr)
+}
+
+/// 从套接字fd接收数据到用户地址buf_ptr处长度为buf_len的缓冲区，除只接受套接字外与read相同
+///
+/// 不是套接字返回ENOTSOCK
+pub fn sys_recv(fd: usize, buf_ptr: usize, buf_len: usize, result_ptr: usize) -> SyscallResult {
+    get_socket(fd)?;
+    sys_read(fd, buf_ptr, buf_len, result_ptr)
+}
@@ -1,121 +1,136 @@
 //! 任务管理相关的系统调用
 use super::*;
+use crate::mm::{self, MemoryArea, MemorySet, USER_STACK_BASE, USER_STACK_SIZE};
 use future::executor;
 use future::futures::{ThreadYield, WaitForProc, WaitForThread};
-use mm::{MemoryArea, USER_STACK_BASE, USER_STACK_SIZE};
 use trap::CURRENT_THREAD;
+use user_syscall::Errno;
 
-use alloc::string::ToString;
 use alloc::sync::Arc;
 use x86_64::structures::paging::PageTableFlags;
 
 /// 退出当前进程
-pub fn sys_proc_exit(exit_code: usize) -> (usize, usize) {
+pub fn sys_proc_exit(exit_code: usize) -> SyscallResult {
     // 退出当前进程
     let cur = CURRENT_THREAD.get().as_ref().unwrap().clone();
-    cur.proc().unwrap().exit();
+    cur.proc().unwrap().exit(exit_code);
     // println!(
     //     "[Kernel] proc `{}` exited with exit code `{}`",
     //     cur.proc().unwrap().name(),
     //     exit_code,
     // );
-    (exit_code, 0)
+    Ok((exit_code, 0))
 }
 
 /// 创建新进程
 ///
 /// 并设置其主线程为就绪态
 ///
-/// 若创建失败返回usize::MAX
-pub fn sys_proc_create(name_ptr: usize, path_ptr: usize, args_ptr: usize) -> (usize, usize) {
-    let name = unsafe { (*(name_ptr as *const &str)).to_string() };
-    let path = unsafe { (*(path_ptr as *const &str)).to_string() };
-    // 获取命令行参数从用户堆拷贝到内核堆
-    let args: Option<Vec<String>> = if args_ptr != 0 {
-        let args_ref: &Vec<String> = unsafe { &(*(args_ptr as *const Vec<String>)) };
-        // [Debug]
-        println!(
-            "path_ptr = {:x}, args_ptr = {:x}, args[0] = {}",
-            path_ptr, args_ptr, args_ref[0]
-        );
-        Some(args_ref.clone())
-    } else {
-        None
-    };
-    let new_process = Process::new(name, &path, args);
-    if new_process.is_none() {
-        return (usize::MAX, 0);
-    }
-    let new_process = new_process.unwrap();
-    let new_process_id = new_process.pid();
+/// name和path为(ptr, len)，argv_ptr为以0结尾的字符串指针数组，为0时没有参数
+///
+/// 用户地址不合法返回EFAULT，可执行文件不存在返回ENOENT
+pub fn sys_proc_create(
+    name_ptr: usize,
+    name_len: usize,
+    path_ptr: usize,
+    path_len: usize,
+    argv_ptr: usize,
+) -> SyscallResult {
     // 获取当前进程
     let current_process = CURRENT_THREAD.get().as_ref().unwrap().proc().unwrap();
+    let memory_set = current_process.memory_set();
+    // 从用户地址空间复制名称、路径和命令行参数
+    let name = memory_set
+        .read_user_str(name_ptr, name_len)
+        .ok_or(Errno::EFAULT)?;
+    let path = memory_set
+        .read_user_str(path_ptr, path_len)
+        .ok_or(Errno::EFAULT)?;
+    let args = read_args(&memory_set, argv_ptr)?;
+    let new_process = Process::new(name, &path, args).ok_or(Errno::ENOENT)?;
+    let new_process_id = new_process.pid();
     // 加入到父进程的子进程列表中
     current_process.add_child(new_process.clone());
     new_process.set_parent(Arc::downgrade(&current_process));
     // 设置进程就绪
     new_process.root_thread().resume();
-    (new_process_id, 0)
+    Ok((new_process_id, 0))
 }
 
 /// 替换当前进程elf
 ///
-/// 若失败返回usize::MAX
-pub fn sys_exec(path_ptr: usize, args_ptr: usize) -> (usize, usize) {
-    let path = unsafe { *(path_ptr as *const &str) };
-    let path = path.to_string();
-    // println!(
-    //     "in kernel sys_exec, path: {}, path_len:{}",
-    //     path,
-    //     path.len()
-    // );
-    // 获取命令行参数从用户堆拷贝到内核堆
-    let args: Option<Vec<String>> = if args_ptr != 0 {
-        let args_ref: &Vec<String> = unsafe { &(*(args_ptr as *const Vec<String>)) };
-        Some(args_ref.clone())
-    } else {
-        None
-    };
+/// path为(ptr, len)，argv_ptr为以0结尾的字符串指针数组，为0时没有参数
+///
+/// 用户地址不合法返回EFAULT，可执行文件不存在返回ENOENT
+pub fn sys_exec(path_ptr: usize, path_len: usize, argv_ptr: usize) -> SyscallResult {
     let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
     let current_proc = current_thread.proc().unwrap();
-    (current_proc.exec(&path, args), 0)
+    let memory_set = current_proc.memory_set();
+    let path = memory_set
+        .read_user_str(path_ptr, path_len)
+        .ok_or(Errno::EFAULT)?;
+    let args = read_args(&memory_set, argv_ptr)?;
+    current_proc.exec(&path, args).ok_or(Errno::ENOENT)?;
+    Ok((0, 0))
+}
+
+/// 从用户地址空间读取命令行参数，argv_ptr为0时没有参数
+///
+/// 地址不合法时返回EFAULT
+fn read_args(memory_set: &MemorySet, argv_ptr: usize) -> Result<Option<Vec<String>>, Errno> {
+    if argv_ptr == 0 {
+        return Ok(None);
+    }
+    memory_set
+        .read_user_argv(argv_ptr)
+        .map(Some)
+        .ok_or(Errno::EFAULT)
 }
 
-/// 当前线程等待一个进程结束
+/// 当前线程等待一个进程结束，第二个返回值为其退出码
+///
+/// 若子进程已经退出则直接返回退出码，否则异步等待，
+/// 由等待协程在进程退出后写回退出码
 ///
-/// 若等待的进程不存在则返回255
-pub fn sys_proc_wait(pid: usize) -> (usize, usize) {
+/// 若等待的进程不存在则返回ECHILD
+pub fn sys_proc_wait(pid: usize) -> SyscallResult {
     // 获取当前线程
     let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
 
     let waited_process = match PROCESS_MAP.get().get(&pid) {
         Some(process) => process.clone(),
         None => {
+            // 子进程已经退出，直接返回退出码
+            if let Some(exit_code) = current_thread.proc().unwrap().take_exited_child(pid) {
+                return Ok((0, exit_code));
+            }
             // println!("[Kernel] waited proc does not existed or already dropped");
-            return (255, 0);
+            return Err(Errno::ECHILD);
         }
     };
     current_thread.set_state(ThreadState::Waiting);
     executor::spawn(WaitForProc::new(current_thread, waited_process));
-    (0, 0)
+    Ok((0, 0))
 }
 
 /// 当前线程放弃CPU
-pub fn sys_yield() -> (usize, usize) {
+pub fn sys_yield() -> SyscallResult {
     let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
     executor::spawn(ThreadYield::new(current_thread));
-    return (0, 0);
+    return Ok((0, 0));
 }
 
 /// 创建线程，返回tid
-pub fn sys_thread_create(entry: usize, arg1: usize, arg2: usize) -> (usize, usize) {
+pub fn sys_thread_create(entry: usize, arg1: usize, arg2: usize) -> SyscallResult {
     let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
     let current_proc = current_thread.proc().unwrap();
     let tid = current_proc.alloc_tid();
     // 每两个用户栈之间隔一段空间
     let sp_base = USER_STACK_BASE + tid * 2 * USER_STACK_SIZE;
-    let flags =
-        PageTableFlags::WRITABLE | PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    let flags = PageTableFlags::WRITABLE
+        | PageTableFlags::PRESENT
+        | PageTableFlags::USER_ACCESSIBLE
+        | PageTableFlags::NO_EXECUTE;
     // 分配用户栈
     let stack_area = MemoryArea::new(sp_base, USER_STACK_SIZE, flags, mm::MemAreaType::USERSTACK);
     // 插入到当前进程所在的地址空间中
@@ -132,23 +147,23 @@ pub fn sys_thread_create(entry: usize, arg1: usize, arg2: usize) -> (usize, usiz
     );
     new_thread.set_state(ThreadState::Runnable);
     current_proc.add_thread(new_thread);
-    (tid, 0)
+    Ok((tid, 0))
 }
 
 /// 退出当前线程
 ///
 /// 设置为Exited状态等待调度器清理
-pub fn sys_thread_exit() -> (usize, usize) {
+pub fn sys_thread_exit() -> SyscallResult {
     // 获取当前线程
     let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
     current_thread.set_state(ThreadState::Exited);
-    (0, 0)
+    Ok((0, 0))
 }
 
 /// 主线程等待tid线程
 ///
-/// 若不是主线程调用，就报错并返回usize::MAX
-pub fn sys_thread_join(tid: usize) -> (usize, usize) {
+/// 若不是主线程调用，就报错并返回EPERM，线程不存在返回ESRCH
+pub fn sys_thread_join(tid: usize) -> SyscallResult {
     let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
     let cur_tid = current_thread.tid();
     if cur_tid != 0 {
@@ -156,7 +171,7 @@ pub fn sys_thread_join(tid: usize) -> (usize, usize) {
             "[Kernel] Thread join failed, can only be called by root thread, current tid: {}",
             cur_tid
         );
-        return (usize::MAX, 0);
+        return Err(Errno::EPERM);
     }
     // 获取tid对应的线程
     let waited_thread = current_thread.proc().unwrap().get_thread(tid);
@@ -167,31 +182,31 @@ pub fn sys_thread_join(tid: usize) -> (usize, usize) {
             //     "[Kernel] Thread join info: waited thread already exited!, tid: {}",
             //     tid
             // );
-            return (usize::MAX, 0);
+            return Err(Errno::ESRCH);
         }
     };
     // 创建等待协程
     current_thread.set_state(ThreadState::Waiting);
     executor::spawn(WaitForThread::new(current_thread, waited_thread));
-    return (0, 0);
+    return Ok((0, 0));
 }
 
 /// 获取当前进程PID
-pub fn sys_get_pid() -> (usize, usize) {
+pub fn sys_get_pid() -> SyscallResult {
     let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
-    (current_thread.proc().unwrap().pid(), 0)
+    Ok((current_thread.proc().unwrap().pid(), 0))
 }
 
 /// 获取当前线程tid
-pub fn sys_get_tid() -> (usize, usize) {
+pub fn sys_get_tid() -> SyscallResult {
     let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
-    (current_thread.tid(), 0)
+    Ok((current_thread.tid(), 0))
 }
 
 /// 复制当前进程
-pub fn sys_fork() -> (usize, usize) {
+pub fn sys_fork() -> SyscallResult {
     let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
     let current_proc = current_thread.proc().unwrap();
     let child_proc = current_proc.fork();
-    (child_proc.pid(), 0)
+    Ok((child_proc.pid(), 0))
 }
@@ -1,6 +1,7 @@
 //! 进程抽象
 use crate::{mm::*, *};
 
+use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use alloc::sync::Weak;
 use core::sync::atomic::{AtomicUsize, Ordering};
@@ -21,6 +22,9 @@ pub static PROCESS_MAP: Lazy<Cell<HashMap<usize, Arc<Process>>>> =
 /// 全局变量：进程ID，用于创建进程时分配ID
 pub static PROCESS_ID: AtomicUsize = AtomicUsize::new(0);
 
+/// 每个进程最多保留的未被等待的已退出子进程个数，超出时丢弃最早退出的
+const MAX_EXITED_CHILDREN: usize = 64;
+
 /// 进程抽象
 #[derive(Default)]
 pub struct Process {
@@ -34,6 +38,10 @@ pub struct Process {
     parent: RwLock<Weak<Process>>,
     /// 子进程队列
     children: Cell<Vec<Arc<Process>>>,
+    /// 已退出但还未被等待的子进程的PID和退出码，按退出顺序排列
+    exited_children: Cell<VecDeque<(usize, usize)>>,
+    /// 退出码，进程退出后才有值
+    exit_code: Cell<Option<usize>>,
     /// 进程包含的线程集合，TID到线程对象的映射
     threads: Cell<HashMap<usize, Arc<Thread>>>,
     /// 线程ID，创建新线程时分配
@@ -53,37 +61,34 @@ impl Process {
     ///
     /// 为其创建虚存空间，将elf文件载入虚存空间中，并建立根线程
     ///
-    /// 若路径有误，则返回None
+    /// 若路径有误或不是ELF文件，则返回None
     pub fn new(name: String, path: &str, args: Option<Vec<String>>) -> Option<Arc<Self>> {
-        // 从文件系统中读取elf文件，载入到地址空间
+        // 从文件系统中读取elf文件头，段数据在缺页时读入
         let file = match open_file(path, OpenFlags::RDONLY) {
-            Some(file) => file,
+            Ok(file) => file,
             // 路径有误，直接返回None
-            None => return None,
+            Err(_) => return None,
         };
+        let elf_data = read_elf_header(&file.inode())?;
+        let elf = ElfFile::new(&elf_data).ok()?;
         // 新建进程虚存空间
         let memory_set = MemorySet::new();
-        let elf_data = file.read_all();
-        let elf = ElfFile::new(&elf_data).unwrap();
-        load_app(memory_set.clone(), &elf);
+        load_app(memory_set.clone(), &elf, file.inode());
         let entry = elf.header.pt2.entry_point() as usize;
 
         // 为根线程创建用户栈内存区域
         let stack_area = MemoryArea::new(
             USER_STACK_BASE,
             USER_STACK_SIZE,
-            PageTableFlags::USER_ACCESSIBLE | PageTableFlags::WRITABLE | PageTableFlags::PRESENT,
+            PageTableFlags::USER_ACCESSIBLE
+                | PageTableFlags::WRITABLE
+                | PageTableFlags::PRESENT
+                | PageTableFlags::NO_EXECUTE,
             MemAreaType::USERSTACK,
         );
         memory_set.insert_area(stack_area.clone());
-        // 切换到新进程地址空间以访问其用户栈
-        memory_set.activate();
         // 参数压栈
-        let (stack_top, argc, argv) = if args.is_none() {
-            (USER_STACK_BASE + USER_STACK_SIZE, 0, 0)
-        } else {
-            push_to_stack(stack_area.clone(), args)
-        };
+        let (stack_top, argc, argv) = push_to_stack(&memory_set, stack_area.clone(), args);
         // 构造新进程，默认打开标准输入输出
         let pid = PROCESS_ID.fetch_add(1, Ordering::Relaxed);
         let new_proc = Arc::new(Process {
@@ -91,7 +96,7 @@ impl Process {
             name,
             memory_set,
             file_table: Cell::new(vec![
-                Some(Arc::new(Stdin)),
+                Some(Arc::new(Stdin::default())),
                 Some(Arc::new(Stdout)),
                 Some(Arc::new(Stdout)),
             ]),
@@ -122,7 +127,7 @@ impl Process {
     pub fn fork(&self) -> Arc<Process> {
         assert_eq!(self.threads.len(), 1);
         let pid = PROCESS_ID.fetch_add(1, Ordering::Relaxed);
-        // 创建子进程复制父进程的文件表和地址空间（不包括用户栈）
+        // 创建子进程复制父进程的文件表和地址空间（不包括用户栈），页帧写时复制
         let memory_set = self.memory_set.clone_myself();
         let child_proc = Arc::new(Process {
             pid,
@@ -136,7 +141,7 @@ impl Process {
         // 复制用户栈
         let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
         let current_proc = current_thread.proc().unwrap();
-        let new_stack_area = current_thread.stack_area().clone_myself();
+        let new_stack_area = self.memory_set.clone_area(&current_thread.stack_area());
         memory_set.insert_area(new_stack_area.clone());
         let current_ctx = current_thread.user_context();
         // 创建根线程
@@ -162,10 +167,12 @@ impl Process {
     }
 
     /// 替换当前进程的elf文件
-    pub fn exec(&self, path: &str, args: Option<Vec<String>>) -> usize {
-        if let Some(file) = open_file(path, OpenFlags::RDONLY) {
-            let elf_data = file.read_all();
-            let elf = ElfFile::new(&elf_data).unwrap();
+    ///
+    /// 若路径有误或不是ELF文件，则返回None
+    pub fn exec(&self, path: &str, args: Option<Vec<String>>) -> Option<()> {
+        if let Ok(file) = open_file(path, OpenFlags::RDONLY) {
+            let elf_data = read_elf_header(&file.inode())?;
+            let elf = ElfFile::new(&elf_data).ok()?;
             // 清理除了exec之外的所有子线程
             let current_thread = CURRENT_THREAD.get().as_ref().unwrap().clone();
             for (_, thread) in self.threads.get() {
@@ -176,30 +183,35 @@ impl Process {
             let threads = self.threads.get_mut();
             threads.clear();
             threads.insert(current_thread.tid(), current_thread.clone());
-            // 清理地址空间之前的elf虚存区域
-            self.memory_set.clear_elf();
+            // 清理地址空间之前的elf虚存区域和mmap映射
+            self.memory_set.clear();
             // 重新加载elf
-            load_app(self.memory_set.clone(), &elf);
+            load_app(self.memory_set.clone(), &elf, file.inode());
             let entry = elf.header.pt2.entry_point() as usize;
-            // 参数压栈
+            // 刷新TLB
             self.memory_set.activate();
-            let (stack_top, argc, argv) = if args.is_none() {
-                (USER_STACK_BASE + USER_STACK_SIZE, 0, 0)
-            } else {
-                push_to_stack(current_thread.stack_area(), args)
-            };
+            // 参数压栈
+            let (stack_top, argc, argv) =
+                push_to_stack(&self.memory_set, current_thread.stack_area(), args);
             // 准备根线程现场
             // println!("entry: {:x}", entry);
             current_thread.set_ip(entry);
             current_thread.set_sp(stack_top);
             current_thread.set_args(argc, argv);
-            return 1;
+            return Some(());
         }
-        usize::MAX
+        None
     }
 
-    /// 退出进程
-    pub fn exit(&self) {
+    /// 退出进程，记录退出码供父进程等待时获取
+    ///
+    /// 进程只会退出一次，重复调用时直接返回
+    pub fn exit(&self, exit_code: usize) {
+        if self.exit_code.is_some() {
+            return;
+        }
+        // 需要在线程退出（唤醒等待者）之前记录退出码
+        *self.exit_code.get_mut() = Some(exit_code);
         // 退出所有线程
         for (_tid, thread) in self.threads.clone().into_iter() {
             // 线程应该是被调度器exit()的
@@ -209,10 +221,13 @@ impl Process {
         PROCESS_MAP.get_mut().remove(&self.pid);
         if let Some(parent_proc) = self.parent() {
             parent_proc.remove_child(self.pid);
+            parent_proc.add_exited_child(self.pid, exit_code);
         }
         // 是否需要手动drop？
         // drop(self.memory_set);
         self.children.get_mut().drain(..);
+        // 自己退出后不会再等待子进程
+        self.exited_children.get_mut().clear();
         // 无需清理自己的线程队列，线程被调度器清理的时候会清除进程对自己的引用
         // self.threads.get_mut().drain();
     }
@@ -222,6 +237,31 @@ impl Process {
         self.children.get_mut().push(process);
     }
 
+    /// 记录一个已退出子进程的退出码
+    ///
+    /// 当前进程已退出时不再记录，记录过多时丢弃最早退出的子进程的退出码
+    pub fn add_exited_child(&self, pid: usize, exit_code: usize) {
+        if self.exit_code.is_some() {
+            return;
+        }
+        let exited_children = self.exited_children.get_mut();
+        if exited_children.len() == MAX_EXITED_CHILDREN {
+            exited_children.pop_front();
+        }
+        exited_children.push_back((pid, exit_code));
+    }
+
+    /// 取出一个已退出子进程的退出码，若没有则返回None
+    pub fn take_exited_child(&self, pid: usize) -> Option<usize> {
+        let exited_children = self.exited_children.get_mut();
+        let index = exited_children
+            .iter()
+            .position(|&(child, _)| child == pid)?;
+        exited_children
+            .remove(index)
+            .map(|(_, exit_code)| exit_code)
+    }
+
     /// 分配一个线程id
     pub fn alloc_tid(&self) -> usize {
         self.thread_id.fetch_add(1, Ordering::Relaxed)
@@ -324,6 +364,11 @@ impl Process {
         thread
     }
 
+    /// 获取退出码，进程未退出时返回None
+    pub fn exit_code(&self) -> Option<usize> {
+        *self.exit_code.get()
+    }
+
     /// 获取父进程的引用
     pub fn parent(&self) -> Option<Arc<Process>> {
         self.parent.read().upgrade()
@@ -124,8 +124,8 @@ impl Thread {
         }
         let process = process.unwrap();
         if self.tid == 0 {
-            // 退出进程
-            process.exit();
+            // 根线程主动退出，退出进程
+            process.exit(0);
         } else {
             // 删除进程对自己的引用
             process.remove_thread(self.tid);
@@ -163,6 +163,11 @@ impl Thread {
         self.user_context.get_mut().general.rax = rax;
     }
 
+    /// 设置系统调用的两个返回值
+    pub fn set_syscall_ret(&self, ret0: usize, ret1: usize) {
+        self.user_context.get_mut().set_syscall_ret(ret0, ret1);
+    }
+
     /// 获取用户栈
     pub fn stack_area(&self) -> Arc<MemoryArea> {
         self.stack_area.clone()
@@ -1,12 +1,46 @@
 //! 中断和用户态系统调用的处理入口
 
+use crate::mm::current_memory_set;
 use crate::*;
 
 use alloc::sync::Arc;
+use core::fmt;
 use trapframe::{TrapFrame, UserContext};
+use user_syscall::EXIT_CODE_PAGE_FAULT;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::PageFaultErrorCode;
 const PAGE_FAULT: usize = 14;
 const TIMER: usize = 32;
 
+/// 页错误的诊断信息
+struct PageFaultInfo {
+    /// 触发页错误的虚地址（CR2）
+    vaddr: usize,
+    /// 错误码
+    error_code: usize,
+    /// 触发页错误的指令地址
+    rip: usize,
+}
+
+impl fmt::Display for PageFaultInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "addr: {:#x}, error code: {:#x} {:?}, rip: {:#x}",
+            self.vaddr,
+            self.error_code,
+            PageFaultErrorCode::from_bits_truncate(self.error_code as u64),
+            self.rip
+        )
+    }
+}
+
+/// 页错误是否由写访问触发
+fn is_write_fault(error_code: usize) -> bool {
+    PageFaultErrorCode::from_bits_truncate(error_code as u64)
+        .contains(PageFaultErrorCode::CAUSED_BY_WRITE)
+}
+
 #[no_mangle]
 /// 中断处理入口，由汇编直接调用无需手动调用
 pub extern "C" fn trap_handler(tf: &mut TrapFrame) {
@@ -45,18 +79,38 @@ pub fn handle_trap(
         context.unwrap().trap_num
     };
     match trap_num {
-        // 页错误，目前直接panic
+        // 页错误，用户态的页错误只终止出错的进程，内核态的页错误直接panic
         PAGE_FAULT => {
-            println!(
-                "[Trap Handler]: PAGEFAULT, memory_set root_pa: {:x}",
-                current_proc().memory_set().page_table().get().paddr()
-            );
-            panic!("page fault");
+            let vaddr = Cr2::read().as_u64() as usize;
+            if let Some(context) = context {
+                let info = PageFaultInfo {
+                    vaddr,
+                    error_code: context.error_code,
+                    rip: context.general.rip,
+                };
+                handle_user_page_fault(thread.unwrap(), info);
+            } else {
+                let tf = tf.unwrap();
+                let info = PageFaultInfo {
+                    vaddr,
+                    error_code: tf.error_code,
+                    rip: tf.rip,
+                };
+                // 内核访问用户地址（如写入用户缓冲区）时触发的缺页
+                if let Some(ms) = current_memory_set() {
+                    if ms.handle_page_fault(vaddr, is_write_fault(info.error_code)) {
+                        return;
+                    }
+                }
+                panic!("page fault in kernel, {}", info);
+            }
         }
         // 时钟中断，轮转用户线程或内核线程
         TIMER => {
             pic::ack();
             *pic::TICKS.get_mut() += 1;
+            requests::flush_tick(*pic::TICKS);
+            future::timer::timer_tick(*pic::TICKS);
             // 用户时钟
             if let Some(thread) = thread {
                 // 时间片轮转
@@ -75,3 +129,25 @@ pub fn handle_trap(
         }
     }
 }
+
+/// 处理用户态页错误
+///
+/// 若是访问尚未分配页帧的合法地址，则分配页帧后返回用户态重新执行；
+/// 否则终止出错线程所属的进程
+fn handle_user_page_fault(thread: Arc<Thread>, info: PageFaultInfo) {
+    let proc = thread.proc().unwrap();
+    if proc
+        .memory_set()
+        .handle_page_fault(info.vaddr, is_write_fault(info.error_code))
+    {
+        return;
+    }
+    println!(
+        "\x1b[31m[Trap Handler] Page fault in process `{}`(pid: {}, tid: {}), {}, process killed\x1b[0m",
+        proc.name(),
+        proc.pid(),
+        thread.tid(),
+        info
+    );
+    proc.exit(EXIT_CODE_PAGE_FAULT);
+}
@@ -1,6 +1,7 @@
 //! 辅助工具模块
 pub mod my_x86_64;
 pub mod pic;
+pub mod rtc;
 pub mod serial;
 
 // pub use console::*;
@@ -0,0 +1,83 @@
+//! CMOS实时时钟
+//!
+//! 启动时读取一次实时时钟作为启动时间，之后以时钟中断计数推算当前时间
+
+use crate::*;
+
+const CMOS_ADDR: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const REG_SECOND: u8 = 0x00;
+const REG_MINUTE: u8 = 0x02;
+const REG_HOUR: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+
+/// 每秒的时钟中断次数，见pic::init
+pub const TICKS_PER_SECOND: usize = 1000;
+
+/// 启动时间，自1970年以来的秒数
+static BOOT_TIME: Cell<u64> = zero();
+
+fn read_cmos(reg: u8) -> u8 {
+    out8(CMOS_ADDR, reg);
+    in8(CMOS_DATA)
+}
+
+/// 读取一次实时时钟，返回（年，月，日，时，分，秒）
+fn read_rtc() -> (u64, u64, u64, u64, u64, u64) {
+    // 等待时钟更新结束
+    while read_cmos(REG_STATUS_A) & 0x80 != 0 {}
+    let status_b = read_cmos(REG_STATUS_B);
+    let decode = |v: u8| {
+        // 默认为BCD编码
+        if status_b & 0x04 == 0 {
+            ((v >> 4) * 10 + (v & 0x0F)) as u64
+        } else {
+            v as u64
+        }
+    };
+    let hour = read_cmos(REG_HOUR);
+    let mut hour24 = decode(hour & 0x7F);
+    // 12小时制，最高位表示下午
+    if status_b & 0x02 == 0 && hour & 0x80 != 0 {
+        hour24 = (hour24 + 12) % 24;
+    }
+    (
+        2000 + decode(read_cmos(REG_YEAR)),
+        decode(read_cmos(REG_MONTH)),
+        decode(read_cmos(REG_DAY)),
+        hour24,
+        decode(read_cmos(REG_MINUTE)),
+        decode(read_cmos(REG_SECOND)),
+    )
+}
+
+/// 公历日期转换为自1970年以来的天数
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// 读取实时时钟，记录启动时间
+pub fn init() {
+    let (year, month, day, hour, minute, second) = read_rtc();
+    *BOOT_TIME.get_mut() =
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+}
+
+/// 当前时间，自1970年以来的秒数
+pub fn now() -> u64 {
+    *BOOT_TIME + (*pic::TICKS / TICKS_PER_SECOND) as u64
+}
@@ -45,6 +45,15 @@ pub fn serial_receive() -> u8 {
     }
 }
 
+/// 是否有字节可以接收，不阻塞
+pub fn serial_can_receive() -> bool {
+    if let Some(ref mut serial) = SERIAL.get() {
+        serial.lock().can_receive()
+    } else {
+        panic!("Printing to an uninitualised serial");
+    }
+}
+
 /// 清除中断
 pub fn clear_irq() {}
 
@@ -160,6 +169,11 @@ impl SerialPort {
         }
     }
 
+    /// Returns whether a received byte is waiting to be read.
+    fn can_receive(&mut self) -> bool {
+        self.line_sts().contains(LineStsFlags::INPUT_FULL)
+    }
+
     /// Receives a byte on the serial port.
     fn receive(&mut self) -> u8 {
         unsafe {
@@ -6,9 +6,13 @@ pub type Pid = usize;
 pub type BufPtr = usize;
 pub type BufLen = usize;
 pub type PathPtr = usize;
+pub type PathLen = usize;
 pub type FLAGS = u32;
 pub type FdPtr = usize;
 pub type ResultPtr = usize;
+pub type Offset = usize;
+pub type Whence = usize;
+pub type Len = usize;
 
 /// 文件系统类请求描述信息
 #[derive(Debug, Clone, Copy)]
@@ -19,7 +23,28 @@ pub enum FsReqDescription {
     Write(Pid, Fd, BufPtr, BufLen, ResultPtr),
     /// 打开一个磁盘文件，将句柄写入FdPtr中，
     /// 在sys_open中构造
-    Open(Pid, PathPtr, FLAGS, FdPtr),
+    Open(Pid, PathPtr, PathLen, FLAGS, FdPtr),
+    /// 从Offset处读磁盘文件，不移动读写位置，在sys_pread中被构造
+    Pread(Pid, Fd, BufPtr, BufLen, Offset, ResultPtr),
+    /// 从Offset处写磁盘文件，不移动读写位置，在sys_pwrite中被构造
+    Pwrite(Pid, Fd, BufPtr, BufLen, Offset, ResultPtr),
+    /// 移动磁盘文件的读写位置，Offset为有符号偏移，
+    /// 将新的位置写入ResultPtr中，在sys_lseek中被构造
+    Lseek(Pid, Fd, Offset, Whence, ResultPtr),
+    /// 读取目录中的目录项到buf中，将写入的字节数写入ResultPtr中，
+    /// 在sys_getdents中被构造
+    Getdents(Pid, Fd, BufPtr, BufLen, ResultPtr),
+    /// 将路径对应的文件截断或扩展到Len，将结果写入ResultPtr中，
+    /// 在sys_truncate中被构造
+    Truncate(Pid, PathPtr, PathLen, Len, ResultPtr),
+    /// 将磁盘文件截断或扩展到Len，将结果写入ResultPtr中，
+    /// 在sys_ftruncate中被构造
+    Ftruncate(Pid, Fd, Len, ResultPtr),
+    /// 将所有脏块写回磁盘，将结果写入ResultPtr中，在sys_sync中被构造
+    Sync(Pid, ResultPtr),
+    /// 将磁盘文件的脏块写回磁盘，将结果写入ResultPtr中，
+    /// 在sys_fsync中被构造
+    Fsync(Pid, Fd, ResultPtr),
 }
 
 impl CastBytes for FsReqDescription {}
@@ -1,27 +1,34 @@
 //! 调试类系统调用
 use super::*;
+use alloc::vec::Vec;
 
 /// 输出缓冲区
 
 /// 打印到终端
 pub fn debug_write(msg: &str) -> usize {
-    let msg_ptr = &msg as *const &str as usize;
-    let (ret0, _) = sys_debug_write(msg_ptr);
+    let (ret0, _) = sys_debug_write(msg.as_ptr() as usize, msg.len());
     ret0
 }
 
 /// 测试用户到内核的数据传输
 pub fn debug_data_transport(buffers: &[&[u8]], result: &mut [u8]) -> usize {
-    let bufs_ptr = &buffers as *const &[&[u8]] as usize;
-    let ret_ptr = result.as_mut_ptr() as usize;
-    let (ret0, _ret1) = sys_debug_data_transport(bufs_ptr, ret_ptr);
+    // 每个缓冲区以(ptr, len)的形式传给内核
+    let bufs: Vec<[usize; 2]> = buffers
+        .iter()
+        .map(|buf| [buf.as_ptr() as usize, buf.len()])
+        .collect();
+    let (ret0, _ret1) = sys_debug_data_transport(
+        bufs.as_ptr() as usize,
+        bufs.len(),
+        result.as_mut_ptr() as usize,
+        result.len(),
+    );
     ret0
 }
 
 /// 测试是否能使用内核全局ROOT_INODE查找文件系统
 pub fn debug_open(name: &str) -> usize {
-    let name_ptr = &name as *const &str as usize;
-    let (ret0, _ret1) = sys_debug_open(name_ptr);
+    let (ret0, _ret1) = sys_debug_open(name.as_ptr() as usize, name.len());
     ret0
 }
 
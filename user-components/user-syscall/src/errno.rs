@@ -0,0 +1,150 @@
+//! 系统调用错误码，内核与用户库共用
+//!
+//! 系统调用出错时，第一个返回值为错误码的相反数（与Linux相同），
+//! 即落在[usize::MAX - MAX_ERRNO + 1, usize::MAX]中的值都表示错误
+use core::fmt;
+use num_derive::FromPrimitive;
+
+/// 最大的错误码
+pub const MAX_ERRNO: usize = 4095;
+
+/// 系统调用错误码
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum Errno {
+    /// 操作不允许
+    EPERM = 1,
+    /// 文件或目录不存在
+    ENOENT = 2,
+    /// 进程或线程不存在
+    ESRCH = 3,
+    /// 系统调用被中断
+    EINTR = 4,
+    /// I/O错误
+    EIO = 5,
+    /// 设备或地址不存在，如非阻塞地打开没有读者的FIFO
+    ENXIO = 6,
+    /// 参数列表过长
+    E2BIG = 7,
+    /// 不是合法的可执行文件
+    ENOEXEC = 8,
+    /// 文件描述符不合法
+    EBADF = 9,
+    /// 没有可等待的子进程
+    ECHILD = 10,
+    /// 资源暂时不可用
+    EAGAIN = 11,
+    /// 内存不足
+    ENOMEM = 12,
+    /// 没有访问权限
+    EACCES = 13,
+    /// 用户地址不合法
+    EFAULT = 14,
+    /// 资源正忙
+    EBUSY = 16,
+    /// 文件已存在
+    EEXIST = 17,
+    /// 不是目录
+    ENOTDIR = 20,
+    /// 是目录
+    EISDIR = 21,
+    /// 参数不合法
+    EINVAL = 22,
+    /// 进程打开的文件过多
+    EMFILE = 24,
+    /// 文件过大
+    EFBIG = 27,
+    /// 磁盘空间不足
+    ENOSPC = 28,
+    /// 文件不支持移动读写位置
+    ESPIPE = 29,
+    /// 链接数过多
+    EMLINK = 31,
+    /// 管道读端已关闭
+    EPIPE = 32,
+    /// 文件名过长
+    ENAMETOOLONG = 36,
+    /// 系统调用不存在
+    ENOSYS = 38,
+    /// 目录非空
+    ENOTEMPTY = 39,
+    /// 不是套接字
+    ENOTSOCK = 88,
+    /// 名字已被使用
+    EADDRINUSE = 98,
+    /// 套接字已连接
+    EISCONN = 106,
+    /// 套接字未连接
+    ENOTCONN = 107,
+    /// 连接被拒绝
+    ECONNREFUSED = 111,
+}
+
+impl Errno {
+    /// 编码为系统调用的返回值
+    pub fn as_ret(self) -> usize {
+        (self as usize).wrapping_neg()
+    }
+
+    /// 从系统调用的返回值中解析错误码，返回值不表示错误时返回None
+    pub fn from_ret(ret: usize) -> Option<Self> {
+        let errno = ret.wrapping_neg();
+        if errno == 0 || errno > MAX_ERRNO {
+            return None;
+        }
+        Some(num_traits::FromPrimitive::from_usize(errno).unwrap_or(Errno::EINVAL))
+    }
+
+    /// 错误描述
+    pub fn description(&self) -> &'static str {
+        match self {
+            Errno::EPERM => "Operation not permitted",
+            Errno::ENOENT => "No such file or directory",
+            Errno::ESRCH => "No such process",
+            Errno::EINTR => "Interrupted system call",
+            Errno::EIO => "I/O error",
+            Errno::ENXIO => "No such device or address",
+            Errno::E2BIG => "Argument list too long",
+            Errno::ENOEXEC => "Exec format error",
+            Errno::EBADF => "Bad file descriptor",
+            Errno::ECHILD => "No child processes",
+            Errno::EAGAIN => "Resource temporarily unavailable",
+            Errno::ENOMEM => "Out of memory",
+            Errno::EACCES => "Permission denied",
+            Errno::EFAULT => "Bad address",
+            Errno::EBUSY => "Device or resource busy",
+            Errno::EEXIST => "File exists",
+            Errno::ENOTDIR => "Not a directory",
+            Errno::EISDIR => "Is a directory",
+            Errno::EINVAL => "Invalid argument",
+            Errno::EMFILE => "Too many open files",
+            Errno::EFBIG => "File too large",
+            Errno::ENOSPC => "No space left on device",
+            Errno::ESPIPE => "Illegal seek",
+            Errno::EMLINK => "Too many links",
+            Errno::EPIPE => "Broken pipe",
+            Errno::ENAMETOOLONG => "File name too long",
+            Errno::ENOSYS => "Function not implemented",
+            Errno::ENOTEMPTY => "Directory not empty",
+            Errno::ENOTSOCK => "Socket operation on non-socket",
+            Errno::EADDRINUSE => "Address already in use",
+            Errno::EISCONN => "Transport endpoint is already connected",
+            Errno::ENOTCONN => "Transport endpoint is not connected",
+            Errno::ECONNREFUSED => "Connection refused",
+        }
+    }
+}
+
+impl fmt::Display for Errno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self, self.description())
+    }
+}
+
+/// 将系统调用的返回值转换为Result
+pub(crate) fn check(ret: usize) -> Result<usize, Errno> {
+    match Errno::from_ret(ret) {
+        Some(errno) => Err(errno),
+        None => Ok(ret),
+    }
+}
@@ -8,74 +8,324 @@ bitflags::bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        /// 非阻塞读写
+        const NONBLOCK = 1 << 11;
+    }
+}
+
+/// fcntl的命令
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum FcntlCmd {
+    /// 获取打开文件的标志
+    GetFl = 3,
+    /// 设置打开文件的标志，只能修改NONBLOCK
+    SetFl = 4,
+}
+
+bitflags::bitflags! {
+    /// poll等待的事件
+    pub struct PollEvents: u16 {
+        /// 可以读取
+        const IN = 1 << 0;
+        /// 可以写入
+        const OUT = 1 << 2;
+        /// 出错，如管道读端已全部关闭
+        const ERR = 1 << 3;
+        /// 对端已关闭，如管道写端已全部关闭
+        const HUP = 1 << 4;
+        /// fd不存在
+        const NVAL = 1 << 5;
+    }
+}
+
+/// poll等待的一个文件
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct PollFd {
+    /// 文件描述符
+    pub fd: usize,
+    /// 等待的事件
+    pub events: PollEvents,
+    /// 返回时已就绪的事件，ERR、HUP和NVAL总是会被报告
+    pub revents: PollEvents,
+}
+
+impl PollFd {
+    /// 等待fd上的events
+    pub fn new(fd: usize, events: PollEvents) -> Self {
+        Self {
+            fd,
+            events,
+            revents: PollEvents::empty(),
+        }
+    }
+}
+
+/// lseek中偏移的起始位置
+#[repr(usize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum Whence {
+    /// 从文件开头计算
+    Set = 0,
+    /// 从当前读写位置计算
+    Cur = 1,
+    /// 从文件末尾计算
+    End = 2,
+}
+
+/// 文件类型
+#[repr(u32)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+pub enum FileType {
+    /// 普通文件
+    #[default]
+    File = 1,
+    /// 目录
+    Directory = 2,
+    /// 管道
+    Fifo = 3,
+    /// 字符设备，如标准输入输出
+    CharDevice = 4,
+    /// 套接字
+    Socket = 5,
+}
+
+/// 文件元数据，由stat和fstat返回
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stat {
+    /// I结点编号，不是磁盘文件时为0
+    pub ino: u64,
+    /// 文件类型
+    pub file_type: FileType,
+    /// 链接数
+    pub nlink: u32,
+    /// 文件大小（字节）
+    pub size: u64,
+    /// 占用的磁盘块数，包括索引块
+    pub blocks: u64,
+    /// 创建时间，自1970年以来的秒数
+    pub ctime: u64,
+    /// 最后修改时间，自1970年以来的秒数
+    pub mtime: u64,
+}
+
+/// 文件名的最大长度
+pub const NAME_MAX: usize = 27;
+
+/// 目录项，由getdents返回
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Dirent {
+    /// I结点编号
+    pub ino: u64,
+    /// 文件类型
+    pub file_type: FileType,
+    /// 文件名长度
+    pub name_len: u32,
+    /// 文件名，不以'\0'结尾
+    pub name: [u8; NAME_MAX + 1],
+}
+
+impl Dirent {
+    /// 文件名
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len as usize]).unwrap()
     }
 }
 
 /// 当前进程打开一个文件
 ///
-/// 成功返回fd，否则返回None
-pub fn open(path: &str, flags: OpenFlags) -> Option<usize> {
-    let path_ptr = &path as *const &str as usize;
-    let (fd, _) = sys_open(path_ptr, flags.bits as _);
-    if fd == usize::MAX {
-        return None;
-    }
-    return Some(fd);
+/// 打开命名管道时等待另一端打开，除非flags中有NONBLOCK。
+/// 成功返回fd，否则返回错误码
+pub fn open(path: &str, flags: OpenFlags) -> Result<usize, Errno> {
+    let (fd, _) = sys_open(path.as_ptr() as usize, path.len(), flags.bits as _);
+    check(fd)
 }
 
 /// 读取当前进程的一个文件
 ///
-/// 成功返回读取的字节数，否则返回None
-pub fn read(fd: usize, buf: &mut [u8]) -> Option<usize> {
+/// 成功返回读取的字节数，否则返回错误码
+pub fn read(fd: usize, buf: &mut [u8]) -> Result<usize, Errno> {
     let buf_ptr = buf.as_mut_ptr() as usize;
     let (read_bytes, _) = sys_read(fd, buf_ptr, buf.len());
-    // println!("read_bytes: {:x}", read_bytes);
-    if read_bytes == usize::MAX {
-        return None;
-    }
-    return Some(read_bytes);
+    check(read_bytes)
 }
 
 /// 写入当前进程的一个文件
 ///
-/// 成功返回写入的字节数，否则返回None
-pub fn write(fd: usize, buf: &[u8]) -> Option<usize> {
+/// 成功返回写入的字节数，否则返回错误码
+pub fn write(fd: usize, buf: &[u8]) -> Result<usize, Errno> {
     let buf_ptr = buf.as_ptr() as usize;
     let (write_bytes, _) = sys_write(fd, buf_ptr, buf.len());
-    if write_bytes == usize::MAX {
-        return None;
-    }
-    return Some(write_bytes);
+    check(write_bytes)
+}
+
+/// 移动文件的读写位置，dup和fork得到的描述符共享读写位置
+///
+/// 成功返回新的读写位置，否则返回错误码
+pub fn lseek(fd: usize, offset: isize, whence: Whence) -> Result<usize, Errno> {
+    let (pos, _) = sys_lseek(fd, offset, whence as usize);
+    check(pos)
+}
+
+/// 从文件的offset处读取，不移动读写位置
+///
+/// 成功返回读取的字节数，否则返回错误码
+pub fn pread(fd: usize, buf: &mut [u8], offset: usize) -> Result<usize, Errno> {
+    let (read_bytes, _) = sys_pread(fd, buf.as_mut_ptr() as usize, buf.len(), offset);
+    check(read_bytes)
+}
+
+/// 从文件的offset处写入，不移动读写位置
+///
+/// 成功返回写入的字节数，否则返回错误码
+pub fn pwrite(fd: usize, buf: &[u8], offset: usize) -> Result<usize, Errno> {
+    let (write_bytes, _) = sys_pwrite(fd, buf.as_ptr() as usize, buf.len(), offset);
+    check(write_bytes)
 }
 
 /// 关闭当前进程的一个文件
 ///
-/// 成功返回0，否则返回None
-pub fn close(fd: usize) -> Option<usize> {
+/// 成功返回Ok，否则返回错误码
+pub fn close(fd: usize) -> Result<(), Errno> {
     let (ret1, _) = sys_close(fd);
-    if ret1 == usize::MAX {
-        return None;
-    }
-    Some(ret1)
+    check(ret1).map(|_| ())
 }
 
 /// 创建管道，返回读端和写端的fd
-pub fn make_pipe() -> (usize, usize) {
-    sys_pipe()
+pub fn make_pipe() -> Result<(usize, usize), Errno> {
+    let (read_fd, write_fd) = sys_pipe();
+    check(read_fd).map(|read_fd| (read_fd, write_fd))
 }
 
 /// 复制当前进程的一个文件
 ///
-/// 若成功返回复制后的fd，否则返回None
-pub fn dup(fd: usize) -> Option<usize> {
+/// 若成功返回复制后的fd，否则返回错误码
+pub fn dup(fd: usize) -> Result<usize, Errno> {
     let (ret1, _) = sys_dup(fd);
-    if ret1 == usize::MAX {
-        return None;
-    }
-    Some(ret1)
+    check(ret1)
+}
+
+/// 创建目录
+///
+/// 成功返回Ok，否则返回错误码
+pub fn mkdir(path: &str) -> Result<(), Errno> {
+    let (ret1, _) = sys_mkdir(path.as_ptr() as usize, path.len());
+    check(ret1).map(|_| ())
+}
+
+/// 删除空目录
+///
+/// 成功返回Ok，否则返回错误码
+pub fn rmdir(path: &str) -> Result<(), Errno> {
+    let (ret1, _) = sys_rmdir(path.as_ptr() as usize, path.len());
+    check(ret1).map(|_| ())
+}
+
+/// 创建命名管道，打开它的进程通过一个共享的管道通信
+///
+/// 成功返回Ok，否则返回错误码
+pub fn mkfifo(path: &str) -> Result<(), Errno> {
+    let (ret1, _) = sys_mkfifo(path.as_ptr() as usize, path.len());
+    check(ret1).map(|_| ())
+}
+
+/// 删除文件
+///
+/// 成功返回Ok，否则返回错误码
+pub fn unlink(path: &str) -> Result<(), Errno> {
+    let (ret1, _) = sys_unlink(path.as_ptr() as usize, path.len());
+    check(ret1).map(|_| ())
+}
+
+/// 重命名文件或目录，new_path已存在时将其替换
+///
+/// 成功返回Ok，否则返回错误码
+pub fn rename(old_path: &str, new_path: &str) -> Result<(), Errno> {
+    let (ret1, _) = sys_rename(
+        old_path.as_ptr() as usize,
+        old_path.len(),
+        new_path.as_ptr() as usize,
+        new_path.len(),
+    );
+    check(ret1).map(|_| ())
+}
+
+/// 获取路径为path的文件的元数据
+///
+/// 成功返回元数据，否则返回错误码
+pub fn stat(path: &str) -> Result<Stat, Errno> {
+    let mut stat = Stat::default();
+    let stat_ptr = &mut stat as *mut Stat as usize;
+    let (ret1, _) = sys_stat(path.as_ptr() as usize, path.len(), stat_ptr);
+    check(ret1).map(|_| stat)
+}
+
+/// 获取当前进程一个文件的元数据
+///
+/// 成功返回元数据，否则返回错误码
+pub fn fstat(fd: usize) -> Result<Stat, Errno> {
+    let mut stat = Stat::default();
+    let stat_ptr = &mut stat as *mut Stat as usize;
+    let (ret1, _) = sys_fstat(fd, stat_ptr);
+    check(ret1).map(|_| stat)
+}
+
+/// 读取目录fd中的目录项到buf中，每次调用从上次读到的位置继续
+///
+/// 成功返回读取的目录项个数，读到目录末尾时返回0，否则返回错误码
+pub fn getdents(fd: usize, buf: &mut [Dirent]) -> Result<usize, Errno> {
+    let (read_bytes, _) = sys_getdents(fd, buf.as_mut_ptr() as usize, core::mem::size_of_val(buf));
+    check(read_bytes).map(|read_bytes| read_bytes / core::mem::size_of::<Dirent>())
+}
+
+/// 将路径为path的文件截断或扩展到len字节，扩展的部分填零
+///
+/// 成功返回Ok，否则返回错误码
+pub fn truncate(path: &str, len: usize) -> Result<(), Errno> {
+    let (ret1, _) = sys_truncate(path.as_ptr() as usize, path.len(), len);
+    check(ret1).map(|_| ())
+}
+
+/// 将当前进程的一个文件截断或扩展到len字节，不移动读写位置
+///
+/// 成功返回Ok，否则返回错误码
+pub fn ftruncate(fd: usize, len: usize) -> Result<(), Errno> {
+    let (ret1, _) = sys_ftruncate(fd, len);
+    check(ret1).map(|_| ())
 }
 
-/// 列出可用用户app
-pub fn ls() -> (usize, usize) {
-    sys_ls()
+/// 将文件系统块缓存中的所有脏块写回磁盘
+pub fn sync() -> Result<(), Errno> {
+    let (ret1, _) = sys_sync();
+    check(ret1).map(|_| ())
+}
+
+/// 将当前进程的一个磁盘文件写回磁盘
+///
+/// 成功返回Ok，否则返回错误码
+pub fn fsync(fd: usize) -> Result<(), Errno> {
+    let (ret1, _) = sys_fsync(fd);
+    check(ret1).map(|_| ())
+}
+
+/// 获取或设置当前进程一个打开文件的标志
+///
+/// GetFl返回访问模式和NONBLOCK标志，SetFl根据arg设置NONBLOCK标志；
+/// dup和fork得到的描述符共享这些标志，磁盘文件和标准输出的读写从不阻塞，忽略NONBLOCK。
+/// 失败返回错误码
+pub fn fcntl(fd: usize, cmd: FcntlCmd, arg: usize) -> Result<usize, Errno> {
+    let (ret1, _) = sys_fcntl(fd, cmd as usize, arg);
+    check(ret1)
+}
+
+/// 同时等待多个文件就绪，timeout为等待的毫秒数，None表示一直等待
+///
+/// 返回revents不为空的文件个数，超时返回0，否则返回错误码
+pub fn poll(fds: &mut [PollFd], timeout: Option<usize>) -> Result<usize, Errno> {
+    let timeout = timeout.unwrap_or(usize::MAX);
+    let (ret1, _) = sys_poll(fds.as_mut_ptr() as usize, fds.len(), timeout);
+    check(ret1)
 }
@@ -1,8 +1,11 @@
 //! 封装内核提供的系统调用给用户程序使用
 #![no_std]
 mod debug;
+mod errno;
 mod fs;
+mod mm;
 pub mod print;
+mod socket;
 mod sync;
 mod task;
 
@@ -13,7 +16,10 @@ extern crate num_derive;
 use num_derive::FromPrimitive;
 
 pub use debug::*;
+pub use errno::*;
 pub use fs::*;
+pub use mm::*;
+pub use socket::*;
 pub use sync::*;
 pub use task::*;
 
@@ -66,8 +72,55 @@ pub enum SyscallNum {
     Pipe,
     /// 复制文件
     Dup,
-    /// 列出可用的用户程序
-    Ls,
+    /// 创建目录
+    Mkdir,
+    /// 删除空目录
+    Rmdir,
+    /// 创建命名管道
+    Mkfifo,
+    /// 删除文件
+    Unlink,
+    /// 重命名文件或目录
+    Rename,
+    /// 移动读写位置
+    Lseek,
+    /// 从指定位置读文件
+    Pread,
+    /// 从指定位置写文件
+    Pwrite,
+    /// 按路径获取文件元数据
+    Stat,
+    /// 获取打开文件的元数据
+    Fstat,
+    /// 读取目录项
+    Getdents,
+    /// 按路径截断文件
+    Truncate,
+    /// 截断打开的文件
+    Ftruncate,
+    /// 写回所有脏块
+    Sync,
+    /// 写回文件
+    Fsync,
+    /// 获取或设置打开文件的标志
+    Fcntl,
+    /// 等待多个文件就绪
+    Poll,
+
+    /// 创建套接字
+    Socket,
+    /// 为套接字绑定名字
+    Bind,
+    /// 监听连接
+    Listen,
+    /// 接受连接
+    Accept,
+    /// 连接到监听中的套接字
+    Connect,
+    /// 通过套接字发送数据
+    Send,
+    /// 从套接字接收数据
+    Recv,
 
     /// 创建互斥锁
     MutexCreate,
@@ -87,6 +140,19 @@ pub enum SyscallNum {
     CondvarWait,
     /// 唤醒条件变量
     CondvarSignal,
+
+    /// 映射内存区域
+    Mmap,
+    /// 解除内存映射
+    Munmap,
+    /// 修改内存映射的访问权限
+    Mprotect,
+    /// 设置堆顶
+    Brk,
+    /// 打开或创建共享内存对象
+    ShmOpen,
+    /// 删除共享内存对象的名字
+    ShmUnlink,
 }
 
 /// 用户态使用系统调用
@@ -112,23 +178,28 @@ fn syscall(id: SyscallNum, args: [usize; 6]) -> (usize, usize) {
     (ret0, ret1)
 }
 
-fn sys_debug_write(msg_ptr: usize) -> (usize, usize) {
-    syscall(SyscallNum::DebugWrite, [msg_ptr, 0, 0, 0, 0, 0])
+fn sys_debug_write(msg_ptr: usize, msg_len: usize) -> (usize, usize) {
+    syscall(SyscallNum::DebugWrite, [msg_ptr, msg_len, 0, 0, 0, 0])
 }
 
 fn sys_serial_read(buf_ptr: usize) -> (usize, usize) {
     syscall(SyscallNum::SerialRead, [buf_ptr, 0, 0, 0, 0, 0])
 }
 
-fn sys_debug_data_transport(bufs_ptr: usize, ret_ptr: usize) -> (usize, usize) {
+fn sys_debug_data_transport(
+    bufs_ptr: usize,
+    bufs_count: usize,
+    ret_ptr: usize,
+    ret_len: usize,
+) -> (usize, usize) {
     syscall(
         SyscallNum::DebugDataTransport,
-        [bufs_ptr, ret_ptr, 0, 0, 0, 0],
+        [bufs_ptr, bufs_count, ret_ptr, ret_len, 0, 0],
     )
 }
 
-fn sys_debug_open(name_ptr: usize) -> (usize, usize) {
-    syscall(SyscallNum::DebugOpen, [name_ptr, 0, 0, 0, 0, 0])
+fn sys_debug_open(name_ptr: usize, name_len: usize) -> (usize, usize) {
+    syscall(SyscallNum::DebugOpen, [name_ptr, name_len, 0, 0, 0, 0])
 }
 
 fn sys_proc_exit(exit_code: usize) -> (usize, usize) {
@@ -139,10 +210,16 @@ fn sys_proc_wait(pid: usize) -> (usize, usize) {
     syscall(SyscallNum::ProcWait, [pid, 0, 0, 0, 0, 0])
 }
 
-fn sys_proc_create(name_ptr: usize, path_ptr: usize, args_ptr: usize) -> (usize, usize) {
+fn sys_proc_create(
+    name_ptr: usize,
+    name_len: usize,
+    path_ptr: usize,
+    path_len: usize,
+    argv_ptr: usize,
+) -> (usize, usize) {
     syscall(
         SyscallNum::ProcCreate,
-        [name_ptr, path_ptr, args_ptr, 0, 0, 0],
+        [name_ptr, name_len, path_ptr, path_len, argv_ptr, 0],
     )
 }
 
@@ -150,16 +227,17 @@ fn sys_yield() -> (usize, usize) {
     syscall(SyscallNum::Yield, [0, 0, 0, 0, 0, 0])
 }
 
-fn sys_open(path_ptr: usize, flags: usize) -> (usize, usize) {
+fn sys_open(path_ptr: usize, path_len: usize, flags: usize) -> (usize, usize) {
     // 将fd指针传送到内核，内核转发到内核线程，最终服务完成后将结果写入fd,
     // 这样做是因为这个系统调用是异步的，不能直接使用寄存器返回fd的值
     let mut fd: usize = 0;
     let fd_ptr = &mut fd as *mut usize as usize;
-    // 如果fs线程不存在或其他错误，则内核返回usize::MAX
-    let (ret1, _) = syscall(SyscallNum::Open, [path_ptr, flags, fd_ptr, 0, 0, 0]);
-    if ret1 == usize::MAX {
-        return (usize::MAX, 0);
+    // 如果fs线程不存在或其他错误，则内核直接返回错误码
+    let (ret1, _) = syscall(SyscallNum::Open, [path_ptr, path_len, flags, fd_ptr, 0, 0]);
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
     }
+    // 异步完成后fd中为打开的文件描述符或错误码
     (fd, 0)
 }
 
@@ -167,9 +245,9 @@ fn sys_read(fd: usize, buf_ptr: usize, buf_len: usize) -> (usize, usize) {
     let mut read_size: usize = 1;
     let result_ptr = &mut read_size as *mut usize as usize;
     let (ret1, _) = syscall(SyscallNum::Read, [fd, buf_ptr, buf_len, result_ptr, 0, 0]);
-    if ret1 == usize::MAX {
+    if Errno::from_ret(ret1).is_some() {
         // 出错了
-        return (usize::MAX, 0);
+        return (ret1, 0);
     } else if ret1 != 0 {
         // 是标准输入输出，同步返回了
         read_size = ret1;
@@ -186,9 +264,9 @@ fn sys_write(fd: usize, buf_ptr: usize, buf_len: usize) -> (usize, usize) {
     let mut write_size: usize = 0;
     let result_ptr = &mut write_size as *mut usize as usize;
     let (ret1, _) = syscall(SyscallNum::Write, [fd, buf_ptr, buf_len, result_ptr, 0, 0]);
-    if ret1 == usize::MAX {
+    if Errno::from_ret(ret1).is_some() {
         // 出错
-        return (usize::MAX, 0);
+        return (ret1, 0);
     } else if ret1 != 0 {
         // 标准输出，同步返回
         write_size = ret1;
@@ -196,6 +274,178 @@ fn sys_write(fd: usize, buf_ptr: usize, buf_len: usize) -> (usize, usize) {
     (write_size, 0)
 }
 
+fn sys_lseek(fd: usize, offset: isize, whence: usize) -> (usize, usize) {
+    let mut pos: usize = 0;
+    let result_ptr = &mut pos as *mut usize as usize;
+    let (ret1, _) = syscall(
+        SyscallNum::Lseek,
+        [fd, offset as usize, whence, result_ptr, 0, 0],
+    );
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    }
+    // 异步完成后pos中为新的读写位置或错误码
+    (pos, 0)
+}
+
+fn sys_pread(fd: usize, buf_ptr: usize, buf_len: usize, offset: usize) -> (usize, usize) {
+    let mut read_size: usize = 0;
+    let result_ptr = &mut read_size as *mut usize as usize;
+    let (ret1, _) = syscall(
+        SyscallNum::Pread,
+        [fd, buf_ptr, buf_len, offset, result_ptr, 0],
+    );
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    }
+    (read_size, 0)
+}
+
+fn sys_pwrite(fd: usize, buf_ptr: usize, buf_len: usize, offset: usize) -> (usize, usize) {
+    let mut write_size: usize = 0;
+    let result_ptr = &mut write_size as *mut usize as usize;
+    let (ret1, _) = syscall(
+        SyscallNum::Pwrite,
+        [fd, buf_ptr, buf_len, offset, result_ptr, 0],
+    );
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    }
+    (write_size, 0)
+}
+
+fn sys_stat(path_ptr: usize, path_len: usize, stat_ptr: usize) -> (usize, usize) {
+    syscall(SyscallNum::Stat, [path_ptr, path_len, stat_ptr, 0, 0, 0])
+}
+
+fn sys_fstat(fd: usize, stat_ptr: usize) -> (usize, usize) {
+    syscall(SyscallNum::Fstat, [fd, stat_ptr, 0, 0, 0, 0])
+}
+
+fn sys_getdents(fd: usize, buf_ptr: usize, buf_len: usize) -> (usize, usize) {
+    let mut read_size: usize = 0;
+    let result_ptr = &mut read_size as *mut usize as usize;
+    let (ret1, _) = syscall(
+        SyscallNum::Getdents,
+        [fd, buf_ptr, buf_len, result_ptr, 0, 0],
+    );
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    }
+    (read_size, 0)
+}
+
+fn sys_truncate(path_ptr: usize, path_len: usize, len: usize) -> (usize, usize) {
+    let mut result: usize = 0;
+    let result_ptr = &mut result as *mut usize as usize;
+    let (ret1, _) = syscall(
+        SyscallNum::Truncate,
+        [path_ptr, path_len, len, result_ptr, 0, 0],
+    );
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    }
+    (result, 0)
+}
+
+fn sys_ftruncate(fd: usize, len: usize) -> (usize, usize) {
+    let mut result: usize = 0;
+    let result_ptr = &mut result as *mut usize as usize;
+    let (ret1, _) = syscall(SyscallNum::Ftruncate, [fd, len, result_ptr, 0, 0, 0]);
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    }
+    (result, 0)
+}
+
+fn sys_sync() -> (usize, usize) {
+    let mut result: usize = 0;
+    let result_ptr = &mut result as *mut usize as usize;
+    let (ret1, _) = syscall(SyscallNum::Sync, [result_ptr, 0, 0, 0, 0, 0]);
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    }
+    (result, 0)
+}
+
+fn sys_fsync(fd: usize) -> (usize, usize) {
+    let mut result: usize = 0;
+    let result_ptr = &mut result as *mut usize as usize;
+    let (ret1, _) = syscall(SyscallNum::Fsync, [fd, result_ptr, 0, 0, 0, 0]);
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    }
+    (result, 0)
+}
+
+fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> (usize, usize) {
+    syscall(SyscallNum::Fcntl, [fd, cmd, arg, 0, 0, 0])
+}
+
+fn sys_poll(fds_ptr: usize, nfds: usize, timeout: usize) -> (usize, usize) {
+    let mut ready: usize = 0;
+    let result_ptr = &mut ready as *mut usize as usize;
+    let (ret1, _) = syscall(SyscallNum::Poll, [fds_ptr, nfds, timeout, result_ptr, 0, 0]);
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    }
+    // 完成后ready中为就绪的文件个数
+    (ready, 0)
+}
+
+fn sys_socket() -> (usize, usize) {
+    syscall(SyscallNum::Socket, [0, 0, 0, 0, 0, 0])
+}
+
+fn sys_bind(fd: usize, name_ptr: usize, name_len: usize) -> (usize, usize) {
+    syscall(SyscallNum::Bind, [fd, name_ptr, name_len, 0, 0, 0])
+}
+
+fn sys_listen(fd: usize, backlog: usize) -> (usize, usize) {
+    syscall(SyscallNum::Listen, [fd, backlog, 0, 0, 0, 0])
+}
+
+fn sys_accept(fd: usize) -> (usize, usize) {
+    let mut new_fd: usize = 0;
+    let fd_ptr = &mut new_fd as *mut usize as usize;
+    let (ret1, _) = syscall(SyscallNum::Accept, [fd, fd_ptr, 0, 0, 0, 0]);
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    }
+    // 完成后new_fd中为新连接的文件描述符
+    (new_fd, 0)
+}
+
+fn sys_connect(fd: usize, name_ptr: usize, name_len: usize) -> (usize, usize) {
+    syscall(SyscallNum::Connect, [fd, name_ptr, name_len, 0, 0, 0])
+}
+
+fn sys_send(fd: usize, buf_ptr: usize, buf_len: usize) -> (usize, usize) {
+    let mut send_size: usize = 0;
+    let result_ptr = &mut send_size as *mut usize as usize;
+    let (ret1, _) = syscall(SyscallNum::Send, [fd, buf_ptr, buf_len, result_ptr, 0, 0]);
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    } else if ret1 != 0 {
+        // 同步返回
+        send_size = ret1;
+    }
+    (send_size, 0)
+}
+
+fn sys_recv(fd: usize, buf_ptr: usize, buf_len: usize) -> (usize, usize) {
+    let mut recv_size: usize = 1;
+    let result_ptr = &mut recv_size as *mut usize as usize;
+    let (ret1, _) = syscall(SyscallNum::Recv, [fd, buf_ptr, buf_len, result_ptr, 0, 0]);
+    if Errno::from_ret(ret1).is_some() {
+        return (ret1, 0);
+    } else if ret1 != 0 {
+        // 同步返回
+        recv_size = ret1;
+    }
+    (recv_size, 0)
+}
+
 fn sys_close(fd: usize) -> (usize, usize) {
     let (ret1, _) = syscall(SyscallNum::Close, [fd, 0, 0, 0, 0, 0]);
     (ret1, 0)
@@ -209,8 +459,27 @@ fn sys_dup(fd: usize) -> (usize, usize) {
     syscall(SyscallNum::Dup, [fd, 0, 0, 0, 0, 0])
 }
 
-fn sys_ls() -> (usize, usize) {
-    syscall(SyscallNum::Ls, [0, 0, 0, 0, 0, 0])
+fn sys_mkdir(path_ptr: usize, path_len: usize) -> (usize, usize) {
+    syscall(SyscallNum::Mkdir, [path_ptr, path_len, 0, 0, 0, 0])
+}
+
+fn sys_rmdir(path_ptr: usize, path_len: usize) -> (usize, usize) {
+    syscall(SyscallNum::Rmdir, [path_ptr, path_len, 0, 0, 0, 0])
+}
+
+fn sys_mkfifo(path_ptr: usize, path_len: usize) -> (usize, usize) {
+    syscall(SyscallNum::Mkfifo, [path_ptr, path_len, 0, 0, 0, 0])
+}
+
+fn sys_unlink(path_ptr: usize, path_len: usize) -> (usize, usize) {
+    syscall(SyscallNum::Unlink, [path_ptr, path_len, 0, 0, 0, 0])
+}
+
+fn sys_rename(old_ptr: usize, old_len: usize, new_ptr: usize, new_len: usize) -> (usize, usize) {
+    syscall(
+        SyscallNum::Rename,
+        [old_ptr, old_len, new_ptr, new_len, 0, 0],
+    )
 }
 
 fn sys_mutex_create() -> (usize, usize) {
@@ -253,8 +522,8 @@ fn sys_fork() -> (usize, usize) {
     syscall(SyscallNum::Fork, [0, 0, 0, 0, 0, 0])
 }
 
-fn sys_exec(path_ptr: usize, args_ptr: usize) -> (usize, usize) {
-    syscall(SyscallNum::Exec, [path_ptr, args_ptr, 0, 0, 0, 0])
+fn sys_exec(path_ptr: usize, path_len: usize, argv_ptr: usize) -> (usize, usize) {
+    syscall(SyscallNum::Exec, [path_ptr, path_len, argv_ptr, 0, 0, 0])
 }
 
 fn sys_sem_create(n: usize) -> (usize, usize) {
@@ -279,3 +548,34 @@ fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> (usize, usize) {
 fn sys_condvar_signal(condvar_id: usize) -> (usize, usize) {
     syscall(SyscallNum::CondvarSignal, [condvar_id, 0, 0, 0, 0, 0])
 }
+
+fn sys_mmap(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: usize,
+    offset: usize,
+) -> (usize, usize) {
+    syscall(SyscallNum::Mmap, [addr, len, prot, flags, fd, offset])
+}
+
+fn sys_munmap(addr: usize, len: usize) -> (usize, usize) {
+    syscall(SyscallNum::Munmap, [addr, len, 0, 0, 0, 0])
+}
+
+fn sys_mprotect(addr: usize, len: usize, prot: usize) -> (usize, usize) {
+    syscall(SyscallNum::Mprotect, [addr, len, prot, 0, 0, 0])
+}
+
+fn sys_brk(addr: usize) -> (usize, usize) {
+    syscall(SyscallNum::Brk, [addr, 0, 0, 0, 0, 0])
+}
+
+fn sys_shm_open(name_ptr: usize, name_len: usize, flags: usize) -> (usize, usize) {
+    syscall(SyscallNum::ShmOpen, [name_ptr, name_len, flags, 0, 0, 0])
+}
+
+fn sys_shm_unlink(name_ptr: usize, name_len: usize) -> (usize, usize) {
+    syscall(SyscallNum::ShmUnlink, [name_ptr, name_len, 0, 0, 0, 0])
+}
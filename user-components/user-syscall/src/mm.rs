@@ -0,0 +1,93 @@
+//! 内存管理相关的用户系统调用封装
+use super::*;
+
+bitflags::bitflags! {
+    /// 映射区域的访问权限
+    pub struct MmapProt: u32 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXEC = 1 << 2;
+    }
+}
+
+bitflags::bitflags! {
+    /// 映射方式
+    pub struct MmapFlags: u32 {
+        /// 共享映射，fork后父子进程共享，文件映射的修改会写回文件
+        const SHARED = 1 << 0;
+        /// 私有映射，写时复制，修改不会写回文件
+        const PRIVATE = 1 << 1;
+        /// 必须映射在给定地址处，覆盖原有映射
+        const FIXED = 1 << 4;
+        /// 匿名映射，不使用文件，内容初始化为0
+        const ANONYMOUS = 1 << 5;
+    }
+}
+
+/// 将文件fd从offset开始的内容映射到当前进程地址空间中，匿名映射时忽略fd和offset
+///
+/// 成功返回映射区域的起始地址，否则返回错误码
+pub fn mmap(
+    addr: usize,
+    len: usize,
+    prot: MmapProt,
+    flags: MmapFlags,
+    fd: usize,
+    offset: usize,
+) -> Result<usize, Errno> {
+    let (start, _) = sys_mmap(addr, len, prot.bits() as _, flags.bits() as _, fd, offset);
+    check(start)
+}
+
+/// 解除[addr, addr + len)的映射
+///
+/// 成功返回Ok，否则返回错误码
+pub fn munmap(addr: usize, len: usize) -> Result<(), Errno> {
+    let (ret, _) = sys_munmap(addr, len);
+    check(ret).map(|_| ())
+}
+
+/// 修改[addr, addr + len)的访问权限
+///
+/// 成功返回Ok，否则返回错误码
+pub fn mprotect(addr: usize, len: usize, prot: MmapProt) -> Result<(), Errno> {
+    let (ret, _) = sys_mprotect(addr, len, prot.bits() as _);
+    check(ret).map(|_| ())
+}
+
+/// 设置堆顶为addr，addr为0时查询当前堆顶
+///
+/// 成功返回新的堆顶，否则返回错误码
+pub fn brk(addr: usize) -> Result<usize, Errno> {
+    let (ret, _) = sys_brk(addr);
+    check(ret)
+}
+
+/// 将堆顶移动increment字节
+///
+/// 成功返回原来的堆顶，即新分配内存的起始地址，否则返回错误码
+pub fn sbrk(increment: isize) -> Result<usize, Errno> {
+    let old_brk = brk(0)?;
+    if increment != 0 {
+        let new_brk = old_brk.checked_add_signed(increment).ok_or(Errno::ENOMEM)?;
+        brk(new_brk)?;
+    }
+    Ok(old_brk)
+}
+
+/// 打开名为name的共享内存对象，flags含CREATE时不存在则新建，含TRUNC时截断为0字节
+///
+/// 新建的对象长度为0，用ftruncate设置长度后再以共享方式mmap。
+/// 成功返回fd，否则返回错误码
+pub fn shm_open(name: &str, flags: OpenFlags) -> Result<usize, Errno> {
+    let (fd, _) = sys_shm_open(name.as_ptr() as usize, name.len(), flags.bits() as _);
+    check(fd)
+}
+
+/// 删除共享内存对象的名字，已打开和已映射的对象不受影响
+///
+/// 成功返回Ok，否则返回错误码
+pub fn shm_unlink(name: &str) -> Result<(), Errno> {
+    let (ret, _) = sys_shm_unlink(name.as_ptr() as usize, name.len());
+    check(ret).map(|_| ())
+}
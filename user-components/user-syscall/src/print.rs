@@ -10,7 +10,7 @@ struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        write(STDOUT, s.as_bytes());
+        let _ = write(STDOUT, s.as_bytes());
         Ok(())
     }
 }
@@ -35,6 +35,6 @@ macro_rules! println {
 
 pub fn getchar() -> u8 {
     let mut c = [0u8; 1];
-    read(STDIN, &mut c);
+    let _ = read(STDIN, &mut c);
     c[0]
 }
@@ -0,0 +1,61 @@
+//! 本地流式套接字的用户系统调用封装
+//!
+//! 套接字的名字位于抽象命名空间中，与文件系统中的路径无关。
+//! 套接字也是文件，可以用read、write、poll和dup操作
+use super::*;
+
+/// 创建一个套接字
+///
+/// 成功返回fd，否则返回错误码
+pub fn socket() -> Result<usize, Errno> {
+    let (fd, _) = sys_socket();
+    check(fd)
+}
+
+/// 将套接字绑定到名字name，关闭后名字被释放
+///
+/// 成功返回Ok，名字已被使用返回EADDRINUSE
+pub fn bind(fd: usize, name: &str) -> Result<(), Errno> {
+    let (ret1, _) = sys_bind(fd, name.as_ptr() as usize, name.len());
+    check(ret1).map(|_| ())
+}
+
+/// 开始监听已绑定的套接字，backlog为等待接受的连接的最大个数
+///
+/// 成功返回Ok，否则返回错误码
+pub fn listen(fd: usize, backlog: usize) -> Result<(), Errno> {
+    let (ret1, _) = sys_listen(fd, backlog);
+    check(ret1).map(|_| ())
+}
+
+/// 接受一个连接，没有连接时等待，非阻塞模式下返回EAGAIN
+///
+/// 成功返回新连接的fd，否则返回错误码
+pub fn accept(fd: usize) -> Result<usize, Errno> {
+    let (new_fd, _) = sys_accept(fd);
+    check(new_fd)
+}
+
+/// 连接到名为name的正在监听的套接字，不等待对方接受
+///
+/// 成功返回Ok，没有正在监听的套接字或等待队列已满返回ECONNREFUSED
+pub fn connect(fd: usize, name: &str) -> Result<(), Errno> {
+    let (ret1, _) = sys_connect(fd, name.as_ptr() as usize, name.len());
+    check(ret1).map(|_| ())
+}
+
+/// 通过已连接的套接字发送数据，除只接受套接字外与write相同
+///
+/// 成功返回发送的字节数，不是套接字返回ENOTSOCK，未连接返回ENOTCONN，对方已关闭返回EPIPE
+pub fn send(fd: usize, buf: &[u8]) -> Result<usize, Errno> {
+    let (ret1, _) = sys_send(fd, buf.as_ptr() as usize, buf.len());
+    check(ret1)
+}
+
+/// 从已连接的套接字接收数据，除只接受套接字外与read相同
+///
+/// 成功返回接收的字节数，对方已关闭时返回0，不是套接字返回ENOTSOCK，未连接返回ENOTCONN
+pub fn recv(fd: usize, buf: &mut [u8]) -> Result<usize, Errno> {
+    let (ret1, _) = sys_recv(fd, buf.as_mut_ptr() as usize, buf.len());
+    check(ret1)
+}
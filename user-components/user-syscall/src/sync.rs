@@ -7,16 +7,16 @@ pub fn mutex_create() -> usize {
     mutex_id
 }
 
-/// 互斥锁上锁，成功返回True，否则返回False
-pub fn mutex_lock(mutex_id: usize) -> bool {
+/// 互斥锁上锁，互斥锁不存在时返回错误码
+pub fn mutex_lock(mutex_id: usize) -> Result<(), Errno> {
     let (ret, _) = sys_mutex_lock(mutex_id);
-    ret == 0
+    check(ret).map(|_| ())
 }
 
-/// 互斥锁解锁，成功返回true，否则false
-pub fn mutex_unlock(mutex_id: usize) -> bool {
+/// 互斥锁解锁，互斥锁不存在时返回错误码
+pub fn mutex_unlock(mutex_id: usize) -> Result<(), Errno> {
     let (ret, _) = sys_mutex_unlock(mutex_id);
-    ret == 0
+    check(ret).map(|_| ())
 }
 
 /// 创建信号量，返回id
@@ -25,16 +25,16 @@ pub fn sem_create(n: usize) -> usize {
     sem_id
 }
 
-/// 增加信号量资源，返回是否成功
-pub fn sem_up(sem_id: usize) -> bool {
+/// 增加信号量资源，信号量不存在时返回错误码
+pub fn sem_up(sem_id: usize) -> Result<(), Errno> {
     let (ret, _) = sys_sem_up(sem_id);
-    ret == 0
+    check(ret).map(|_| ())
 }
 
-/// 减少信号量资源，返回是否成功
-pub fn sem_down(sem_id: usize) -> bool {
+/// 减少信号量资源，信号量不存在时返回错误码
+pub fn sem_down(sem_id: usize) -> Result<(), Errno> {
     let (ret, _) = sys_sem_down(sem_id);
-    ret == 0
+    check(ret).map(|_| ())
 }
 
 /// 创建条件变量，返回编号
@@ -43,14 +43,14 @@ pub fn condvar_create() -> usize {
     condvar_id
 }
 
-/// 当前线程阻塞条件变量
-pub fn condvar_wait(condvar_id: usize, mutex_id: usize) -> bool {
+/// 当前线程阻塞条件变量，条件变量或互斥锁不存在时返回错误码
+pub fn condvar_wait(condvar_id: usize, mutex_id: usize) -> Result<(), Errno> {
     let (ret, _) = sys_condvar_wait(condvar_id, mutex_id);
-    ret == 0
+    check(ret).map(|_| ())
 }
 
-/// 唤醒条件变量阻塞的线程
-pub fn condvar_signal(condvar_id: usize) -> bool {
+/// 唤醒条件变量阻塞的线程，条件变量不存在时返回错误码
+pub fn condvar_signal(condvar_id: usize) -> Result<(), Errno> {
     let (ret, _) = sys_condvar_signal(condvar_id);
-    ret == 0
+    check(ret).map(|_| ())
 }
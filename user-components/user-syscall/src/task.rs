@@ -4,6 +4,9 @@ use crate::sys_proc_exit;
 use crate::*;
 use alloc::{string::String, vec::Vec};
 
+/// 进程因非法访存（页错误）被内核终止时的退出码
+pub const EXIT_CODE_PAGE_FAULT: usize = 139;
+
 /// 退出当前进程
 pub fn proc_exit(exit_code: usize) -> () {
     sys_proc_exit(exit_code);
@@ -11,36 +14,71 @@ pub fn proc_exit(exit_code: usize) -> () {
 
 /// 创建新进程
 ///
-/// 若创建失败，则第一个返回值为usize::MAX
-pub fn proc_create(name: &str, path: &str, args: Option<Vec<String>>) -> (usize, usize) {
-    let name_ptr: usize = &name as *const &str as usize;
-    let path_ptr = &path as *const &str as usize;
-    let mut args_ptr = 0;
-    if let Some(args) = &args {
-        args_ptr = args as *const Vec<String> as usize;
-    }
-    sys_proc_create(name_ptr, path_ptr, args_ptr)
+/// 成功返回新进程的pid，否则返回错误码
+pub fn proc_create(name: &str, path: &str, args: Option<Vec<String>>) -> Result<usize, Errno> {
+    let argv = args.as_deref().map(CArgv::new);
+    let (pid, _) = sys_proc_create(
+        name.as_ptr() as usize,
+        name.len(),
+        path.as_ptr() as usize,
+        path.len(),
+        argv.as_ref().map_or(0, CArgv::as_ptr),
+    );
+    check(pid)
 }
 
 /// 替换当前进程的elf
 ///
-/// 若创建失败，则第一个返回值为usize::MAX
-pub fn exec(path: &str, args: Option<&Vec<String>>) -> (usize, usize) {
-    // println!("In user exec path: {}", path);
-    let path_ptr = &path as *const &str as usize;
-    let mut args_ptr = 0;
-    if let Some(args) = args {
-        args_ptr = args as *const Vec<String> as usize;
+/// 成功时不会返回，失败返回错误码
+pub fn exec(path: &str, args: Option<&Vec<String>>) -> Result<(), Errno> {
+    let argv = args.map(|args| CArgv::new(args));
+    let (ret, _) = sys_exec(
+        path.as_ptr() as usize,
+        path.len(),
+        argv.as_ref().map_or(0, CArgv::as_ptr),
+    );
+    check(ret).map(|_| ())
+}
+
+/// 传给内核的命令行参数
+///
+/// 每个参数以'\0'结尾，指针数组以0结尾
+struct CArgv {
+    /// 以'\0'结尾的参数，需要在系统调用返回前保持存活
+    _strs: Vec<Vec<u8>>,
+    /// 参数指针数组
+    ptrs: Vec<usize>,
+}
+
+impl CArgv {
+    fn new(args: &[String]) -> Self {
+        let strs: Vec<Vec<u8>> = args
+            .iter()
+            .map(|arg| {
+                let mut s = Vec::with_capacity(arg.len() + 1);
+                s.extend_from_slice(arg.as_bytes());
+                s.push(0);
+                s
+            })
+            .collect();
+        let mut ptrs: Vec<usize> = strs.iter().map(|s| s.as_ptr() as usize).collect();
+        ptrs.push(0);
+        Self { _strs: strs, ptrs }
+    }
+
+    fn as_ptr(&self) -> usize {
+        self.ptrs.as_ptr() as usize
     }
-    sys_exec(path_ptr, args_ptr)
 }
 
 /// 当前线程等待一个进程结束
-pub fn proc_wait(pid: usize) {
-    let (ret0, _ret1) = sys_proc_wait(pid);
-    if ret0 == 255 {
-        // debug_write("SyscallError: waited process does not exist");
-    }
+///
+/// 返回被等待进程的退出码，若进程不存在则返回ECHILD
+///
+/// 退出码通过第二个返回值传回，负的退出码不会被当作错误码
+pub fn proc_wait(pid: usize) -> Result<usize, Errno> {
+    let (ret0, ret1) = sys_proc_wait(pid);
+    check(ret0).map(|_| ret1)
 }
 
 /// 当前线程主动放弃CPU
@@ -48,14 +86,10 @@ pub fn current_yield() {
     sys_yield();
 }
 
-/// 创建线程，返回新线程tid，若失败返回None
-pub fn thread_create(entry: usize, arg1: usize, arg2: usize) -> Option<usize> {
+/// 创建线程，返回新线程tid，若失败返回错误码
+pub fn thread_create(entry: usize, arg1: usize, arg2: usize) -> Result<usize, Errno> {
     let (tid, _ret1) = sys_thread_create(entry, arg1, arg2);
-    if tid == usize::MAX {
-        None
-    } else {
-        Some(tid)
-    }
+    check(tid)
 }
 
 /// 当前线程退出
@@ -65,13 +99,10 @@ pub fn thread_exit() {
 
 /// 当前线程等待另一个线程
 ///
-/// 只允许主线程使用，若非主线程则返回None
-pub fn thread_join(tid: usize) -> Option<usize> {
+/// 只允许主线程使用，若非主线程则返回EPERM，线程不存在则返回ESRCH
+pub fn thread_join(tid: usize) -> Result<usize, Errno> {
     let (ret1, _) = sys_thread_join(tid);
-    if ret1 == usize::MAX {
-        return None;
-    }
-    Some(ret1)
+    check(ret1)
 }
 
 /// 获取当前进程pid
@@ -14,11 +14,10 @@ pub fn main(argc: usize, argv: &[&str]) -> i32 {
         println!("argv[{}] = {}", i, arg);
     }
     assert!(argc == 2);
-    let fd = open(argv[1], OpenFlags::RDONLY);
-    if fd == None {
-        panic!("Error occurred when opening file");
-    }
-    let fd = fd.unwrap();
+    let fd = match open(argv[1], OpenFlags::RDONLY) {
+        Ok(fd) => fd,
+        Err(errno) => panic!("Error occurred when opening file: {}", errno),
+    };
     let mut buf = [0u8; 256];
     loop {
         let size = read(fd, &mut buf).unwrap();
@@ -27,6 +26,6 @@ pub fn main(argc: usize, argv: &[&str]) -> i32 {
         }
         print!("{}", core::str::from_utf8(&buf[..size]).unwrap());
     }
-    close(fd);
+    close(fd).unwrap();
     0
 }
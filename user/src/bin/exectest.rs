@@ -15,10 +15,10 @@ fn main() -> usize {
     let pid = fork();
     if pid == 0 {
         let args = vec!["arg1".to_string(), "arg2".to_string()];
-        exec("printargs", Some(&args));
+        exec("printargs", Some(&args)).unwrap();
     } else {
         println!("I am parent process");
-        proc_wait(pid);
+        proc_wait(pid).unwrap();
     }
     println!("Exec test passed!");
     0
@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, current_yield, fork, get_time, mkfifo, open, proc_wait, read, stat, unlink, write,
+    Errno, FileType, OpenFlags,
+};
+
+const FIFO: &str = "fifotest.fifo";
+
+/// 让出CPU直到经过ms毫秒
+fn sleep(ms: usize) {
+    let start = get_time();
+    while get_time() - start < ms {
+        current_yield();
+    }
+}
+
+/// 读取直到写端全部关闭，返回读取的字节数
+fn read_all(fd: usize, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        let n = read(fd, &mut buf[len..]).unwrap();
+        if n == 0 {
+            return len;
+        }
+        len += n;
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    mkfifo(FIFO).unwrap();
+    assert_eq!(mkfifo(FIFO), Err(Errno::EEXIST));
+    assert_eq!(stat(FIFO).unwrap().file_type, FileType::Fifo);
+    assert_eq!(open(FIFO, OpenFlags::RDWR), Err(Errno::EINVAL));
+
+    // 非阻塞打开：没有读者时不能打开写端，读端总能打开
+    let nonblock_write = OpenFlags::WRONLY | OpenFlags::NONBLOCK;
+    assert_eq!(open(FIFO, nonblock_write), Err(Errno::ENXIO));
+    let read_fd = open(FIFO, OpenFlags::RDONLY | OpenFlags::NONBLOCK).unwrap();
+    let mut buf = [0u8; 64];
+    assert_eq!(read(read_fd, &mut buf), Ok(0));
+    let write_fd = open(FIFO, OpenFlags::WRONLY).unwrap();
+    assert_eq!(read(read_fd, &mut buf), Err(Errno::EAGAIN));
+    assert_eq!(write(write_fd, b"hello"), Ok(5));
+    assert_eq!(read(read_fd, &mut buf), Ok(5));
+    assert_eq!(&buf[..5], b"hello");
+    close(write_fd).unwrap();
+    close(read_fd).unwrap();
+    println!("nonblocking open passed!");
+
+    // 写者先打开，阻塞到读者打开
+    let pid = fork();
+    if pid == 0 {
+        let fd = open(FIFO, OpenFlags::WRONLY).unwrap();
+        assert_eq!(write(fd, b"from writer"), Ok(11));
+        close(fd).unwrap();
+        return 0;
+    }
+    sleep(10);
+    let fd = open(FIFO, OpenFlags::RDONLY).unwrap();
+    assert_eq!(read_all(fd, &mut buf), 11);
+    assert_eq!(&buf[..11], b"from writer");
+    close(fd).unwrap();
+    proc_wait(pid).unwrap();
+
+    // 读者先打开，阻塞到写者打开
+    let pid = fork();
+    if pid == 0 {
+        sleep(10);
+        let fd = open(FIFO, OpenFlags::WRONLY).unwrap();
+        assert_eq!(write(fd, b"late writer"), Ok(11));
+        close(fd).unwrap();
+        return 0;
+    }
+    let fd = open(FIFO, OpenFlags::RDONLY).unwrap();
+    assert_eq!(read_all(fd, &mut buf), 11);
+    assert_eq!(&buf[..11], b"late writer");
+    close(fd).unwrap();
+    proc_wait(pid).unwrap();
+
+    unlink(FIFO).unwrap();
+    println!("fifotest passed!");
+    0
+}
@@ -13,7 +13,14 @@ fn main() -> usize {
         println!("I am child process, pid: {}", pid);
     } else {
         println!("I am parent process, child pid: {}", pid);
-        proc_wait(pid);
+        proc_wait(pid).unwrap();
+        // 负的退出码不会被当作错误码
+        let exit_code = -2isize as usize;
+        let pid = fork();
+        if pid == 0 {
+            return exit_code;
+        }
+        assert_eq!(proc_wait(pid), Ok(exit_code));
     }
     println!("Fork test passed!");
     0
@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use user_lib::{fork, proc_wait};
+
+#[no_mangle]
+fn main() -> i32 {
+    // 超过初始堆大小，需要通过sbrk扩展堆
+    let len = 0x100000;
+    let mut v: Vec<u8> = vec![0; len];
+    for (i, byte) in v.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    // 子进程继承父进程的堆
+    let pid = fork();
+    if pid == 0 {
+        assert!(v.iter().enumerate().all(|(i, &byte)| byte == i as u8));
+        let more: Vec<usize> = (0..0x10000).collect();
+        assert_eq!(more.iter().sum::<usize>(), 0xffff * 0x10000 / 2);
+        return 0;
+    }
+    proc_wait(pid).unwrap();
+    assert!(v.iter().enumerate().all(|(i, &byte)| byte == i as u8));
+    println!("Heap test passed!");
+    0
+}
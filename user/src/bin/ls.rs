@@ -1,12 +1,108 @@
 #![no_std]
 #![no_main]
 
-use user_lib::ls;
-
+#[macro_use]
 extern crate user_lib;
+extern crate alloc;
+
+use alloc::format;
+use alloc::string::String;
+use user_lib::{close, fstat, getdents, open, stat, Dirent, FileType, OpenFlags};
+
+/// 将自1970年以来的秒数格式化为"YYYY-MM-DD HH:MM"
+fn format_time(secs: u64) -> String {
+    let (days, rem) = (secs / 86400, secs % 86400);
+    // 从天数推算公历日期
+    let z = days + 719468;
+    let era = z / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60
+    )
+}
+
+/// 文件类型的缩写
+fn type_char(file_type: FileType) -> char {
+    match file_type {
+        FileType::File => '-',
+        FileType::Directory => 'd',
+        FileType::Fifo => 'p',
+        FileType::CharDevice => 'c',
+        FileType::Socket => 's',
+    }
+}
+
+/// 打印一个文件，长格式时打印类型、链接数、大小和修改时间
+fn print_entry(dir: &str, name: &str, long: bool) {
+    if !long {
+        println!("{}", name);
+        return;
+    }
+    let path = if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    };
+    match stat(&path) {
+        Ok(stat) => println!(
+            "{} {:>3} {:>8} {} {}",
+            type_char(stat.file_type),
+            stat.nlink,
+            stat.size,
+            format_time(stat.mtime),
+            name
+        ),
+        Err(errno) => println!("ls: cannot stat {}: {}", path, errno),
+    }
+}
 
+/// 用法：ls [-l] [path]
 #[no_mangle]
-fn main() -> i32 {
-    ls();
-    1
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    let long = argc > 1 && argv[1] == "-l";
+    let args = &argv[if long { 2 } else { 1 }..];
+    let path = args.first().copied().unwrap_or("/");
+    let fd = match open(path, OpenFlags::RDONLY) {
+        Ok(fd) => fd,
+        Err(errno) => {
+            println!("ls: cannot access {}: {}", path, errno);
+            return -1;
+        }
+    };
+    // 不是目录时只列出文件本身
+    if fstat(fd).map_or(true, |stat| stat.file_type != FileType::Directory) {
+        close(fd).unwrap();
+        let name = path.rsplit('/').next().unwrap();
+        let dir = &path[..path.len() - name.len()];
+        print_entry(if dir.is_empty() { "." } else { dir }, name, long);
+        return 0;
+    }
+    let mut dirents = [Dirent::default(); 16];
+    loop {
+        let count = match getdents(fd, &mut dirents) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(errno) => {
+                println!("ls: cannot read {}: {}", path, errno);
+                close(fd).unwrap();
+                return -1;
+            }
+        };
+        for dirent in &dirents[..count] {
+            print_entry(path, dirent.name(), long);
+        }
+    }
+    close(fd).unwrap();
+    0
 }
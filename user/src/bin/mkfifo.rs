@@ -0,0 +1,23 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::mkfifo;
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        println!("usage: mkfifo <path>...");
+        return -1;
+    }
+    let mut exit_code = 0;
+    for path in &argv[1..] {
+        if let Err(errno) = mkfifo(path) {
+            println!("mkfifo: cannot create fifo {}: {}", path, errno);
+            exit_code = -1;
+        }
+    }
+    exit_code
+}
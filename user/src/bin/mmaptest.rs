@@ -0,0 +1,115 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{fork, proc_wait, EXIT_CODE_PAGE_FAULT};
+use user_syscall::{close, open, write, Errno, OpenFlags};
+use user_syscall::{mmap, mprotect, munmap, MmapFlags, MmapProt};
+
+const PAGE_SIZE: usize = 4096;
+
+#[no_mangle]
+fn main() -> i32 {
+    // 匿名私有映射
+    let len = 4 * PAGE_SIZE;
+    let addr = mmap(
+        0,
+        len,
+        MmapProt::READ | MmapProt::WRITE,
+        MmapFlags::PRIVATE | MmapFlags::ANONYMOUS,
+        0,
+        0,
+    )
+    .unwrap();
+    let buf = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) };
+    assert!(buf.iter().all(|&b| b == 0));
+    buf.fill(0x5a);
+    // 拆分：中间一页改为只读，再改回可写后与两边合并
+    assert!(mprotect(addr + PAGE_SIZE, PAGE_SIZE, MmapProt::READ).is_ok());
+    assert_eq!(buf[PAGE_SIZE], 0x5a);
+    assert!(mprotect(
+        addr + PAGE_SIZE,
+        PAGE_SIZE,
+        MmapProt::READ | MmapProt::WRITE
+    )
+    .is_ok());
+    buf[PAGE_SIZE] = 1;
+    // 可写可执行违反W^X
+    assert!(mprotect(addr, PAGE_SIZE, MmapProt::WRITE | MmapProt::EXEC).is_err());
+    assert!(munmap(addr, len).is_ok());
+    // 长度溢出的范围被拒绝
+    assert_eq!(munmap(addr, usize::MAX), Err(Errno::EINVAL));
+    assert_eq!(
+        mprotect(addr, usize::MAX, MmapProt::READ),
+        Err(Errno::EINVAL)
+    );
+    let anonymous = MmapFlags::PRIVATE | MmapFlags::ANONYMOUS;
+    assert_eq!(
+        mmap(0, usize::MAX, MmapProt::READ, anonymous, 0, 0),
+        Err(Errno::ENOMEM)
+    );
+    // 不可用的提示地址被忽略
+    let hint = usize::MAX & !(PAGE_SIZE - 1);
+    let addr = mmap(hint, 2 * PAGE_SIZE, MmapProt::READ, anonymous, 0, 0).unwrap();
+    assert_ne!(addr, hint);
+    assert!(munmap(addr, 2 * PAGE_SIZE).is_ok());
+    println!("Anonymous mmap test passed!");
+
+    // 不可访问的保护页，访问时进程被终止，改回可读后数据仍在
+    let addr = mmap(
+        0,
+        PAGE_SIZE,
+        MmapProt::READ | MmapProt::WRITE,
+        anonymous,
+        0,
+        0,
+    )
+    .unwrap();
+    let guard = addr as *mut usize;
+    unsafe { guard.write_volatile(7) };
+    mprotect(addr, PAGE_SIZE, MmapProt::empty()).unwrap();
+    let pid = fork();
+    if pid == 0 {
+        unsafe { guard.read_volatile() };
+        return 0;
+    }
+    assert_eq!(proc_wait(pid), Ok(EXIT_CODE_PAGE_FAULT));
+    mprotect(addr, PAGE_SIZE, MmapProt::READ).unwrap();
+    assert_eq!(unsafe { guard.read_volatile() }, 7);
+    munmap(addr, PAGE_SIZE).unwrap();
+    println!("Guard page test passed!");
+
+    // 私有文件映射
+    let test_str = "Hello, mmap";
+    let fd = open("mmapfile", OpenFlags::CREATE | OpenFlags::RDWR).unwrap();
+    write(fd, test_str.as_bytes()).unwrap();
+    let addr = mmap(0, PAGE_SIZE, MmapProt::READ, MmapFlags::PRIVATE, fd, 0).unwrap();
+    let data = unsafe { core::slice::from_raw_parts(addr as *const u8, PAGE_SIZE) };
+    assert_eq!(&data[..test_str.len()], test_str.as_bytes());
+    assert!(data[test_str.len()..].iter().all(|&b| b == 0));
+    munmap(addr, PAGE_SIZE).unwrap();
+    close(fd).unwrap();
+    println!("File mmap test passed!");
+
+    // 共享匿名映射，子进程的修改对父进程可见
+    let addr = mmap(
+        0,
+        PAGE_SIZE,
+        MmapProt::READ | MmapProt::WRITE,
+        MmapFlags::SHARED | MmapFlags::ANONYMOUS,
+        0,
+        0,
+    )
+    .unwrap();
+    let shared = addr as *mut usize;
+    let pid = fork();
+    if pid == 0 {
+        unsafe { shared.write_volatile(42) };
+        return 0;
+    }
+    proc_wait(pid).unwrap();
+    assert_eq!(unsafe { shared.read_volatile() }, 42);
+    println!("Shared mmap test passed!");
+    0
+}
@@ -25,24 +25,24 @@ const NUMBER_PER_PRODUCER: usize = 100;
 unsafe fn producer(id: *const usize) -> () {
     let id = *id;
     for _ in 0..NUMBER_PER_PRODUCER {
-        sem_down(SEM_EMPTY);
-        sem_down(SEM_MUTEX);
+        sem_down(SEM_EMPTY).unwrap();
+        sem_down(SEM_MUTEX).unwrap();
         BUFFER[FRONT] = id;
         FRONT = (FRONT + 1) % BUFFER_SIZE;
-        sem_up(SEM_MUTEX);
-        sem_up(SEM_EXISTED);
+        sem_up(SEM_MUTEX).unwrap();
+        sem_up(SEM_EXISTED).unwrap();
     }
     thread_exit()
 }
 
 unsafe fn consumer() -> () {
     for _ in 0..PRODUCER_COUNT * NUMBER_PER_PRODUCER {
-        sem_down(SEM_EXISTED);
-        sem_down(SEM_MUTEX);
+        sem_down(SEM_EXISTED).unwrap();
+        sem_down(SEM_MUTEX).unwrap();
         print!("{} ", BUFFER[TAIL]);
         TAIL = (TAIL + 1) % BUFFER_SIZE;
-        sem_up(SEM_MUTEX);
-        sem_up(SEM_EMPTY);
+        sem_up(SEM_MUTEX).unwrap();
+        sem_up(SEM_EMPTY).unwrap();
     }
     println!("");
     thread_exit()
@@ -67,7 +67,8 @@ pub fn main() -> i32 {
     threads.push(thread_create(consumer as usize, 0, 0));
     // wait for all threads to complete
     for thread in threads.iter() {
-        thread_join(thread.unwrap());
+        // 线程可能已经退出
+        let _ = thread_join(thread.unwrap());
     }
     println!("mpsc_sem passed!");
     0
@@ -13,19 +13,19 @@ fn main() -> i32 {
     let test_str = "Hello, world";
     let filea = "filea";
     let fd = open(filea, OpenFlags::CREATE | OpenFlags::WRONLY);
-    assert!(fd.is_some());
+    assert!(fd.is_ok());
     let fd = fd.unwrap();
-    write(fd, test_str.as_bytes());
+    write(fd, test_str.as_bytes()).unwrap();
     println!("Write finished successfully!");
-    close(fd);
+    close(fd).unwrap();
 
     let fd = open(filea, OpenFlags::RDONLY);
-    assert!(fd.is_some());
+    assert!(fd.is_ok());
     let fd = fd.unwrap();
     let mut buffer = [0u8; 100];
     let read_len = read(fd, &mut buffer).unwrap();
     println!("Read finished successfully! Readlen {}", read_len);
-    close(fd);
+    close(fd).unwrap();
 
     assert_eq!(test_str, core::str::from_utf8(&buffer[..read_len]).unwrap());
     println!("file_test passed!");
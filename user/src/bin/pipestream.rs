@@ -0,0 +1,74 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{close, fork, make_pipe, proc_wait, read, write, Errno};
+
+/// 写入的总字节数，远大于管道缓冲区的容量
+const TOTAL: usize = 64 * 1024;
+
+#[no_mangle]
+fn main() -> i32 {
+    let (read_fd, write_fd) = make_pipe().unwrap();
+    let pid = fork();
+    if pid == 0 {
+        // 子进程分块写入，缓冲区满时阻塞直到父进程读取
+        close(read_fd).unwrap();
+        let mut chunk = [0u8; 1000];
+        let mut sent = 0;
+        while sent < TOTAL {
+            let len = chunk.len().min(TOTAL - sent);
+            for (i, byte) in chunk[..len].iter_mut().enumerate() {
+                *byte = ((sent + i) % 251) as u8;
+            }
+            assert_eq!(write(write_fd, &chunk[..len]), Ok(len));
+            sent += len;
+        }
+        close(write_fd).unwrap();
+        return 0;
+    }
+    // 父进程边读边检查，写端关闭后读到0字节
+    close(write_fd).unwrap();
+    let mut buffer = [0u8; 700];
+    let mut received = 0;
+    loop {
+        let n = read(read_fd, &mut buffer).unwrap();
+        if n == 0 {
+            break;
+        }
+        for (i, byte) in buffer[..n].iter().enumerate() {
+            assert_eq!(*byte, ((received + i) % 251) as u8);
+        }
+        received += n;
+    }
+    assert_eq!(received, TOTAL);
+    assert_eq!(read(read_fd, &mut buffer), Ok(0));
+    close(read_fd).unwrap();
+    proc_wait(pid).unwrap();
+    println!("streaming passed!");
+
+    // 读端全部关闭后写入返回EPIPE
+    let (read_fd, write_fd) = make_pipe().unwrap();
+    close(read_fd).unwrap();
+    assert_eq!(write(write_fd, b"lost"), Err(Errno::EPIPE));
+    close(write_fd).unwrap();
+    // 写入时读端关闭，阻塞的写者被唤醒
+    let (read_fd, write_fd) = make_pipe().unwrap();
+    let pid = fork();
+    if pid == 0 {
+        close(write_fd).unwrap();
+        let mut buffer = [0u8; 16];
+        assert_eq!(read(read_fd, &mut buffer), Ok(16));
+        close(read_fd).unwrap();
+        return 0;
+    }
+    close(read_fd).unwrap();
+    let data = [7u8; 3 * 4096];
+    let written = write(write_fd, &data).unwrap();
+    assert!(written >= 16 && written < data.len());
+    close(write_fd).unwrap();
+    proc_wait(pid).unwrap();
+    println!("pipestream passed!");
+    0
+}
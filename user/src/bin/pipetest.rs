@@ -15,17 +15,17 @@ static STR: &str = "Hello, world!";
 
 #[no_mangle]
 fn main(_argc: usize, _argv: &[&str]) -> usize {
-    let (read_fd, write_fd) = make_pipe();
+    let (read_fd, write_fd) = make_pipe().unwrap();
     // println!("read_fd: {}, write_fd:{}", read_fd, write_fd);
     let pid = fork();
     if pid == 0 {
         println!("pid: {}", pid);
         // 子进程，关闭写端，再读读端
-        close(write_fd);
+        close(write_fd).unwrap();
         let mut buffer = [0u8; 32];
         let read_size = read(read_fd, &mut buffer).unwrap();
         // 关闭读端
-        close(read_fd);
+        close(read_fd).unwrap();
         // let a = 1;
         println!("read_size: {}", read_size);
         assert_eq!(core::str::from_utf8(&buffer[..read_size]).unwrap(), STR);
@@ -33,11 +33,11 @@ fn main(_argc: usize, _argv: &[&str]) -> usize {
     } else {
         println!("child pid: {}", pid);
         // 父进程，关闭读端，再写写端
-        close(read_fd);
+        close(read_fd).unwrap();
         assert_eq!(write(write_fd, STR.as_bytes()).unwrap(), STR.len());
         // 关闭写端
-        close(write_fd);
-        proc_wait(pid);
+        close(write_fd).unwrap();
+        proc_wait(pid).unwrap();
         println!("pipetest passed!");
         0
     }
@@ -0,0 +1,87 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, current_yield, fcntl, fork, get_time, make_pipe, poll, proc_wait, read, write, Errno,
+    FcntlCmd, OpenFlags, PollEvents, PollFd,
+};
+
+/// 将fd设置为非阻塞模式
+fn set_nonblock(fd: usize) {
+    let flags = fcntl(fd, FcntlCmd::GetFl, 0).unwrap();
+    fcntl(
+        fd,
+        FcntlCmd::SetFl,
+        flags | OpenFlags::NONBLOCK.bits() as usize,
+    )
+    .unwrap();
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // 非阻塞的管道没有数据时返回EAGAIN，缓冲区满时只写入放得下的部分
+    let (read_fd, write_fd) = make_pipe().unwrap();
+    set_nonblock(read_fd);
+    set_nonblock(write_fd);
+    let flags = fcntl(read_fd, FcntlCmd::GetFl, 0).unwrap();
+    assert_eq!(flags, OpenFlags::NONBLOCK.bits() as usize);
+    let mut buffer = [0u8; 1024];
+    assert_eq!(read(read_fd, &mut buffer), Err(Errno::EAGAIN));
+    let data = [3u8; 8192];
+    assert_eq!(write(write_fd, &data), Ok(4096));
+    assert_eq!(write(write_fd, &data), Err(Errno::EAGAIN));
+    let mut fds = [PollFd::new(write_fd, PollEvents::OUT)];
+    assert_eq!(poll(&mut fds, Some(0)), Ok(0));
+    assert_eq!(read(read_fd, &mut buffer), Ok(1024));
+    assert_eq!(poll(&mut fds, Some(0)), Ok(1));
+    assert_eq!(fds[0].revents, PollEvents::OUT);
+    close(read_fd).unwrap();
+    close(write_fd).unwrap();
+    println!("nonblock passed!");
+
+    // 没有文件就绪时等待到超时
+    let (read_fd, write_fd) = make_pipe().unwrap();
+    let mut fds = [PollFd::new(read_fd, PollEvents::IN)];
+    assert_eq!(poll(&mut fds, Some(0)), Ok(0));
+    let start = get_time();
+    assert_eq!(poll(&mut fds, Some(20)), Ok(0));
+    assert!(get_time() - start >= 20);
+    assert!(fds[0].revents.is_empty());
+
+    // 同时等待标准输入和管道，子进程写入管道后被唤醒
+    let pid = fork();
+    if pid == 0 {
+        close(read_fd).unwrap();
+        let start = get_time();
+        while get_time() - start < 10 {
+            current_yield();
+        }
+        assert_eq!(write(write_fd, b"ping"), Ok(4));
+        close(write_fd).unwrap();
+        return 0;
+    }
+    close(write_fd).unwrap();
+    let mut fds = [
+        PollFd::new(0, PollEvents::IN),
+        PollFd::new(read_fd, PollEvents::IN),
+    ];
+    assert_eq!(poll(&mut fds, None), Ok(1));
+    assert!(fds[0].revents.is_empty());
+    assert!(fds[1].revents.contains(PollEvents::IN));
+    assert_eq!(read(read_fd, &mut buffer), Ok(4));
+    assert_eq!(&buffer[..4], b"ping");
+    proc_wait(pid).unwrap();
+    // 写端全部关闭后报告HUP
+    let mut fds = [PollFd::new(read_fd, PollEvents::IN)];
+    assert_eq!(poll(&mut fds, None), Ok(1));
+    assert_eq!(fds[0].revents, PollEvents::HUP);
+    close(read_fd).unwrap();
+    // 不存在的fd报告NVAL
+    let mut fds = [PollFd::new(100, PollEvents::IN)];
+    assert_eq!(poll(&mut fds, Some(0)), Ok(1));
+    assert_eq!(fds[0].revents, PollEvents::NVAL);
+    println!("polltest passed!");
+    0
+}
@@ -33,7 +33,8 @@ pub fn main() -> i32 {
         v.push(thread_create(f as usize, 0, 0).unwrap());
     }
     for tid in v.iter() {
-        thread_join(*tid);
+        // 线程可能已经退出
+        let _ = thread_join(*tid);
     }
     println!("time cost is {}ms", get_time() - start);
     assert_eq!(unsafe { A }, PER_THREAD * THREAD_COUNT);
@@ -16,14 +16,14 @@ const THREAD_COUNT: usize = 16;
 unsafe fn f() -> () {
     let mut t = 2usize;
     for _ in 0..PER_THREAD {
-        mutex_lock(0);
+        mutex_lock(0).unwrap();
         let a = &mut A as *mut usize;
         let cur = a.read_volatile();
         for _ in 0..500 {
             t = t * t % 10007;
         }
         a.write_volatile(cur + 1);
-        mutex_unlock(0);
+        mutex_unlock(0).unwrap();
     }
     thread_exit()
 }
@@ -37,7 +37,8 @@ pub fn main() -> i32 {
         v.push(thread_create(f as usize, 0, 0).unwrap());
     }
     for tid in v.iter() {
-        thread_join(*tid);
+        // 线程可能已经退出
+        let _ = thread_join(*tid);
     }
     println!("time cost is {}ms", get_time() - start);
     assert_eq!(unsafe { A }, PER_THREAD * THREAD_COUNT);
@@ -10,7 +10,7 @@ extern crate user_lib;
 fn main() -> i32 {
     let mut buffer = [0u8; 30];
     let read_size = read(0, &mut buffer);
-    assert!(read_size.is_some());
+    assert!(read_size.is_ok());
     let size = read_size.unwrap();
     print!("{}", core::str::from_utf8(&buffer[..size]).unwrap());
     1
@@ -14,20 +14,21 @@ static WRITE_TIMES: usize = 20;
 fn main() -> i32 {
     let filea = "filea";
     let fd = open(filea, OpenFlags::CREATE | OpenFlags::WRONLY);
-    assert!(fd.is_some());
+    assert!(fd.is_ok());
     let fd = fd.unwrap();
     let test_str = "a";
 
     for _ in 0..WRITE_TIMES {
-        write(fd, test_str.as_bytes());
+        // 写请求可能因为fs内核线程崩溃而被丢弃
+        let _ = write(fd, test_str.as_bytes());
     }
 
     let fd = open(filea, OpenFlags::RDONLY);
-    assert!(fd.is_some());
+    assert!(fd.is_ok());
     let fd = fd.unwrap();
     let mut buffer = [0u8; 100];
     let read_len = read(fd, &mut buffer).unwrap();
-    close(fd);
+    close(fd).unwrap();
     println!(
         "Write {} bytes, read {} bytes, {} requestes dropped totally",
         WRITE_TIMES,
@@ -0,0 +1,59 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, dup, fork, lseek, open, pread, proc_wait, pwrite, read, unlink, write, Errno, OpenFlags,
+    Whence,
+};
+
+#[no_mangle]
+fn main() -> i32 {
+    let path = "seekfile";
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::RDWR).unwrap();
+    write(fd, b"0123456789").unwrap();
+
+    // SET/CUR/END
+    let mut buffer = [0u8; 16];
+    assert_eq!(lseek(fd, 2, Whence::Set), Ok(2));
+    assert_eq!(read(fd, &mut buffer[..3]), Ok(3));
+    assert_eq!(&buffer[..3], b"234");
+    assert_eq!(lseek(fd, -1, Whence::Cur), Ok(4));
+    assert_eq!(lseek(fd, -2, Whence::End), Ok(8));
+    assert_eq!(read(fd, &mut buffer), Ok(2));
+    assert_eq!(&buffer[..2], b"89");
+    assert_eq!(lseek(fd, -11, Whence::End), Err(Errno::EINVAL));
+    assert_eq!(lseek(0, 0, Whence::Set), Err(Errno::ESPIPE));
+    println!("lseek passed!");
+
+    // pread/pwrite不移动读写位置
+    assert_eq!(lseek(fd, 1, Whence::Set), Ok(1));
+    assert_eq!(pread(fd, &mut buffer[..4], 5), Ok(4));
+    assert_eq!(&buffer[..4], b"5678");
+    assert_eq!(pwrite(fd, b"ab", 12), Ok(2));
+    assert_eq!(lseek(fd, 0, Whence::Cur), Ok(1));
+    assert_eq!(lseek(fd, 0, Whence::End), Ok(14));
+    assert_eq!(pread(fd, &mut buffer, 8), Ok(6));
+    assert_eq!(&buffer[..6], b"89\0\0ab");
+    println!("pread/pwrite passed!");
+
+    // dup和fork得到的描述符共享读写位置
+    let new_fd = dup(fd).unwrap();
+    assert_eq!(lseek(new_fd, 3, Whence::Set), Ok(3));
+    assert_eq!(lseek(fd, 0, Whence::Cur), Ok(3));
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(read(fd, &mut buffer[..2]), Ok(2));
+        return 0;
+    }
+    proc_wait(pid).unwrap();
+    assert_eq!(lseek(fd, 0, Whence::Cur), Ok(5));
+    println!("shared offset passed!");
+
+    close(new_fd).unwrap();
+    close(fd).unwrap();
+    unlink(path).unwrap();
+    println!("seek test passed!");
+    0
+}
@@ -14,6 +14,7 @@ use user_syscall::dup;
 use user_syscall::fork;
 use user_syscall::open;
 use user_syscall::OpenFlags;
+use user_syscall::EXIT_CODE_PAGE_FAULT;
 
 use alloc::string::String;
 use alloc::vec::Vec;
@@ -116,7 +117,7 @@ pub fn main() -> i32 {
                 let mut pipes_fd: Vec<(usize, usize)> = Vec::new();
                 if !process_argmuments.is_empty() {
                     for _ in 0..process_argmuments.len() - 1 {
-                        let pipe_fd = make_pipe();
+                        let pipe_fd = make_pipe().unwrap();
                         pipes_fd.push(pipe_fd);
                     }
                 }
@@ -132,50 +133,55 @@ pub fn main() -> i32 {
 
                         // 重定向输入
                         if !input.is_empty() {
-                            let input_fd = open(input, OpenFlags::RDONLY);
-                            if input_fd == None {
-                                println!("Error when opening file {}", input);
-                                return -4;
-                            }
+                            let input_fd = match open(input, OpenFlags::RDONLY) {
+                                Ok(fd) => fd,
+                                Err(errno) => {
+                                    println!("Error when opening file {}: {}", input, errno);
+                                    return -4;
+                                }
+                            };
                             // 关闭标准输入
-                            close(0);
-                            assert_eq!(dup(input_fd.unwrap()), Some(0));
+                            close(0).unwrap();
+                            assert_eq!(dup(input_fd), Ok(0));
                             // 标准输入改为input_fd
-                            close(input_fd.unwrap());
+                            close(input_fd).unwrap();
                         }
                         // 重定向输出
                         if !output.is_empty() {
-                            let output_fd = open(output, OpenFlags::WRONLY | OpenFlags::CREATE);
-                            if output_fd == None {
-                                println!("Error when opening file {}", output);
-                                return -4;
-                            }
+                            let output_fd =
+                                match open(output, OpenFlags::WRONLY | OpenFlags::CREATE) {
+                                    Ok(fd) => fd,
+                                    Err(errno) => {
+                                        println!("Error when opening file {}: {}", output, errno);
+                                        return -4;
+                                    }
+                                };
                             // 关闭标准输出
-                            close(1);
-                            assert_eq!(dup(output_fd.unwrap()), Some(1));
+                            close(1).unwrap();
+                            assert_eq!(dup(output_fd), Ok(1));
                             // 标准输入改为input_fd
-                            close(output_fd.unwrap());
+                            close(output_fd).unwrap();
                         }
                         // 从管道读端接受输入
                         if i > 0 {
-                            close(0);
+                            close(0).unwrap();
                             let read_end = (&pipes_fd[i - 1].0).clone();
-                            assert_eq!(dup(read_end), Some(0));
+                            assert_eq!(dup(read_end), Ok(0));
                         }
                         // 输出输送到管道写端
                         if i < process_argmuments.len() - 1 {
-                            close(1);
+                            close(1).unwrap();
                             let write_end = pipes_fd[i].1;
-                            assert_eq!(dup(write_end), Some(1));
+                            assert_eq!(dup(write_end), Ok(1));
                         }
                         // 从文件表中移除从父进程(shell)继承的所有管道文件
                         for pipe_fd in &pipes_fd {
-                            close(pipe_fd.0);
-                            close(pipe_fd.1);
+                            close(pipe_fd.0).unwrap();
+                            close(pipe_fd.1).unwrap();
                         }
                         // 执行应用程序
-                        if exec(args[0].as_str(), Some(args)).0 == usize::MAX {
-                            println!("Error when executing!");
+                        if let Err(errno) = exec(args[0].as_str(), Some(args)) {
+                            println!("Error when executing {}: {}", args[0], errno);
                             return -4;
                         }
                         unreachable!();
@@ -186,12 +192,14 @@ pub fn main() -> i32 {
                 }
                 // shell进程关闭所有管道
                 for pipe_fd in &pipes_fd {
-                    close(pipe_fd.0);
-                    close(pipe_fd.1);
+                    close(pipe_fd.0).unwrap();
+                    close(pipe_fd.1).unwrap();
                 }
                 // 等待所有子进程结束
                 for pid in &children {
-                    proc_wait(*pid);
+                    if proc_wait(*pid) == Ok(EXIT_CODE_PAGE_FAULT) {
+                        println!("[Shell] Process {} was killed by page fault", pid);
+                    }
                 }
                 // 清空本行
                 line.clear();
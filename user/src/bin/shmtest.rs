@@ -0,0 +1,166 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    close, current_yield, fork, fstat, ftruncate, make_pipe, mmap, munmap, proc_wait, read,
+    shm_open, shm_unlink, write, Errno, MmapFlags, MmapProt, OpenFlags,
+};
+
+const NAME: &str = "shmtest";
+const PAGE_SIZE: usize = 4096;
+const BUFFER_SIZE: usize = 400;
+const PRODUCER_COUNT: usize = 4;
+const NUMBER_PER_PRODUCER: usize = 100;
+
+/// 位于共享内存中的环形缓冲区，用自旋锁保护
+///
+/// 信号量属于单个进程，进程间只能通过共享内存中的原子变量同步
+#[repr(C)]
+struct Ring {
+    lock: AtomicUsize,
+    front: usize,
+    tail: usize,
+    count: usize,
+    buffer: [usize; BUFFER_SIZE],
+}
+
+impl Ring {
+    /// 加锁后执行f，f返回false时让出CPU后重试
+    fn with_lock(&mut self, mut f: impl FnMut(&mut Self) -> bool) {
+        loop {
+            while self
+                .lock
+                .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                current_yield();
+            }
+            let done = f(self);
+            self.lock.store(0, Ordering::Release);
+            if done {
+                return;
+            }
+            current_yield();
+        }
+    }
+}
+
+/// 将共享内存对象fd的前len字节映射到地址空间中
+fn map(fd: usize, len: usize) -> usize {
+    mmap(
+        0,
+        len,
+        MmapProt::READ | MmapProt::WRITE,
+        MmapFlags::SHARED,
+        fd,
+        0,
+    )
+    .unwrap()
+}
+
+/// 生产者进程按名字打开共享内存，放入NUMBER_PER_PRODUCER个id
+fn producer(id: usize) -> i32 {
+    let fd = shm_open(NAME, OpenFlags::RDWR).unwrap();
+    let len = fstat(fd).unwrap().size as usize;
+    let addr = map(fd, len);
+    close(fd).unwrap();
+    let ring = unsafe { &mut *(addr as *mut Ring) };
+    for _ in 0..NUMBER_PER_PRODUCER {
+        ring.with_lock(|ring| {
+            if ring.count == BUFFER_SIZE {
+                return false;
+            }
+            ring.buffer[ring.front] = id;
+            ring.front = (ring.front + 1) % BUFFER_SIZE;
+            ring.count += 1;
+            true
+        });
+    }
+    munmap(addr, len).unwrap();
+    0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let _ = shm_unlink(NAME);
+    assert_eq!(shm_open(NAME, OpenFlags::RDWR), Err(Errno::ENOENT));
+    let fd = shm_open(NAME, OpenFlags::CREATE | OpenFlags::RDWR).unwrap();
+    assert_eq!(fstat(fd).unwrap().size, 0);
+    let len = core::mem::size_of::<Ring>();
+    ftruncate(fd, len).unwrap();
+    assert_eq!(fstat(fd).unwrap().size as usize, len);
+    // 过大的长度被拒绝，对象保持不变
+    assert_eq!(ftruncate(fd, usize::MAX), Err(Errno::EFBIG));
+    assert_eq!(fstat(fd).unwrap().size as usize, len);
+    // 超出对象长度或私有映射都不允许
+    let prot = MmapProt::READ | MmapProt::WRITE;
+    assert_eq!(
+        mmap(0, len + PAGE_SIZE, prot, MmapFlags::SHARED, fd, 0),
+        Err(Errno::EINVAL)
+    );
+    assert_eq!(
+        mmap(0, len, prot, MmapFlags::PRIVATE, fd, 0),
+        Err(Errno::EINVAL)
+    );
+    let addr = map(fd, len);
+    let ring = unsafe { &mut *(addr as *mut Ring) };
+    assert_eq!(ring.count, 0);
+
+    // 同一对象的两次映射看到相同的数据
+    let other = map(fd, len);
+    unsafe { *(other as *mut usize).add(1) = 7 };
+    assert_eq!(ring.front, 7);
+    // 内核写入共享映射时不进行写时复制，另一个映射也能看到
+    let (read_fd, write_fd) = make_pipe().unwrap();
+    write(write_fd, &[9; 8]).unwrap();
+    let front = unsafe { core::slice::from_raw_parts_mut((other as *mut u8).add(8), 8) };
+    assert_eq!(read(read_fd, front), Ok(8));
+    assert_eq!(ring.front, usize::from_ne_bytes([9; 8]));
+    close(read_fd).unwrap();
+    close(write_fd).unwrap();
+    ring.front = 0;
+    munmap(other, len).unwrap();
+    println!("shared mapping passed!");
+
+    // 多个生产者进程，父进程作为消费者
+    let mut pids = [0; PRODUCER_COUNT];
+    for (id, pid) in pids.iter_mut().enumerate() {
+        *pid = fork();
+        if *pid == 0 {
+            return producer(id);
+        }
+    }
+    close(fd).unwrap();
+    let mut received = [0; PRODUCER_COUNT];
+    for _ in 0..PRODUCER_COUNT * NUMBER_PER_PRODUCER {
+        ring.with_lock(|ring| {
+            if ring.count == 0 {
+                return false;
+            }
+            received[ring.buffer[ring.tail]] += 1;
+            ring.tail = (ring.tail + 1) % BUFFER_SIZE;
+            ring.count -= 1;
+            true
+        });
+    }
+    for pid in pids {
+        proc_wait(pid).unwrap();
+    }
+    assert!(received.iter().all(|&n| n == NUMBER_PER_PRODUCER));
+    println!("producer/consumer passed!");
+
+    // 删除名字后已有的映射仍然有效，重新创建得到新的对象
+    shm_unlink(NAME).unwrap();
+    assert_eq!(shm_unlink(NAME), Err(Errno::ENOENT));
+    assert_eq!(ring.count, 0);
+    let fd = shm_open(NAME, OpenFlags::CREATE | OpenFlags::RDWR).unwrap();
+    assert_eq!(fstat(fd).unwrap().size, 0);
+    close(fd).unwrap();
+    shm_unlink(NAME).unwrap();
+    munmap(addr, len).unwrap();
+    println!("shmtest passed!");
+    0
+}
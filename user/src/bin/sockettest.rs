@@ -0,0 +1,119 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    accept, bind, close, connect, current_yield, dup, fcntl, fork, fstat, listen, poll, proc_wait,
+    recv, send, socket, thread_create, thread_exit, Errno, FcntlCmd, FileType, OpenFlags,
+    PollEvents, PollFd,
+};
+
+const NAME: &str = "sockettest";
+const EXIT_NAME: &str = "sockettest-exit";
+
+/// 一直在accept中等待的线程
+fn accept_forever(fd: usize) {
+    let _ = accept(fd);
+    thread_exit()
+}
+
+/// 读取直到对方关闭，返回读取的字节数
+fn recv_all(fd: usize, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        let n = recv(fd, &mut buf[len..]).unwrap();
+        if n == 0 {
+            return len;
+        }
+        len += n;
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let server = socket().unwrap();
+    assert_eq!(fstat(server).unwrap().file_type, FileType::Socket);
+    let mut buf = [0u8; 64];
+    assert_eq!(recv(server, &mut buf), Err(Errno::ENOTCONN));
+    // 不是套接字的文件不能用send和recv
+    assert_eq!(send(1, b"stdout"), Err(Errno::ENOTSOCK));
+    assert_eq!(recv(0, &mut buf), Err(Errno::ENOTSOCK));
+    assert_eq!(listen(server, 1), Err(Errno::EINVAL));
+    bind(server, NAME).unwrap();
+    let other = socket().unwrap();
+    assert_eq!(bind(other, NAME), Err(Errno::EADDRINUSE));
+    assert_eq!(connect(other, "nosuchsocket"), Err(Errno::ECONNREFUSED));
+    assert_eq!(connect(other, NAME), Err(Errno::ECONNREFUSED));
+    listen(server, 1).unwrap();
+
+    // 等待队列已满时拒绝连接，非阻塞模式下没有连接时accept返回EAGAIN
+    connect(other, NAME).unwrap();
+    assert_eq!(connect(other, NAME), Err(Errno::EISCONN));
+    let third = socket().unwrap();
+    assert_eq!(connect(third, NAME), Err(Errno::ECONNREFUSED));
+    let conn = accept(server).unwrap();
+    assert_eq!(send(other, b"queued"), Ok(6));
+    assert_eq!(recv(conn, &mut buf), Ok(6));
+    assert_eq!(&buf[..6], b"queued");
+    close(conn).unwrap();
+    assert_eq!(send(other, b"lost"), Err(Errno::EPIPE));
+    close(other).unwrap();
+    close(third).unwrap();
+    fcntl(server, FcntlCmd::SetFl, OpenFlags::NONBLOCK.bits() as usize).unwrap();
+    assert_eq!(accept(server), Err(Errno::EAGAIN));
+    fcntl(server, FcntlCmd::SetFl, 0).unwrap();
+    println!("connection queue passed!");
+
+    // 子进程连接后交换数据，再将标准输出重定向到套接字
+    let pid = fork();
+    if pid == 0 {
+        close(server).unwrap();
+        let client = socket().unwrap();
+        connect(client, NAME).unwrap();
+        assert_eq!(send(client, b"ping"), Ok(4));
+        let mut buf = [0u8; 4];
+        assert_eq!(recv(client, &mut buf), Ok(4));
+        assert_eq!(&buf, b"pong");
+        close(1).unwrap();
+        assert_eq!(dup(client), Ok(1));
+        close(client).unwrap();
+        println!("hello from client");
+        return 0;
+    }
+    let mut fds = [PollFd::new(server, PollEvents::IN)];
+    assert_eq!(poll(&mut fds, None), Ok(1));
+    let conn = accept(server).unwrap();
+    assert_eq!(recv(conn, &mut buf[..4]), Ok(4));
+    assert_eq!(&buf[..4], b"ping");
+    assert_eq!(send(conn, b"pong"), Ok(4));
+    let len = recv_all(conn, &mut buf);
+    assert_eq!(&buf[..len], b"hello from client\n");
+    close(conn).unwrap();
+    proc_wait(pid).unwrap();
+
+    // 关闭后名字被释放
+    close(server).unwrap();
+    let server = socket().unwrap();
+    bind(server, NAME).unwrap();
+    close(server).unwrap();
+
+    // 进程在accept中等待时退出，名字同样被释放
+    let pid = fork();
+    if pid == 0 {
+        let server = socket().unwrap();
+        bind(server, EXIT_NAME).unwrap();
+        listen(server, 1).unwrap();
+        thread_create(accept_forever as usize, server, 0).unwrap();
+        for _ in 0..10 {
+            current_yield();
+        }
+        return 0;
+    }
+    proc_wait(pid).unwrap();
+    let server = socket().unwrap();
+    bind(server, EXIT_NAME).unwrap();
+    close(server).unwrap();
+    println!("sockettest passed!");
+    0
+}
@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::stat;
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc < 2 {
+        println!("usage: stat <path>...");
+        return -1;
+    }
+    let mut exit_code = 0;
+    for path in &argv[1..] {
+        match stat(path) {
+            Ok(stat) => {
+                println!("  File: {}", path);
+                println!(
+                    "  Size: {:<10} Blocks: {:<10} {:?}",
+                    stat.size, stat.blocks, stat.file_type
+                );
+                println!("Inode: {:<10} Links: {}", stat.ino, stat.nlink);
+                println!("Create: {}", stat.ctime);
+                println!("Modify: {}", stat.mtime);
+            }
+            Err(errno) => {
+                println!("stat: cannot stat {}: {}", path, errno);
+                exit_code = -1;
+            }
+        }
+    }
+    exit_code
+}
@@ -0,0 +1,17 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::sync;
+
+/// 将文件系统块缓存中的所有脏块写回磁盘
+#[no_mangle]
+fn main() -> i32 {
+    if let Err(errno) = sync() {
+        println!("sync: {}", errno);
+        return -1;
+    }
+    0
+}
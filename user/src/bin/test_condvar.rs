@@ -21,21 +21,21 @@ const MUTEX_ID: usize = 0;
 unsafe fn first() -> () {
     // sleep(10);
     println!("First work, Change A --> 1 and wakeup Second");
-    mutex_lock(MUTEX_ID);
+    mutex_lock(MUTEX_ID).unwrap();
     A = 1;
-    condvar_signal(CONDVAR_ID);
-    mutex_unlock(MUTEX_ID);
+    condvar_signal(CONDVAR_ID).unwrap();
+    mutex_unlock(MUTEX_ID).unwrap();
     thread_exit()
 }
 
 unsafe fn second() -> () {
     println!("Second want to continue,but need to wait A=1");
-    mutex_lock(MUTEX_ID);
+    mutex_lock(MUTEX_ID).unwrap();
     while A == 0 {
         println!("Second: A is {}", A);
-        condvar_wait(CONDVAR_ID, MUTEX_ID);
+        condvar_wait(CONDVAR_ID, MUTEX_ID).unwrap();
     }
-    mutex_unlock(MUTEX_ID);
+    mutex_unlock(MUTEX_ID).unwrap();
     println!("A is {}, Second can work now", A);
     thread_exit()
 }
@@ -52,7 +52,8 @@ pub fn main() -> i32 {
     ];
     // wait for all threads to complete
     for thread in threads.iter() {
-        thread_join(thread.unwrap());
+        // 线程可能已经退出
+        let _ = thread_join(thread.unwrap());
     }
     println!("test_condvar passed!");
     0
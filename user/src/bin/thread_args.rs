@@ -39,7 +39,8 @@ pub fn main() -> i32 {
         ));
     }
     for tid in v.iter() {
-        thread_join(tid.unwrap());
+        // 线程可能已经退出
+        let _ = thread_join(tid.unwrap());
         println!("Thread#{} exited", tid.unwrap());
     }
     println!("main thread exited. Thread with args test passed");
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+use user_lib::{
+    close, fstat, fsync, ftruncate, lseek, mkdir, open, pread, rmdir, truncate, unlink, write,
+    Errno, OpenFlags, Whence,
+};
+
+#[no_mangle]
+fn main() -> i32 {
+    let path = "truncfile";
+    let fd = open(path, OpenFlags::CREATE | OpenFlags::RDWR).unwrap();
+    let data = [b'x'; 2000];
+    write(fd, &data).unwrap();
+
+    // 缩小，读写位置不变
+    assert_eq!(ftruncate(fd, 700), Ok(()));
+    assert_eq!(fstat(fd).unwrap().size, 700);
+    assert_eq!(lseek(fd, 0, Whence::Cur), Ok(2000));
+
+    // 扩展的部分填零
+    assert_eq!(truncate(path, 1500), Ok(()));
+    let mut buffer = [0xffu8; 16];
+    assert_eq!(pread(fd, &mut buffer, 696), Ok(16));
+    assert_eq!(&buffer[..4], b"xxxx");
+    assert!(buffer[4..].iter().all(|&b| b == 0));
+    println!("shrink/extend passed!");
+
+    assert_eq!(ftruncate(fd, 0), Ok(()));
+    assert_eq!(fstat(fd).unwrap().blocks, 0);
+    assert_eq!(ftruncate(0, 0), Err(Errno::EINVAL));
+    assert_eq!(truncate("no_such_file", 0), Err(Errno::ENOENT));
+    mkdir("truncdir").unwrap();
+    assert_eq!(truncate("truncdir", 0), Err(Errno::EISDIR));
+    rmdir("truncdir").unwrap();
+    println!("truncate errors passed!");
+
+    // 写回磁盘
+    assert_eq!(fsync(fd), Ok(()));
+    assert_eq!(fsync(0), Err(Errno::EINVAL));
+    assert_eq!(fsync(100), Err(Errno::EBADF));
+    println!("fsync passed!");
+
+    close(fd).unwrap();
+    unlink(path).unwrap();
+    println!("truncate test passed!");
+    0
+}
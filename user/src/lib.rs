@@ -12,14 +12,18 @@ extern crate alloc;
 extern crate bitflags;
 
 use alloc::vec::Vec;
-use buddy_system_allocator::LockedHeap;
+use buddy_system_allocator::{Heap, LockedHeapWithRescue};
+use core::alloc::Layout;
 pub use user_syscall::*;
 
+/// 初始堆大小
 const USER_HEAP_SIZE: usize = 0x40000;
-static mut HEAP_SPACE: [u8; USER_HEAP_SIZE] = [0; USER_HEAP_SIZE];
+
+/// 堆内存不足时每次至少扩展的大小
+const USER_HEAP_GROW_SIZE: usize = 0x10000;
 
 #[global_allocator]
-static HEAP: LockedHeap<32> = LockedHeap::empty();
+static HEAP: LockedHeapWithRescue<32> = LockedHeapWithRescue::new(grow_heap);
 
 #[alloc_error_handler]
 pub fn handle_alloc_error(layout: core::alloc::Layout) -> ! {
@@ -70,10 +74,23 @@ pub extern "C" fn _start(argc: usize, argv: usize) -> ! {
 
 /// 用户态初始化堆内存
 pub fn init_heap() {
-    // 初始化堆内存分配器
+    // 通过sbrk申请初始的堆内存
+    let start = sbrk(USER_HEAP_SIZE as isize).expect("Failed to initialize user heap");
     unsafe {
-        HEAP.lock()
-            .init(HEAP_SPACE.as_ptr() as usize, USER_HEAP_SIZE);
+        HEAP.lock().init(start, USER_HEAP_SIZE);
+    }
+}
+
+/// 堆内存不足时通过sbrk扩展堆
+///
+/// 伙伴系统要求分配的块按大小对齐，所以至少扩展两倍的块大小
+fn grow_heap(heap: &mut Heap<32>, layout: &Layout) {
+    let block_size = layout.size().max(layout.align()).next_power_of_two();
+    let size = (block_size * 2).max(USER_HEAP_GROW_SIZE);
+    if let Ok(start) = sbrk(size as isize) {
+        unsafe {
+            heap.add_to_heap(start, start + size);
+        }
     }
 }
 